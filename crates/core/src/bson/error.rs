@@ -45,6 +45,11 @@ impl BsonError {
             err: Box::new(BsonErrorImpl { offset, kind }),
         }
     }
+
+    /// The offset in the document at which the error occurred, if known.
+    pub fn offset(&self) -> Option<usize> {
+        self.err.offset
+    }
 }
 
 impl core::error::Error for BsonError {}
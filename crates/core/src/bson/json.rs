@@ -0,0 +1,307 @@
+use core::cell::Cell;
+use core::fmt::Write;
+
+use alloc::string::String;
+use serde::{
+    Serialize, Serializer,
+    ser::{Error, SerializeMap, SerializeSeq},
+};
+
+use super::{
+    BsonError,
+    error::ErrorKind,
+    parser::{BinarySubtype, ElementType, Parser},
+};
+
+/// Converts a BSON document (including its length prefix) into a JSON object.
+///
+/// Since the result is stored in `ps_oplog.data` and read with SQLite's JSON functions, values are
+/// mapped as follows:
+///
+///  - `int32` and `int64` values are written as integers, without loss of precision.
+///  - `double` values are written as numbers, or as `null` if they're not finite.
+///  - Datetimes are written as text in the `YYYY-MM-DD HH:MM:SS.sssZ` format understood by SQLite.
+///  - Binary values with the UUID subtype are written as hyphenated UUID strings, other binary
+///    values are written as base64 text.
+///  - Object ids are written as hexadecimal text.
+pub fn to_json(document: &[u8]) -> Result<String, BsonError> {
+    let mut parser = Parser::new(document);
+    let contents = parser.document_scope()?;
+    if !parser.remaining().is_empty() {
+        return Err(parser.error(ErrorKind::InvalidSize));
+    }
+
+    let error = Cell::new(None);
+    let serialized = serde_json::to_string(&DocumentAsJson {
+        contents,
+        is_array: false,
+        error: &error,
+    });
+
+    match (serialized, error.take()) {
+        (_, Some(err)) => Err(err),
+        (Ok(json), None) => Ok(json),
+        (Err(err), None) => Err(serde::de::Error::custom(err)),
+    }
+}
+
+/// Serializes the contents of a BSON document or array as a JSON object or array.
+///
+/// Since [Serializer] errors can't wrap a [BsonError], parsing errors are reported through the
+/// shared `error` cell instead.
+struct DocumentAsJson<'a, 'de> {
+    contents: Parser<'de>,
+    is_array: bool,
+    error: &'a Cell<Option<BsonError>>,
+}
+
+impl<'a, 'de> DocumentAsJson<'a, 'de> {
+    fn report<E: Error>(&self, err: BsonError) -> E {
+        let desc = E::custom(&err);
+        self.error.set(Some(err));
+        desc
+    }
+
+    fn read_value(
+        &self,
+        parser: &mut Parser<'de>,
+        element_type: ElementType,
+    ) -> Result<ValueAsJson<'a, 'de>, BsonError> {
+        Ok(match element_type {
+            ElementType::Double => ValueAsJson::Double(parser.read_double()?),
+            ElementType::String => ValueAsJson::Str(parser.read_string()?),
+            ElementType::Document | ElementType::Array => ValueAsJson::Document(DocumentAsJson {
+                contents: parser.document_scope()?,
+                is_array: matches!(element_type, ElementType::Array),
+                error: self.error,
+            }),
+            ElementType::Binary => {
+                let (subtype, bytes) = parser.read_binary()?;
+                ValueAsJson::Owned(format_binary(subtype, bytes))
+            }
+            ElementType::ObjectId => {
+                let mut hex = String::with_capacity(24);
+                for byte in parser.read_object_id()? {
+                    let _ = write!(&mut hex, "{byte:02x}");
+                }
+                ValueAsJson::Owned(hex)
+            }
+            ElementType::Boolean => ValueAsJson::Bool(parser.read_bool()?),
+            ElementType::DatetimeUtc => ValueAsJson::Owned(format_datetime(parser.read_int64()?)),
+            ElementType::Timestamp => ValueAsJson::Unsigned(parser.read_uint64()?),
+            ElementType::Null | ElementType::Undefined => ValueAsJson::Null,
+            ElementType::Int32 => ValueAsJson::Int(parser.read_int32()?.into()),
+            ElementType::Int64 => ValueAsJson::Int(parser.read_int64()?),
+        })
+    }
+}
+
+impl Serialize for DocumentAsJson<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut parser = self.contents.clone();
+
+        // Reads the next key-value pair of the document, or returns `None` at the end.
+        let mut next = || -> Result<Option<(&str, ValueAsJson)>, BsonError> {
+            if parser.end_document()? {
+                return Ok(None);
+            }
+
+            let element_type = parser.read_element_type()?;
+            let name = parser.read_cstr()?;
+            let value = self.read_value(&mut parser, element_type)?;
+            Ok(Some((name, value)))
+        };
+
+        if self.is_array {
+            // Arrays are encoded as documents with keys "0", "1", ..., we ignore those.
+            let mut seq = serializer.serialize_seq(None)?;
+            while let Some((_, value)) = next().map_err(|e| self.report::<S::Error>(e))? {
+                seq.serialize_element(&value)?;
+            }
+            seq.end()
+        } else {
+            let mut map = serializer.serialize_map(None)?;
+            while let Some((name, value)) = next().map_err(|e| self.report::<S::Error>(e))? {
+                map.serialize_entry(name, &value)?;
+            }
+            map.end()
+        }
+    }
+}
+
+enum ValueAsJson<'a, 'de> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Unsigned(u64),
+    Double(f64),
+    Str(&'de str),
+    Owned(String),
+    Document(DocumentAsJson<'a, 'de>),
+}
+
+impl Serialize for ValueAsJson<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ValueAsJson::Null => serializer.serialize_unit(),
+            ValueAsJson::Bool(value) => serializer.serialize_bool(*value),
+            ValueAsJson::Int(value) => serializer.serialize_i64(*value),
+            ValueAsJson::Unsigned(value) => serializer.serialize_u64(*value),
+            // serde_json serializes NaN and infinite values as null.
+            ValueAsJson::Double(value) => serializer.serialize_f64(*value),
+            ValueAsJson::Str(value) => serializer.serialize_str(value),
+            ValueAsJson::Owned(value) => serializer.serialize_str(value),
+            ValueAsJson::Document(document) => document.serialize(serializer),
+        }
+    }
+}
+
+fn format_binary(subtype: BinarySubtype, bytes: &[u8]) -> String {
+    if subtype.0 == BinarySubtype::UUID.0 {
+        if let Ok(uuid) = uuid::Uuid::from_slice(bytes) {
+            let mut buffer = uuid::Uuid::encode_buffer();
+            return String::from(uuid.hyphenated().encode_lower(&mut buffer));
+        }
+    }
+
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Formats a BSON datetime (milliseconds since the Unix epoch) as `YYYY-MM-DD HH:MM:SS.sssZ`.
+fn format_datetime(millis: i64) -> String {
+    let days = millis.div_euclid(86_400_000);
+    let millis_of_day = millis.rem_euclid(86_400_000);
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let mut formatted = String::with_capacity(24);
+    let _ = write!(
+        &mut formatted,
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03}Z",
+        millis_of_day / 3_600_000,
+        millis_of_day / 60_000 % 60,
+        millis_of_day / 1000 % 60,
+        millis_of_day % 1000,
+    );
+    formatted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scalars() {
+        // {"a": 1, "b": 9007199254740993 (int64), "c": 1.5, "d": "x\"y", "e": true, "f": null}
+        let bson = b"\x34\x00\x00\x00\x10a\x00\x01\x00\x00\x00\x12b\x00\x01\x00\x00\x00\x00\x00\x20\x00\x01c\x00\x00\x00\x00\x00\x00\x00\xf8\x3f\x02d\x00\x04\x00\x00\x00x\"y\x00\x08e\x00\x01\x0af\x00\x00";
+
+        assert_eq!(
+            to_json(bson).unwrap(),
+            r#"{"a":1,"b":9007199254740993,"c":1.5,"d":"x\"y","e":true,"f":null}"#
+        );
+    }
+
+    #[test]
+    fn test_non_finite_double() {
+        // {"nan": NaN}
+        let bson = b"\x12\x00\x00\x00\x01nan\x00\x00\x00\x00\x00\x00\x00\xf8\x7f\x00";
+        assert_eq!(to_json(bson).unwrap(), r#"{"nan":null}"#);
+    }
+
+    #[test]
+    fn test_nested() {
+        // {"nested": {"inner": 42}, "array": [1, 2]}
+        let bson = b"\x37\x00\x00\x00\x03nested\x00\x10\x00\x00\x00\x10inner\x00*\x00\x00\x00\x00\x04array\x00\x13\x00\x00\x00\x100\x00\x01\x00\x00\x00\x101\x00\x02\x00\x00\x00\x00\x00";
+
+        assert_eq!(
+            to_json(bson).unwrap(),
+            r#"{"nested":{"inner":42},"array":[1,2]}"#
+        );
+    }
+
+    #[test]
+    fn test_datetime() {
+        assert_eq!(format_datetime(0), "1970-01-01 00:00:00.000Z");
+        assert_eq!(format_datetime(1709734020123), "2024-03-06 14:07:00.123Z");
+        assert_eq!(format_datetime(-1), "1969-12-31 23:59:59.999Z");
+
+        // {"d": datetime(1709734020123)}
+        let bson = b"\x10\x00\x00\x00\x09d\x00\x1b\xc4\x16\x14\x8e\x01\x00\x00\x00";
+        assert_eq!(
+            to_json(bson).unwrap(),
+            r#"{"d":"2024-03-06 14:07:00.123Z"}"#
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(format_binary(BinarySubtype(0), b""), "");
+        assert_eq!(format_binary(BinarySubtype(0), b"f"), "Zg==");
+        assert_eq!(format_binary(BinarySubtype(0), b"fo"), "Zm8=");
+        assert_eq!(format_binary(BinarySubtype(0), b"foo"), "Zm9v");
+        assert_eq!(format_binary(BinarySubtype(0), b"foobar"), "Zm9vYmFy");
+
+        assert_eq!(
+            format_binary(
+                BinarySubtype::UUID,
+                b"\x12\x34\x56\x78\x9a\xbc\xde\xf0\x12\x34\x56\x78\x9a\xbc\xde\xf0"
+            ),
+            "12345678-9abc-def0-1234-56789abcdef0"
+        );
+    }
+
+    #[test]
+    fn test_object_id() {
+        // {"_id": ObjectId("0102030405060708090a0b0c")}
+        let bson =
+            b"\x16\x00\x00\x00\x07_id\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x00";
+        assert_eq!(
+            to_json(bson).unwrap(),
+            r#"{"_id":"0102030405060708090a0b0c"}"#
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bson = b"\x10\x00\x00\x00\x10a\x00\x01\x00\x00\x00\x00";
+        assert!(to_json(bson).is_err());
+
+        // Trailing data after the document
+        let bson = b"\x05\x00\x00\x00\x00\x00";
+        assert!(to_json(bson).is_err());
+    }
+}
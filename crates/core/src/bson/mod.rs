@@ -1,9 +1,12 @@
 pub use de::Deserializer;
 pub use error::BsonError;
+pub use json::to_json;
+use parser::Parser;
 use serde::Deserialize;
 
 mod de;
 mod error;
+mod json;
 mod parser;
 
 /// Deserializes BSON [bytes] into a structure [T].
@@ -13,12 +16,18 @@ pub fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, BsonE
     T::deserialize(&mut deserializer)
 }
 
+/// Reads the total size of the BSON document at the start of [bytes], or returns `None` if [bytes]
+/// is too short to contain the size.
+pub fn document_size(bytes: &[u8]) -> Result<Option<usize>, BsonError> {
+    Parser::new(bytes).peek_document_size()
+}
+
 #[cfg(test)]
 mod test {
     use alloc::{vec, vec::Vec};
     use core::assert_matches;
 
-    use crate::sync::line::{SyncLine, TokenExpiresIn};
+    use crate::sync::line::{OplogData, SyncLine, TokenExpiresIn};
 
     use super::*;
 
@@ -280,6 +289,38 @@ mod test {
         assert_eq!(doc.binary, &[1, 2, 3, 4]);
     }
 
+    #[test]
+    fn test_oplog_data_document() {
+        // {"data": {"name": "ab", "n": 1}}
+        let bson = b"\x24\x00\x00\x00\x03data\x00\x19\x00\x00\x00\x02name\x00\x03\x00\x00\x00ab\x00\x10n\x00\x01\x00\x00\x00\x00\x00";
+
+        #[derive(Deserialize)]
+        struct TestDoc<'a> {
+            #[serde(borrow)]
+            data: OplogData<'a>,
+        }
+
+        let doc: TestDoc = from_bytes(bson).expect("should deserialize");
+        assert_matches!(doc.data, OplogData::BsonDocument { .. });
+        assert_eq!(doc.data.to_json().unwrap(), r#"{"name":"ab","n":1}"#);
+    }
+
+    #[test]
+    fn test_oplog_data_string() {
+        // {"data": "{}"}
+        let bson = b"\x12\x00\x00\x00\x02data\x00\x03\x00\x00\x00{}\x00\x00";
+
+        #[derive(Deserialize)]
+        struct TestDoc<'a> {
+            #[serde(borrow)]
+            data: OplogData<'a>,
+        }
+
+        let doc: TestDoc = from_bytes(bson).expect("should deserialize");
+        assert_matches!(doc.data, OplogData::Json { .. });
+        assert_eq!(doc.data.to_json().unwrap(), "{}");
+    }
+
     // Error case tests
 
     #[test]
@@ -3,6 +3,7 @@ use core::ffi::CStr;
 use super::{BsonError, error::ErrorKind};
 use num_traits::{FromBytes, Num};
 
+#[derive(Clone)]
 pub struct Parser<'de> {
     offset: usize,
     remaining_input: &'de [u8],
@@ -163,6 +164,22 @@ impl<'de> Parser<'de> {
         self.subreader(total_size - 4)
     }
 
+    /// Reads the total size of the document at the current offset without advancing the parser.
+    ///
+    /// Returns `None` if the remaining input is too short to contain the size.
+    pub fn peek_document_size(&self) -> Result<Option<usize>, BsonError> {
+        if self.remaining_input.len() < 4 {
+            return Ok(None);
+        }
+
+        let total_size = self.clone().read_length()?;
+        if total_size < 5 {
+            return Err(self.error(ErrorKind::InvalidSize));
+        }
+
+        Ok(Some(total_size))
+    }
+
     /// Skips over a document at the current offset, returning the bytes making up the document.
     pub fn skip_document(&mut self) -> Result<&'de [u8], BsonError> {
         let Some(peek_size) = self.remaining_input.get(0..4) else {
@@ -505,8 +522,13 @@ mod test {
 }
 
 #[repr(transparent)]
+#[derive(Clone, Copy)]
 pub struct BinarySubtype(pub u8);
 
+impl BinarySubtype {
+    pub const UUID: Self = Self(4);
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum ElementType {
     Double = 1,
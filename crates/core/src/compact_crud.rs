@@ -0,0 +1,330 @@
+use core::ffi::c_int;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use powersync_sqlite_nostd as sqlite;
+use powersync_sqlite_nostd::{Connection, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlite::ResultCode;
+
+use crate::error::{PowerSyncError, Result};
+use crate::sync::storage_adapter::IN_FLIGHT_CRUD_BATCH_KEY;
+use crate::utils::database::Database;
+use crate::utils::verify_in_transaction;
+
+/// An entry in `ps_crud`, in the format written by the `powersync_crud` virtual table.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CrudEntry {
+    op: String,
+    id: String,
+    #[serde(rename = "type")]
+    row_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    old: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<String>,
+}
+
+impl CrudEntry {
+    const PUT: &str = "PUT";
+    const PATCH: &str = "PATCH";
+    const DELETE: &str = "DELETE";
+
+    /// Attempts to merge a `next` entry, directly following this one in the same transaction, into
+    /// this entry.
+    ///
+    /// Returns `next` if the two entries can't be represented as a single entry.
+    fn merge(&mut self, next: CrudEntry) -> Option<CrudEntry> {
+        if self.row_type != next.row_type || self.id != next.id {
+            return Some(next);
+        }
+
+        // We can't combine different metadata values, since it's up to the app to interpret them.
+        if let (Some(a), Some(b)) = (&self.metadata, &next.metadata) {
+            if a != b {
+                return Some(next);
+            }
+        }
+
+        let created = self.op == Self::PUT;
+        let data = match (self.op.as_str(), next.op.as_str()) {
+            // A PUT replaces the row entirely, and a DELETE removes it. Both make earlier writes
+            // redundant.
+            (_, Self::PUT) | (_, Self::DELETE) => {
+                self.op = next.op;
+                next.data
+            }
+            // A PATCH on a row that has been created or patched before updates that data.
+            (Self::PUT, Self::PATCH) | (Self::PATCH, Self::PATCH) => {
+                let mut data = self.data.take().unwrap_or_default();
+                data.extend(next.data.unwrap_or_default());
+                Some(data)
+            }
+            _ => return Some(next),
+        };
+
+        // Old values describe the row before the first write, so earlier entries take precedence.
+        // If the row has been created by the first entry, there are no old values to report.
+        self.old = match (self.old.take(), next.old) {
+            _ if created => None,
+            (Some(mut old), Some(next_old)) => {
+                for (key, value) in next_old {
+                    old.entry(key).or_insert(value);
+                }
+                Some(old)
+            }
+            (old, next_old) => old.or(next_old),
+        };
+        self.data = data;
+        if self.metadata.is_none() {
+            self.metadata = next.metadata;
+        }
+
+        None
+    }
+}
+
+/// An entry of `ps_crud` that we may merge subsequent entries into.
+struct PendingEntry {
+    client_id: i64,
+    tx_id: i64,
+    entry: CrudEntry,
+    changed: bool,
+}
+
+/// Merges consecutive entries in `ps_crud` that affect the same row in the same transaction.
+///
+/// Entries are never reordered: Only entries directly following each other are merged, and
+/// entries of different transactions are never merged. Entries of a batch returned by
+/// `next_crud_batch` are left untouched until that batch has been completed, since they may be
+/// uploading. Returns the amount of entries that have been removed.
+///
+/// Merged entries are serialized again, so the order of keys in their `data` and `old` objects
+/// may differ from the original entries.
+fn compact_crud(db: Database) -> Result<i64> {
+    // language=SQLite
+    let in_flight = db.prepare_v2("SELECT CAST(value AS INTEGER) FROM ps_kv WHERE key = ?")?;
+    in_flight.bind_text(1, IN_FLIGHT_CRUD_BATCH_KEY, sqlite::Destructor::STATIC)?;
+    let in_flight = if in_flight.step()? {
+        Some(in_flight.column_int64(0))
+    } else {
+        None
+    };
+
+    // language=SQLite
+    let select = db.prepare_v2("SELECT id, tx_id, data FROM ps_crud ORDER BY id")?;
+    // language=SQLite
+    let update = db.prepare_v2("UPDATE ps_crud SET data = ? WHERE id = ?")?;
+    // language=SQLite
+    let delete = db.prepare_v2("DELETE FROM ps_crud WHERE id = ?")?;
+
+    let mut removed = Vec::<i64>::new();
+    let mut updated = Vec::<(i64, String)>::new();
+    let mut current: Option<PendingEntry> = None;
+
+    let mut flush = |pending: Option<PendingEntry>| -> Result<()> {
+        if let Some(pending) = pending {
+            if pending.changed {
+                let data =
+                    serde_json::to_string(&pending.entry).map_err(PowerSyncError::internal)?;
+                updated.push((pending.client_id, data));
+            }
+        }
+        Ok(())
+    };
+
+    while select.step()? {
+        let client_id = select.column_int64(0);
+        if in_flight.is_some_and(|last_id| client_id <= last_id) {
+            continue;
+        }
+
+        let tx_id = select.column_nullable(1, || Ok(select.column_int64(1)))?;
+        let Ok(entry) = serde_json::from_str::<CrudEntry>(select.column_text(2)?) else {
+            // Not written by powersync_crud, don't touch this entry.
+            flush(current.take())?;
+            continue;
+        };
+        let Some(tx_id) = tx_id else {
+            flush(current.take())?;
+            continue;
+        };
+
+        let entry = match &mut current {
+            Some(pending) if pending.tx_id == tx_id => match pending.entry.merge(entry) {
+                None => {
+                    pending.changed = true;
+                    removed.push(client_id);
+                    continue;
+                }
+                Some(entry) => entry,
+            },
+            _ => entry,
+        };
+
+        flush(current.replace(PendingEntry {
+            client_id,
+            tx_id,
+            entry,
+            changed: false,
+        }))?;
+    }
+    flush(current.take())?;
+
+    for (client_id, data) in &updated {
+        update.bind_text(1, data, sqlite::Destructor::STATIC)?;
+        update.bind_int64(2, *client_id)?;
+        update.exec()?;
+    }
+
+    for client_id in &removed {
+        delete.bind_int64(1, *client_id)?;
+        delete.exec()?;
+    }
+
+    Ok(removed.len() as i64)
+}
+
+extern "C" fn powersync_compact_crud(
+    ctx: *mut sqlite::context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite::value,
+) {
+    let result = (|| -> Result<i64> {
+        let db = Database::from(ctx.db_handle());
+        verify_in_transaction(db)?;
+        compact_crud(db)
+    })();
+
+    match result {
+        Ok(removed) => ctx.result_int64(removed),
+        Err(e) => e.apply_to_ctx("powersync_compact_crud", ctx),
+    }
+}
+
+pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode> {
+    db.create_function_v2(
+        "powersync_compact_crud",
+        0,
+        sqlite::UTF8 | sqlite::DIRECTONLY,
+        None,
+        Some(powersync_compact_crud),
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(json: &str) -> CrudEntry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn merged(first: &str, second: &str) -> Option<String> {
+        let mut first = entry(first);
+        match first.merge(entry(second)) {
+            None => Some(serde_json::to_string(&first).unwrap()),
+            Some(_) => None,
+        }
+    }
+
+    #[test]
+    fn merges_patches() {
+        assert_eq!(
+            merged(
+                r#"{"op":"PUT","id":"a","type":"t","data":{"x":1,"y":2}}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"y":3}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"PUT","id":"a","type":"t","data":{"x":1,"y":3}}"#)
+        );
+        assert_eq!(
+            merged(
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"x":1},"old":{"x":0}}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"x":2,"y":1},"old":{"x":1,"y":0}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"PATCH","id":"a","type":"t","data":{"x":2,"y":1},"old":{"x":0,"y":0}}"#)
+        );
+    }
+
+    #[test]
+    fn drops_old_values_of_created_rows() {
+        assert_eq!(
+            merged(
+                r#"{"op":"PUT","id":"a","type":"t","data":{"x":1}}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"x":2},"old":{"x":1}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"PUT","id":"a","type":"t","data":{"x":2}}"#)
+        );
+    }
+
+    #[test]
+    fn merges_delete() {
+        assert_eq!(
+            merged(
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"x":1},"old":{"x":0}}"#,
+                r#"{"op":"DELETE","id":"a","type":"t","old":{"x":1,"y":2}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"DELETE","id":"a","type":"t","old":{"x":0,"y":2}}"#)
+        );
+        assert_eq!(
+            merged(
+                r#"{"op":"DELETE","id":"a","type":"t"}"#,
+                r#"{"op":"PUT","id":"a","type":"t","data":{"x":1}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"PUT","id":"a","type":"t","data":{"x":1}}"#)
+        );
+    }
+
+    #[test]
+    fn does_not_merge() {
+        // Different rows
+        assert_eq!(
+            merged(
+                r#"{"op":"PATCH","id":"a","type":"t","data":{}}"#,
+                r#"{"op":"PATCH","id":"b","type":"t","data":{}}"#
+            ),
+            None
+        );
+        // Patch after delete
+        assert_eq!(
+            merged(
+                r#"{"op":"DELETE","id":"a","type":"t"}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{}}"#
+            ),
+            None
+        );
+        // Conflicting metadata
+        assert_eq!(
+            merged(
+                r#"{"op":"PATCH","id":"a","type":"t","data":{},"metadata":"a"}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{},"metadata":"b"}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn keeps_metadata() {
+        assert_eq!(
+            merged(
+                r#"{"op":"PATCH","id":"a","type":"t","data":{},"metadata":"a"}"#,
+                r#"{"op":"PATCH","id":"a","type":"t","data":{"x":1}}"#
+            )
+            .as_deref(),
+            Some(r#"{"op":"PATCH","id":"a","type":"t","data":{"x":1},"metadata":"a"}"#)
+        );
+    }
+}
@@ -135,6 +135,10 @@ impl VirtualTable {
                 let metadata = args[5];
                 let data = Self::value_to_json(&args[3]);
 
+                // The view trigger has just written to the data table, which we've observed in the
+                // update hook without knowing the id of the row.
+                self.state.track_row_id(row_type, id);
+
                 if flags.ignore_empty_update()
                     && op == "PATCH"
                     && data.map(|r| r.get()) == Some("{}")
@@ -262,7 +266,10 @@ impl SimpleCrudTransactionMode {
     fn set_updated_rows_statement(&mut self, db: Database) -> Result<&Statement> {
         prepare_lazy(&mut self.set_updated_rows, || {
             // language=SQLite
-            db.prepare_v2("INSERT OR IGNORE INTO ps_updated_rows(row_type, row_id) VALUES(?, ?)")
+            db.prepare_v2(
+                "INSERT INTO ps_updated_rows(row_type, row_id, local_write) VALUES(?, ?, 1)
+  ON CONFLICT DO UPDATE SET local_write = 1",
+            )
         })
     }
 
@@ -1,6 +1,9 @@
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::ffi::c_int;
 
 use powersync_sqlite_nostd as sqlite;
@@ -59,7 +62,339 @@ pub fn diff_objects(data_old: &str, data_new: &str) -> Result<String> {
     }
 }
 
+/// Like [diff_objects], but recursively diffs nested objects instead of replacing them entirely.
+///
+/// The result is a JSON merge patch (RFC 7396) transforming `data_old` into `data_new`. As with
+/// [diff_objects], `null` values are treated like absent keys.
+pub fn diff_objects_deep(data_old: &str, data_new: &str) -> Result<String> {
+    let (json::Value::Object(old), json::Value::Object(new)) = parse_pair(data_old, data_new)?
+    else {
+        return Err(PowerSyncError::argument_error("expected two JSON objects"));
+    };
+
+    Ok(json::Value::Object(deep_diff(old, new)).to_string())
+}
+
+/// Computes a JSON merge patch transforming `old` into `new`, see [diff_objects_deep].
+pub fn deep_diff(
+    mut old: json::Map<String, json::Value>,
+    new: json::Map<String, json::Value>,
+) -> json::Map<String, json::Value> {
+    old.retain(|_, v| !v.is_null());
+    let mut diff = json::Map::new();
+
+    for (key, value) in new {
+        if value.is_null() {
+            continue;
+        }
+
+        match (old.remove(&key), value) {
+            (Some(json::Value::Object(old)), json::Value::Object(new)) => {
+                let nested = deep_diff(old, new);
+                if !nested.is_empty() {
+                    diff.insert(key, json::Value::Object(nested));
+                }
+            }
+            (Some(old), new) if old == new => {}
+            (_, new) => {
+                diff.insert(key, new);
+            }
+        }
+    }
+
+    // Remaining keys have been removed.
+    for (key, _) in old {
+        diff.insert(key, json::Value::Null);
+    }
+
+    diff
+}
+
+/// Generates a list of RFC 6902 JSON Patch operations transforming `data_old` into `data_new`.
+///
+/// Unlike the other diff functions, `null` is treated as a regular value here so that applying the
+/// patch reproduces `data_new` exactly. Nested objects are diffed recursively, arrays are replaced
+/// as a whole if they differ.
+pub fn diff_json_patch(data_old: &str, data_new: &str) -> Result<String> {
+    let (old, new) = parse_pair(data_old, data_new)?;
+    let mut operations = vec![];
+    json_patch_operations(&mut String::new(), old, new, &mut operations);
+
+    Ok(json::Value::Array(operations).to_string())
+}
+
+fn json_patch_operations(
+    path: &mut String,
+    old: json::Value,
+    new: json::Value,
+    operations: &mut Vec<json::Value>,
+) {
+    fn operation(op: &str, path: &str, value: Option<json::Value>) -> json::Value {
+        let mut operation = json::Map::new();
+        operation.insert("op".into(), op.into());
+        operation.insert("path".into(), path.into());
+        if let Some(value) = value {
+            operation.insert("value".into(), value);
+        }
+        json::Value::Object(operation)
+    }
+
+    match (old, new) {
+        (json::Value::Object(mut old), json::Value::Object(new)) => {
+            let prefix_len = path.len();
+            for (key, value) in new {
+                push_pointer_token(path, &key);
+                match old.remove(&key) {
+                    Some(old) => json_patch_operations(path, old, value, operations),
+                    None => operations.push(operation("add", path, Some(value))),
+                }
+                path.truncate(prefix_len);
+            }
+
+            for (key, _) in old {
+                push_pointer_token(path, &key);
+                operations.push(operation("remove", path, None));
+                path.truncate(prefix_len);
+            }
+        }
+        (old, new) if old == new => {}
+        (_, new) => operations.push(operation("replace", path, Some(new))),
+    }
+}
+
+fn parse_pair(data_old: &str, data_new: &str) -> Result<(json::Value, json::Value)> {
+    let v_new: json::Value = json::from_str(data_new).map_err(PowerSyncError::as_argument_error)?;
+    let v_old: json::Value = json::from_str(data_old).map_err(PowerSyncError::as_argument_error)?;
+    Ok((v_old, v_new))
+}
+
+/// Appends a reference token to a JSON pointer (RFC 6901).
+fn push_pointer_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    for char in token.chars() {
+        match char {
+            '~' => pointer.push_str("~0"),
+            '/' => pointer.push_str("~1"),
+            c => pointer.push(c),
+        }
+    }
+}
+
+/// Parses a JSON pointer (RFC 6901) into its unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let Some(pointer) = pointer.strip_prefix('/') else {
+        return Err(PowerSyncError::argument_error(format!(
+            "invalid JSON pointer: {pointer}"
+        )));
+    };
+
+    Ok(pointer
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Resolves an array index for JSON patch operations. If `allow_end` is set, `-` and the length
+/// of the array are valid indices referring to the end of the array.
+fn array_index(token: &str, len: usize, allow_end: bool) -> Result<usize> {
+    let index = if token == "-" && allow_end {
+        len
+    } else if token.is_empty()
+        || (token.len() > 1 && token.starts_with('0'))
+        || !token.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(PowerSyncError::argument_error(format!(
+            "invalid array index: {token}"
+        )));
+    } else {
+        token
+            .parse::<usize>()
+            .map_err(|_| PowerSyncError::argument_error(format!("invalid array index: {token}")))?
+    };
+
+    if index > len || (index == len && !allow_end) {
+        return Err(PowerSyncError::argument_error(format!(
+            "array index out of bounds: {token}"
+        )));
+    }
+
+    Ok(index)
+}
+
+fn resolve_mut<'a>(doc: &'a mut json::Value, tokens: &[String]) -> Result<&'a mut json::Value> {
+    let mut current = doc;
+    for token in tokens {
+        current = match current {
+            json::Value::Object(map) => map.get_mut(token),
+            json::Value::Array(array) => {
+                let index = array_index(token, array.len(), false)?;
+                array.get_mut(index)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| PowerSyncError::argument_error(format!("path not found: {token}")))?;
+    }
+
+    Ok(current)
+}
+
+fn patch_add(doc: &mut json::Value, tokens: &[String], value: json::Value) -> Result<()> {
+    let Some((last, parent)) = tokens.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+
+    match resolve_mut(doc, parent)? {
+        json::Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        json::Value::Array(array) => {
+            let index = array_index(last, array.len(), true)?;
+            array.insert(index, value);
+        }
+        _ => {
+            return Err(PowerSyncError::argument_error(
+                "can only add to objects and arrays",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn patch_remove(doc: &mut json::Value, tokens: &[String]) -> Result<json::Value> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return Err(PowerSyncError::argument_error("can't remove the root"));
+    };
+
+    match resolve_mut(doc, parent)? {
+        json::Value::Object(map) => map
+            .remove(last)
+            .ok_or_else(|| PowerSyncError::argument_error(format!("path not found: {last}"))),
+        json::Value::Array(array) => {
+            let index = array_index(last, array.len(), false)?;
+            Ok(array.remove(index))
+        }
+        _ => Err(PowerSyncError::argument_error(format!(
+            "path not found: {last}"
+        ))),
+    }
+}
+
+/// Applies RFC 6902 JSON Patch operations to a document.
+///
+/// Either all operations are applied, or an error is returned.
+pub fn apply_json_patch(doc: &str, patch: &str) -> Result<String> {
+    let mut doc: json::Value = json::from_str(doc).map_err(PowerSyncError::as_argument_error)?;
+    let json::Value::Array(operations) =
+        json::from_str(patch).map_err(PowerSyncError::as_argument_error)?
+    else {
+        return Err(PowerSyncError::argument_error(
+            "expected patch to be an array of operations",
+        ));
+    };
+
+    for operation in operations {
+        let field = |name: &'static str| {
+            operation
+                .get(name)
+                .ok_or_else(|| PowerSyncError::argument_error(format!("missing {name}")))
+        };
+        let pointer = |name: &'static str| match field(name)? {
+            json::Value::String(pointer) => parse_pointer(pointer),
+            _ => Err(PowerSyncError::argument_error(format!(
+                "expected {name} to be a string"
+            ))),
+        };
+
+        let op = field("op")?.as_str().unwrap_or_default();
+        let path = pointer("path")?;
+
+        match op {
+            "add" => patch_add(&mut doc, &path, field("value")?.clone())?,
+            "remove" => {
+                patch_remove(&mut doc, &path)?;
+            }
+            "replace" => {
+                let target = resolve_mut(&mut doc, &path)?;
+                *target = field("value")?.clone();
+            }
+            "move" => {
+                let from = pointer("from")?;
+                if path.len() > from.len() && path.starts_with(&from) {
+                    return Err(PowerSyncError::argument_error(
+                        "can't move a value into one of its children",
+                    ));
+                }
+
+                let value = patch_remove(&mut doc, &from)?;
+                patch_add(&mut doc, &path, value)?;
+            }
+            "copy" => {
+                let from = pointer("from")?;
+                let value = resolve_mut(&mut doc, &from)?.clone();
+                patch_add(&mut doc, &path, value)?;
+            }
+            "test" => {
+                if resolve_mut(&mut doc, &path)? != field("value")? {
+                    return Err(PowerSyncError::argument_error("test operation failed"));
+                }
+            }
+            _ => {
+                return Err(PowerSyncError::argument_error(format!(
+                    "unknown patch operation: {op}"
+                )));
+            }
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+fn powersync_diff_deep_impl(
+    ctx: *mut sqlite::context,
+    args: &[*mut sqlite::value],
+) -> Result<String> {
+    ctx.result_subtype(SUBTYPE_JSON);
+    diff_objects_deep(args[0].text(), args[1].text())
+}
+
+fn powersync_diff_json_patch_impl(
+    ctx: *mut sqlite::context,
+    args: &[*mut sqlite::value],
+) -> Result<String> {
+    ctx.result_subtype(SUBTYPE_JSON);
+    diff_json_patch(args[0].text(), args[1].text())
+}
+
+fn powersync_json_patch_impl(
+    ctx: *mut sqlite::context,
+    args: &[*mut sqlite::value],
+) -> Result<String> {
+    ctx.result_subtype(SUBTYPE_JSON);
+    apply_json_patch(args[0].text(), args[1].text())
+}
+
 create_sqlite_text_fn!(powersync_diff, powersync_diff_impl, "powersync_diff");
+create_sqlite_text_fn!(
+    powersync_diff_deep,
+    powersync_diff_deep_impl,
+    "powersync_diff_deep"
+);
+create_sqlite_text_fn!(
+    powersync_diff_json_patch,
+    powersync_diff_json_patch_impl,
+    "powersync_diff_json_patch"
+);
+create_sqlite_text_fn!(
+    powersync_json_patch,
+    powersync_json_patch_impl,
+    "powersync_json_patch"
+);
 
 pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode> {
     db.create_function_v2(
@@ -73,6 +408,39 @@ pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode
         None,
     )?;
 
+    db.create_function_v2(
+        "powersync_diff_deep",
+        2,
+        sqlite::UTF8 | sqlite::DETERMINISTIC | SQLITE_RESULT_SUBTYPE,
+        None,
+        Some(powersync_diff_deep),
+        None,
+        None,
+        None,
+    )?;
+
+    db.create_function_v2(
+        "powersync_diff_json_patch",
+        2,
+        sqlite::UTF8 | sqlite::DETERMINISTIC | SQLITE_RESULT_SUBTYPE,
+        None,
+        Some(powersync_diff_json_patch),
+        None,
+        None,
+        None,
+    )?;
+
+    db.create_function_v2(
+        "powersync_json_patch",
+        2,
+        sqlite::UTF8 | sqlite::DETERMINISTIC | SQLITE_RESULT_SUBTYPE,
+        None,
+        Some(powersync_json_patch),
+        None,
+        None,
+        None,
+    )?;
+
     Ok(())
 }
 
@@ -134,4 +502,107 @@ mod tests {
             r#"{"a":[1,2,3]}"#
         );
     }
+
+    #[test]
+    fn deep_diff_test() {
+        assert_eq!(
+            diff_objects_deep(r#"{"a": {"b": 1, "c": 2}}"#, r#"{"a": {"b": 1, "c": 3}}"#).unwrap(),
+            r#"{"a":{"c":3}}"#
+        );
+        assert_eq!(
+            diff_objects_deep(r#"{"a": {"b": 1, "c": 2}}"#, r#"{"a": {"b": 1}}"#).unwrap(),
+            r#"{"a":{"c":null}}"#
+        );
+        assert_eq!(
+            diff_objects_deep(r#"{"a": {"b": {"c": 1}}}"#, r#"{"a": {"b": {"c": 1}}}"#).unwrap(),
+            r#"{}"#
+        );
+        assert_eq!(
+            diff_objects_deep(r#"{"a": {"b": 1}}"#, r#"{"a": 1, "b": null}"#).unwrap(),
+            r#"{"a":1}"#
+        );
+        assert_eq!(
+            diff_objects_deep(r#"{"a": 1}"#, r#"{}"#).unwrap(),
+            r#"{"a":null}"#
+        );
+    }
+
+    #[test]
+    fn json_patch_diff_test() {
+        assert_eq!(diff_json_patch(r#"{"a": 1}"#, r#"{"a": 1}"#).unwrap(), "[]");
+        assert_eq!(
+            diff_json_patch(
+                r#"{"a": {"b": 1, "c/d": 2}}"#,
+                r#"{"a": {"b": 2}, "e": null}"#
+            )
+            .unwrap(),
+            r#"[{"op":"replace","path":"/a/b","value":2},{"op":"remove","path":"/a/c~1d"},{"op":"add","path":"/e","value":null}]"#
+        );
+        assert_eq!(
+            diff_json_patch(r#"{"a": [1, 2]}"#, r#"{"a": [1]}"#).unwrap(),
+            r#"[{"op":"replace","path":"/a","value":[1]}]"#
+        );
+    }
+
+    #[test]
+    fn json_patch_roundtrip_test() {
+        let old = r#"{"a":{"b":1,"c~d":[1,2]},"e":"x","f":null}"#;
+        let new = r#"{"a":{"b":2,"c~d":[3]},"g":{"h":true}}"#;
+
+        let patch = diff_json_patch(old, new).unwrap();
+        assert_eq!(apply_json_patch(old, &patch).unwrap(), new);
+    }
+
+    #[test]
+    fn apply_json_patch_test() {
+        assert_eq!(
+            apply_json_patch(
+                r#"{"a": [1, 2]}"#,
+                r#"[{"op":"add","path":"/a/1","value":3},{"op":"add","path":"/a/-","value":4}]"#
+            )
+            .unwrap(),
+            r#"{"a":[1,3,2,4]}"#
+        );
+        assert_eq!(
+            apply_json_patch(
+                r#"{"a": {"b": 1}}"#,
+                r#"[{"op":"move","from":"/a/b","path":"/c"},{"op":"copy","from":"/c","path":"/a/d"}]"#
+            )
+            .unwrap(),
+            r#"{"a":{"d":1},"c":1}"#
+        );
+        assert_eq!(
+            apply_json_patch(
+                r#"{"a": 1}"#,
+                r#"[{"op":"test","path":"/a","value":1},{"op":"replace","path":"","value":[]}]"#
+            )
+            .unwrap(),
+            r#"[]"#
+        );
+
+        assert!(
+            apply_json_patch(r#"{"a": 1}"#, r#"[{"op":"test","path":"/a","value":2}]"#).is_err()
+        );
+        assert!(apply_json_patch(r#"{"a": 1}"#, r#"[{"op":"remove","path":"/b"}]"#).is_err());
+        assert!(
+            apply_json_patch(r#"{"a": 1}"#, r#"[{"op":"replace","path":"/b","value":1}]"#).is_err()
+        );
+        assert!(
+            apply_json_patch(r#"{"a": [1]}"#, r#"[{"op":"add","path":"/a/2","value":1}]"#).is_err()
+        );
+        assert!(
+            apply_json_patch(
+                r#"{"a": [1]}"#,
+                r#"[{"op":"add","path":"/a/01","value":1}]"#
+            )
+            .is_err()
+        );
+        assert!(
+            apply_json_patch(
+                r#"{"a": {}}"#,
+                r#"[{"op":"move","from":"/a","path":"/a/b"}]"#
+            )
+            .is_err()
+        );
+    }
 }
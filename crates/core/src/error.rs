@@ -9,9 +9,11 @@ use alloc::{
     boxed::Box,
     ffi::{CString, NulError},
     string::{String, ToString},
+    vec::Vec,
 };
 use num_traits::FromPrimitive;
 use powersync_sqlite_nostd::{self as sqlite, Connection, Context, ResultCode, context, sqlite3};
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::{
@@ -153,7 +155,7 @@ impl PowerSyncError {
     }
 
     pub fn can_retry(&self) -> bool {
-        match self.inner.as_ref() {
+        match self.root() {
             RawPowerSyncError::Sqlite(cause) => {
                 let base_error = ResultCode::from_i32((cause.code as i32) & 0xFF);
                 if base_error == Some(ResultCode::BUSY) || base_error == Some(ResultCode::LOCKED) {
@@ -166,6 +168,71 @@ impl PowerSyncError {
         }
     }
 
+    /// Whether this error has been caused by a statement violating a constraint, e.g. a unique
+    /// index.
+    pub fn is_constraint_violation(&self) -> bool {
+        match self.root() {
+            RawPowerSyncError::Sqlite(cause) => {
+                ResultCode::from_i32((cause.code as i32) & 0xFF) == Some(ResultCode::CONSTRAINT)
+            }
+            _ => false,
+        }
+    }
+
+    /// The underlying error, skipping [RawPowerSyncError::Context] wrappers.
+    fn root(&self) -> &RawPowerSyncError {
+        match self.inner.as_ref() {
+            RawPowerSyncError::Context { inner, .. } => inner.root(),
+            inner => inner,
+        }
+    }
+
+    /// Describes this error in a structured format, allowing SDKs to inspect errors without
+    /// parsing messages.
+    pub fn describe(&self) -> ErrorDescription<'_> {
+        let mut context = Vec::new();
+        let mut error = self;
+        while let RawPowerSyncError::Context {
+            inner,
+            context: desc,
+        } = error.inner.as_ref()
+        {
+            context.push(desc.as_str());
+            error = inner;
+        }
+
+        let root = error.inner.as_ref();
+        let (sql, sqlite_message) = match root {
+            RawPowerSyncError::Sqlite(e) => (
+                e.statement.as_ref().map(|stmt| stmt.to_string()),
+                e.errstr.as_deref(),
+            ),
+            _ => (None, None),
+        };
+
+        let bson_offset = match root {
+            RawPowerSyncError::ArgumentError { cause, .. }
+            | RawPowerSyncError::SyncProtocolError { cause, .. }
+            | RawPowerSyncError::LocalDataError { cause }
+            | RawPowerSyncError::Internal { cause } => match cause {
+                PowerSyncErrorCause::Bson(e) => e.offset(),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        ErrorDescription {
+            variant: root.variant_name(),
+            message: self.to_string(),
+            code: self.sqlite_error_code() as c_int,
+            retryable: self.can_retry(),
+            sql,
+            sqlite_message,
+            bson_offset,
+            context,
+        }
+    }
+
     pub fn check_sqlite3_version() -> Result<()> {
         let actual_version = sqlite::libversion_number();
 
@@ -274,6 +341,45 @@ pub enum RawPowerSyncError {
     },
 }
 
+impl RawPowerSyncError {
+    fn variant_name(&self) -> &'static str {
+        match self {
+            Self::Sqlite(_) => "Sqlite",
+            Self::ArgumentError { .. } => "ArgumentError",
+            Self::StateError { .. } => "StateError",
+            Self::SyncProtocolError { .. } => "SyncProtocolError",
+            Self::LocalDataError { .. } => "LocalDataError",
+            Self::MissingClientId => "MissingClientId",
+            Self::DownMigrationDidNotUpdateVersion { .. } => "DownMigrationDidNotUpdateVersion",
+            Self::Internal { .. } => "Internal",
+            Self::SqliteVersionMismatch { .. } => "SqliteVersionMismatch",
+            Self::MustBeCalledInTransaction => "MustBeCalledInTransaction",
+            Self::CString { .. } => "CString",
+            Self::Context { inner, .. } => inner.inner.variant_name(),
+        }
+    }
+}
+
+/// A structured description of a [PowerSyncError], see [PowerSyncError::describe].
+#[derive(Serialize, Debug)]
+pub struct ErrorDescription<'a> {
+    /// The name of the [RawPowerSyncError] variant, ignoring context wrappers.
+    variant: &'static str,
+    message: String,
+    /// The (extended) SQLite result code reported for this error.
+    code: c_int,
+    /// Whether the operation may succeed when retried (e.g. because the database was locked).
+    retryable: bool,
+    /// For errors from SQLite, the statement that failed (if known).
+    sql: Option<String>,
+    /// For errors from SQLite, the message reported by `sqlite3_errmsg`.
+    sqlite_message: Option<&'a str>,
+    /// For errors caused by malformed BSON, the offset at which the document was invalid.
+    bson_offset: Option<usize>,
+    /// Context added to the error, outermost first.
+    context: Vec<&'a str>,
+}
+
 #[derive(Debug)]
 pub struct SqliteError {
     code: ResultCode,
@@ -353,3 +459,69 @@ impl Display for PowerSyncErrorCause {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde::de::IgnoredAny;
+
+    use crate::bson;
+
+    use super::*;
+
+    #[test]
+    fn describes_errors() {
+        // Document with an element of unknown type (0x20)
+        let bson_error =
+            bson::from_bytes::<IgnoredAny>(b"\x08\x00\x00\x00\x20a\x00\x00").unwrap_err();
+        let offset = bson_error.offset();
+        assert!(offset.is_some());
+
+        let error = PowerSyncError::sync_protocol_error("invalid binary line", bson_error)
+            .context("inner".to_string())
+            .context("outer".to_string());
+
+        let description = error.describe();
+        assert_eq!(description.variant, "SyncProtocolError");
+        assert_eq!(description.code, ResultCode::ABORT as c_int);
+        assert!(!description.retryable);
+        assert_eq!(description.bson_offset, offset);
+        assert_eq!(description.context, ["outer", "inner"]);
+        assert_eq!(description.sql, None);
+    }
+
+    #[test]
+    fn can_retry_with_context() {
+        let error: PowerSyncError = RawPowerSyncError::Sqlite(SqliteError {
+            code: ResultCode::BUSY_SNAPSHOT,
+            errstr: Some("database is locked".to_string()),
+            statement: Some("SELECT 1".into()),
+        })
+        .into();
+        let error = error.context("while testing".to_string());
+        assert!(error.can_retry());
+
+        let description = error.describe();
+        assert_eq!(description.variant, "Sqlite");
+        assert!(description.retryable);
+        assert_eq!(description.sql.as_deref(), Some("SELECT 1"));
+        assert_eq!(description.sqlite_message, Some("database is locked"));
+    }
+
+    #[test]
+    fn constraint_violation() {
+        let error: PowerSyncError = RawPowerSyncError::Sqlite(SqliteError {
+            code: ResultCode::CONSTRAINT_UNIQUE,
+            errstr: None,
+            statement: None,
+        })
+        .into();
+        assert!(
+            error
+                .context("while testing".to_string())
+                .is_constraint_violation()
+        );
+
+        // Argument errors use a constraint error code, but aren't caused by a constraint.
+        assert!(!PowerSyncError::argument_error("invalid").is_constraint_violation());
+    }
+}
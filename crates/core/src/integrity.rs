@@ -0,0 +1,382 @@
+use core::ffi::{c_int, c_void};
+use core::fmt::Write;
+
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use powersync_sqlite_nostd as sqlite;
+use powersync_sqlite_nostd::bindings::SQLITE_RESULT_SUBTYPE;
+use powersync_sqlite_nostd::{Connection, Context};
+use serde::Serialize;
+use sqlite::ResultCode;
+
+use crate::constants::SUBTYPE_JSON;
+use crate::error::{PowerSyncError, Result};
+use crate::schema::inspection::ExistingTable;
+use crate::schema::storage::TableStorage;
+use crate::state::DatabaseState;
+use crate::sync::Checksum;
+use crate::utils::database::Database;
+use crate::utils::{SqlBuffer, verify_in_transaction};
+
+/// A row in a `ps_data__` table, identified by its type and id.
+#[derive(Serialize, Debug, PartialEq)]
+struct RowReference {
+    #[serde(rename = "type")]
+    row_type: String,
+    id: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ChecksumMismatch {
+    bucket: String,
+    add_checksum: Checksum,
+    op_checksum: Checksum,
+    /// The sum of `ps_oplog.hash` for the bucket, which `op_checksum` should be equal to.
+    oplog_checksum: Checksum,
+}
+
+#[derive(Serialize, Debug)]
+struct UnknownUpdatedRows {
+    #[serde(rename = "type")]
+    row_type: String,
+    count: i64,
+}
+
+/// The result of `powersync_integrity_check()`.
+#[derive(Serialize, Default, Debug)]
+struct IntegrityReport {
+    /// Rows in data tables not backed by any oplog entry or pending local change.
+    ///
+    /// These would have been removed by `sync_local`.
+    dangling_rows: Vec<RowReference>,
+    /// Rows whose latest oplog entry belongs to an applied range of its bucket, but without the
+    /// data table reflecting that entry.
+    unapplied_rows: Vec<RowReference>,
+    /// Buckets where the stored `add_checksum + op_checksum` doesn't match `add_checksum` plus the
+    /// sum of hashes in `ps_oplog`.
+    checksum_mismatches: Vec<ChecksumMismatch>,
+    /// Types in `ps_updated_rows` without a data table.
+    unknown_updated_rows: Vec<UnknownUpdatedRows>,
+}
+
+impl IntegrityReport {
+    fn is_ok(&self) -> bool {
+        self.dangling_rows.is_empty()
+            && self.unapplied_rows.is_empty()
+            && self.checksum_mismatches.is_empty()
+            && self.unknown_updated_rows.is_empty()
+    }
+}
+
+/// Synced (not local-only) data tables, as `(type, quoted table name, storage)` tuples.
+fn synced_tables(db: Database) -> Result<Vec<(String, String, TableStorage)>> {
+    Ok(ExistingTable::list(db)?
+        .into_iter()
+        .filter(|table| !table.local_only)
+        .map(|table| {
+            let quoted = SqlBuffer::quote_identifier(&table.internal_name);
+            (table.name, quoted, table.storage)
+        })
+        .collect())
+}
+
+fn collect_rows(db: Database, sql: &str, row_type: &str) -> Result<Vec<RowReference>> {
+    let stmt = db.prepare_v2(sql)?;
+    stmt.bind_text(1, row_type, sqlite::Destructor::STATIC)?;
+
+    let mut rows = Vec::new();
+    while stmt.step()? {
+        rows.push(RowReference {
+            row_type: row_type.to_string(),
+            id: stmt.column_text(0)?.to_string(),
+        });
+    }
+    Ok(rows)
+}
+
+fn dangling_rows(db: Database, row_type: &str, table: &str) -> Result<Vec<RowReference>> {
+    // Rows in ps_updated_rows are either local writes that haven't been acknowledged yet, or
+    // pending removals. Both are expected to not have an oplog entry.
+    // language=SQLite
+    let sql = format!(
+        "\
+SELECT d.id FROM {table} d
+  WHERE NOT EXISTS (SELECT 1 FROM ps_oplog WHERE row_type = ?1 AND row_id = d.id)
+    AND NOT EXISTS (SELECT 1 FROM ps_updated_rows WHERE row_type = ?1 AND row_id = d.id)"
+    );
+    collect_rows(db, &sql, row_type)
+}
+
+fn unapplied_rows(
+    db: Database,
+    row_type: &str,
+    table: &str,
+    storage: &TableStorage,
+) -> Result<Vec<RowReference>> {
+    // Like sync_local, the latest oplog entry across all buckets determines the row. If that entry
+    // has been applied, the data table must contain its data (or no row for NULL data).
+    let mut sql = SqlBuffer::new();
+    // language=SQLite
+    sql.push_str(
+        "\
+SELECT r.row_id FROM ps_oplog r
+  JOIN ps_buckets b ON b.id = r.bucket
+  WHERE r.row_type = ?1
+    AND r.op_id <= b.last_applied_op
+    AND r.op_id = (SELECT max(op_id) FROM ps_oplog WHERE row_type = ?1 AND row_id = r.row_id)
+    AND NOT EXISTS (SELECT 1 FROM ps_updated_rows WHERE row_type = ?1 AND row_id = r.row_id)
+    AND ",
+    );
+    storage.write_row_differs_from_json(&mut sql, table, "r.row_id", "r.data");
+    sql.push_str("\n  GROUP BY r.row_id");
+    collect_rows(db, &sql.sql, row_type)
+}
+
+fn checksum_mismatches(db: Database) -> Result<Vec<ChecksumMismatch>> {
+    // language=SQLite
+    let stmt = db.prepare_v2(
+        "\
+SELECT b.name, b.add_checksum, b.op_checksum, (SELECT ifnull(sum(hash), 0) FROM ps_oplog WHERE bucket = b.id)
+  FROM ps_buckets b",
+    )?;
+
+    // Checksums are added with 32-bit wraparound, see [Checksum].
+    let checksum = |column: c_int| Checksum::from_value(stmt.column_int64(column) as u32);
+
+    let mut mismatches = Vec::new();
+    while stmt.step()? {
+        let op_checksum = checksum(2);
+        let oplog_checksum = checksum(3);
+
+        if op_checksum != oplog_checksum {
+            mismatches.push(ChecksumMismatch {
+                bucket: stmt.column_text(0)?.to_string(),
+                add_checksum: checksum(1),
+                op_checksum,
+                oplog_checksum,
+            });
+        }
+    }
+
+    Ok(mismatches)
+}
+
+fn unknown_updated_rows(db: Database, state: &DatabaseState) -> Result<Vec<UnknownUpdatedRows>> {
+    // language=SQLite
+    let stmt = db.prepare_v2(
+        "\
+SELECT row_type, count(*) FROM ps_updated_rows u
+  WHERE NOT EXISTS (
+    SELECT 1 FROM sqlite_schema WHERE type = 'table' AND name = 'ps_data__' || u.row_type
+  )
+  GROUP BY row_type",
+    )?;
+
+    let schema = state.view_schema();
+    let mut unknown = Vec::new();
+    while stmt.step()? {
+        let row_type = stmt.column_text(0)?;
+        let is_raw_table = schema
+            .as_ref()
+            .is_some_and(|schema| schema.raw_tables.iter().any(|t| t.name == row_type));
+
+        if !is_raw_table {
+            unknown.push(UnknownUpdatedRows {
+                row_type: row_type.to_string(),
+                count: stmt.column_int64(1),
+            });
+        }
+    }
+
+    Ok(unknown)
+}
+
+fn integrity_check(db: Database, state: &DatabaseState) -> Result<IntegrityReport> {
+    let mut report = IntegrityReport::default();
+
+    for (row_type, table, storage) in synced_tables(db)? {
+        report
+            .dangling_rows
+            .extend(dangling_rows(db, &row_type, &table)?);
+        report
+            .unapplied_rows
+            .extend(unapplied_rows(db, &row_type, &table, &storage)?);
+    }
+
+    report.checksum_mismatches = checksum_mismatches(db)?;
+    report.unknown_updated_rows = unknown_updated_rows(db, state)?;
+    Ok(report)
+}
+
+#[derive(Serialize, Default)]
+struct RepairResult {
+    removed_rows: usize,
+    restored_rows: usize,
+    /// Unapplied rows that could not be restored because their data violates a unique index on
+    /// the table, e.g. because another row has the same value.
+    conflicting_rows: Vec<RowReference>,
+    repaired_buckets: usize,
+}
+
+/// Restores the contents of data tables from `ps_oplog` for rows reported by
+/// [integrity_check], and recomputes `op_checksum` for buckets with a checksum mismatch.
+///
+/// Rows with pending local changes are never touched. Rows that can't be restored without
+/// violating a unique index are left unchanged and reported instead.
+fn repair(db: Database, state: &DatabaseState) -> Result<RepairResult> {
+    // Like sync_local, we write data from the oplog. This makes update hooks report these writes
+    // as remote changes.
+    let _guard = state.sync_local_guard();
+    let mut result = RepairResult::default();
+
+    for (row_type, table, storage) in synced_tables(db)? {
+        // language=SQLite
+        let delete = db.prepare_v2(&format!("DELETE FROM {table} WHERE id = ?"))?;
+        for row in dangling_rows(db, &row_type, &table)? {
+            delete.bind_text(1, &row.id, sqlite::Destructor::STATIC)?;
+            delete.exec()?;
+            result.removed_rows += 1;
+        }
+
+        let mut restore = SqlBuffer::new();
+        let _ = write!(&mut restore, "INSERT INTO {table}");
+        storage.write_insert_from_json(&mut restore, "row_id", "data");
+        // language=SQLite
+        restore.push_str(
+            " FROM (
+    SELECT row_id, data FROM ps_oplog WHERE row_type = ?1 AND row_id = ?2
+      ORDER BY op_id DESC LIMIT 1
+  ) WHERE data IS NOT NULL",
+        );
+        storage.write_upsert_clause(&mut restore);
+        let restore = db.prepare_v2(&restore.sql)?;
+
+        // The latest entry may have NULL data, in which case the row is only removed.
+        // language=SQLite
+        let remove = db.prepare_v2(&format!(
+            "\
+DELETE FROM {table} WHERE id = ?2 AND (
+  SELECT data FROM ps_oplog WHERE row_type = ?1 AND row_id = ?2 ORDER BY op_id DESC LIMIT 1
+) IS NULL"
+        ))?;
+
+        for row in unapplied_rows(db, &row_type, &table, &storage)? {
+            restore.bind_text(1, &row_type, sqlite::Destructor::STATIC)?;
+            restore.bind_text(2, &row.id, sqlite::Destructor::STATIC)?;
+            match restore.exec() {
+                Ok(()) => {}
+                Err(e) if e.is_constraint_violation() => {
+                    result.conflicting_rows.push(row);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+
+            remove.bind_text(1, &row_type, sqlite::Destructor::STATIC)?;
+            remove.bind_text(2, &row.id, sqlite::Destructor::STATIC)?;
+            remove.exec()?;
+            result.restored_rows += 1;
+        }
+    }
+
+    // language=SQLite
+    let fix_checksum = db.prepare_v2(
+        "\
+UPDATE ps_buckets
+  SET op_checksum = (SELECT ifnull(sum(hash), 0) FROM ps_oplog WHERE bucket = ps_buckets.id) & 0xffffffff
+  WHERE name = ?",
+    )?;
+    for mismatch in checksum_mismatches(db)? {
+        fix_checksum.bind_text(1, &mismatch.bucket, sqlite::Destructor::STATIC)?;
+        fix_checksum.exec()?;
+        result.repaired_buckets += 1;
+    }
+
+    Ok(result)
+}
+
+extern "C" fn powersync_integrity_check(
+    ctx: *mut sqlite::context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite::value,
+) {
+    let result = (|| -> Result<String> {
+        let state = unsafe { DatabaseState::from_context(&ctx) };
+        let report = integrity_check(ctx.db_handle().into(), state)?;
+
+        #[derive(Serialize)]
+        struct Output {
+            ok: bool,
+            #[serde(flatten)]
+            report: IntegrityReport,
+        }
+
+        serde_json::to_string(&Output {
+            ok: report.is_ok(),
+            report,
+        })
+        .map_err(PowerSyncError::internal)
+    })();
+
+    match result {
+        Ok(report) => {
+            ctx.result_text_transient(&report);
+            ctx.result_subtype(SUBTYPE_JSON);
+        }
+        Err(e) => e.apply_to_ctx("powersync_integrity_check", ctx),
+    }
+}
+
+extern "C" fn powersync_repair(
+    ctx: *mut sqlite::context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite::value,
+) {
+    let result = (|| -> Result<String> {
+        let db = Database::from(ctx.db_handle());
+        verify_in_transaction(db)?;
+
+        let state = unsafe { DatabaseState::from_context(&ctx) };
+        let result = repair(db, state)?;
+        serde_json::to_string(&result).map_err(PowerSyncError::internal)
+    })();
+
+    match result {
+        Ok(result) => {
+            ctx.result_text_transient(&result);
+            ctx.result_subtype(SUBTYPE_JSON);
+        }
+        Err(e) => e.apply_to_ctx("powersync_repair", ctx),
+    }
+}
+
+pub fn register(
+    db: *mut sqlite::sqlite3,
+    state: Rc<DatabaseState>,
+) -> core::result::Result<(), ResultCode> {
+    db.create_function_v2(
+        "powersync_integrity_check",
+        0,
+        sqlite::UTF8 | sqlite::DIRECTONLY | SQLITE_RESULT_SUBTYPE,
+        Some(Rc::into_raw(state.clone()) as *mut c_void),
+        Some(powersync_integrity_check),
+        None,
+        None,
+        Some(DatabaseState::destroy_rc),
+    )?;
+
+    db.create_function_v2(
+        "powersync_repair",
+        0,
+        sqlite::UTF8 | sqlite::DIRECTONLY | SQLITE_RESULT_SUBTYPE,
+        Some(Rc::into_raw(state) as *mut c_void),
+        Some(powersync_repair),
+        None,
+        None,
+        Some(DatabaseState::destroy_rc),
+    )?;
+
+    Ok(())
+}
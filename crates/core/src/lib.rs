@@ -14,16 +14,19 @@ use crate::{
 };
 
 mod bson;
+mod compact_crud;
 mod constants;
 mod crud_vtab;
 mod diff;
 mod error;
 mod fix_data;
+mod integrity;
 mod json_util;
 mod kv;
 mod macros;
 mod migrations;
 mod pre_close_vtab;
+mod query_tables;
 mod schema;
 mod state;
 mod sync;
@@ -71,9 +74,12 @@ fn init_extension(db: Database) -> Result<()> {
         crate::version::register(db)?;
         crate::uuid::register(db)?;
         crate::diff::register(db)?;
+        crate::compact_crud::register(db)?;
         crate::fix_data::register(db)?;
         crate::json_util::register(db)?;
         crate::view_admin::register(db, state.clone())?;
+        crate::query_tables::register(db, state.clone())?;
+        crate::integrity::register(db, state.clone())?;
         crate::kv::register(db)?;
         crate::state::register(db, state.clone())?;
         sync::register(db, state.clone())?;
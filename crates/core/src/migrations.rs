@@ -16,7 +16,7 @@ use crate::schema::inspection::ExistingView;
 use crate::sync::BucketPriority;
 use crate::utils::database::Database;
 
-pub const LATEST_VERSION: i32 = 14;
+pub const LATEST_VERSION: i32 = 17;
 
 pub fn powersync_migrate(ctx: *mut sqlite::context, target_version: i32) -> Result<()> {
     let local_db = Database::from(ctx.db_handle());
@@ -576,6 +576,49 @@ ON CONFLICT(name) DO UPDATE SET
         track_migration.exec()?;
     }
 
+    if current_version < 15 && target_version >= 15 {
+        let stmt = c"\
+CREATE TABLE ps_sync_history (
+  id INTEGER NOT NULL PRIMARY KEY,
+  started_at INTEGER NOT NULL,
+  ended_at INTEGER,
+  close_reason TEXT,
+  error TEXT,
+  downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+  downloaded_operations INTEGER NOT NULL DEFAULT 0,
+  completed_checkpoints INTEGER NOT NULL DEFAULT 0
+) STRICT;
+
+INSERT INTO ps_migration(id, down_migrations) VALUES(15, json_array(
+json_object('sql', 'DROP TABLE ps_sync_history'),
+json_object('sql', 'DELETE FROM ps_migration WHERE id >= 15')
+));
+";
+        local_db.exec_safe(stmt)?;
+    }
+
+    if current_version < 16 && target_version >= 16 {
+        let stmt = c"\
+ALTER TABLE ps_buckets ADD COLUMN priority INTEGER;
+INSERT INTO ps_migration(id, down_migrations) VALUES(16, json_array(
+json_object('sql', 'ALTER TABLE ps_buckets DROP COLUMN priority'),
+json_object('sql', 'DELETE FROM ps_migration WHERE id >= 16')
+));
+";
+        local_db.exec_safe(stmt)?;
+    }
+
+    if current_version < 17 && target_version >= 17 {
+        let stmt = c"\
+ALTER TABLE ps_updated_rows ADD COLUMN local_write INTEGER NOT NULL DEFAULT 0;
+INSERT INTO ps_migration(id, down_migrations) VALUES(17, json_array(
+json_object('sql', 'ALTER TABLE ps_updated_rows DROP COLUMN local_write'),
+json_object('sql', 'DELETE FROM ps_migration WHERE id >= 17')
+));
+";
+        local_db.exec_safe(stmt)?;
+    }
+
     Ok(())
 }
 
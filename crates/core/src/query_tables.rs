@@ -0,0 +1,149 @@
+use core::ffi::{c_int, c_void};
+
+use alloc::collections::btree_set::BTreeSet;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use powersync_sqlite_nostd as sqlite;
+use powersync_sqlite_nostd::bindings::SQLITE_RESULT_SUBTYPE;
+use powersync_sqlite_nostd::{Connection, Context, Value};
+use sqlite::ResultCode;
+
+use crate::constants::SUBTYPE_JSON;
+use crate::error::{PowerSyncError, Result};
+use crate::schema::Schema;
+use crate::state::DatabaseState;
+use crate::utils::database::Database;
+
+/// Resolves the tables a SQL statement reads from, by inspecting its bytecode with the
+/// `tables_used` table-valued function (available when SQLite is compiled with
+/// `SQLITE_ENABLE_BYTECODE_VTAB`).
+///
+/// Since views are expanded when statements are compiled, reads from PowerSync views show up as
+/// reads from the underlying `ps_data__` or `ps_data_local__` tables. We translate those back to
+/// the name of the view. Other tables (like raw tables) are reported by their name, while internal
+/// PowerSync tables are omitted.
+fn query_tables(db: Database, schema: Option<&Schema>, sql: &str) -> Result<BTreeSet<String>> {
+    // Prepare the statement first, for more helpful errors if it's invalid.
+    db.prepare_v2(sql)?;
+
+    // Covering indexes can be read without opening the table, so we resolve indexes to their
+    // table as well.
+    // language=SQLite
+    let stmt = db
+        .prepare_v2(
+            "\
+SELECT DISTINCT
+  CASE used.type
+    WHEN 'index' THEN (SELECT tbl_name FROM sqlite_schema WHERE type = 'index' AND name = used.name)
+    ELSE used.name
+  END
+FROM tables_used(?) AS used
+WHERE used.schema = 'main' AND NOT used.wr",
+        )
+        .map_err(|e| e.context("Could not inspect statement".to_string()))?;
+    stmt.bind_text(1, sql, sqlite::Destructor::STATIC)?;
+
+    let mut tables = BTreeSet::new();
+    while stmt.step()? {
+        let Some(name) = stmt.column_nullable(0, || stmt.column_text(0))? else {
+            continue;
+        };
+
+        if let Some(name) = user_facing_name(schema, name) {
+            tables.insert(name);
+        }
+    }
+
+    Ok(tables)
+}
+
+/// Translates the name of a table in the database into the name users query.
+fn user_facing_name(schema: Option<&Schema>, table: &str) -> Option<String> {
+    let internal = table
+        .strip_prefix("ps_data__")
+        .or_else(|| table.strip_prefix("ps_data_local__"));
+
+    if let Some(name) = internal {
+        let view_name = schema
+            .and_then(|schema| schema.tables.iter().find(|t| t.name == name))
+            .map(|t| t.view_name())
+            .unwrap_or(name);
+        Some(view_name.to_string())
+    } else if table.starts_with("ps_") || table.starts_with("sqlite_") {
+        None
+    } else {
+        Some(table.to_string())
+    }
+}
+
+extern "C" fn powersync_query_tables(
+    ctx: *mut sqlite::context,
+    argc: c_int,
+    argv: *mut *mut sqlite::value,
+) {
+    let args = sqlite::args!(argc, argv);
+    let result = (|| -> Result<String> {
+        let state = unsafe { DatabaseState::from_context(&ctx) };
+        let schema = state.view_schema();
+        let tables = query_tables(ctx.db_handle().into(), schema.as_deref(), args[0].text())?;
+        serde_json::to_string(&tables).map_err(PowerSyncError::internal)
+    })();
+
+    match result {
+        Ok(tables) => {
+            ctx.result_text_transient(&tables);
+            ctx.result_subtype(SUBTYPE_JSON);
+        }
+        Err(e) => e.apply_to_ctx("powersync_query_tables", ctx),
+    }
+}
+
+pub fn register(
+    db: *mut sqlite::sqlite3,
+    state: Rc<DatabaseState>,
+) -> core::result::Result<(), ResultCode> {
+    db.create_function_v2(
+        "powersync_query_tables",
+        1,
+        sqlite::UTF8 | sqlite::DIRECTONLY | SQLITE_RESULT_SUBTYPE,
+        Some(Rc::into_raw(state) as *mut c_void),
+        Some(powersync_query_tables),
+        None,
+        None,
+        Some(DatabaseState::destroy_rc),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn translates_names() {
+        assert_eq!(
+            user_facing_name(None, "ps_data__users").as_deref(),
+            Some("users")
+        );
+        assert_eq!(
+            user_facing_name(None, "ps_data_local__drafts").as_deref(),
+            Some("drafts")
+        );
+        assert_eq!(
+            user_facing_name(None, "raw_users").as_deref(),
+            Some("raw_users")
+        );
+        assert_eq!(user_facing_name(None, "ps_oplog"), None);
+        assert_eq!(user_facing_name(None, "sqlite_sequence"), None);
+
+        let schema: Schema = serde_json::from_str(
+            r#"{"tables":[{"name":"users","view_name":"people","columns":[]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            user_facing_name(Some(&schema), "ps_data__users").as_deref(),
+            Some("people")
+        );
+    }
+}
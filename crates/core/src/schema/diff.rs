@@ -0,0 +1,313 @@
+use core::ffi::c_int;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use powersync_sqlite_nostd as sqlite;
+use powersync_sqlite_nostd::bindings::SQLITE_RESULT_SUBTYPE;
+use powersync_sqlite_nostd::{Connection, Context, Value, args};
+use serde::Serialize;
+use sqlite::ResultCode;
+
+use crate::constants::SUBTYPE_JSON;
+use crate::error::{PowerSyncError, Result};
+use crate::schema::Schema;
+use crate::schema::inspection::{ExistingTable, ExistingView};
+use crate::schema::management::{TableAction, TablePlan, index_changes, wanted_view};
+use crate::utils::SqlBuffer;
+use crate::utils::database::Database;
+
+/// The result of `powersync_schema_diff()`: Changes that `powersync_replace_schema()` would apply
+/// for a schema.
+#[derive(Serialize, Default, Debug)]
+struct SchemaDiff {
+    tables: Vec<TableDiff>,
+    views: Vec<ViewDiff>,
+    indexes: Vec<IndexDiff>,
+}
+
+#[derive(Serialize, Debug)]
+struct TableDiff {
+    /// The name of the table in the schema (or the name of a dropped table).
+    name: String,
+    /// For tables taking over an existing table with one of their `previous_names`, the name of
+    /// that table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_name: Option<String>,
+    action: TableDiffAction,
+    /// The amount of rows in the existing internal table.
+    rows: i64,
+    /// The amount of rows moved from `ps_untyped` into the new table.
+    untyped_rows: i64,
+    /// Whether `rows` are deleted, as opposed to being moved into the new table or `ps_untyped`.
+    deletes_rows: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum TableDiffAction {
+    Create,
+    Rename,
+    Drop,
+    ConvertToLocalOnly,
+    ConvertToSynced,
+    ChangeStorage,
+}
+
+#[derive(Serialize, Debug)]
+struct ViewDiff {
+    name: String,
+    action: ViewDiffAction,
+    /// For re-created views, the parts with a changed definition (`view`, `insert_trigger`,
+    /// `update_trigger` or `delete_trigger`). Views might also be re-created without changes when
+    /// internal tables are migrated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changed: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ViewDiffAction {
+    Create,
+    Recreate,
+    Drop,
+}
+
+#[derive(Serialize, Debug)]
+struct IndexDiff {
+    name: String,
+    action: IndexDiffAction,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum IndexDiffAction {
+    Create,
+    Recreate,
+    Drop,
+}
+
+fn count_rows(db: Database, internal_name: &str) -> Result<i64> {
+    let stmt = db.prepare_v2(&format!(
+        "SELECT count(*) FROM {}",
+        SqlBuffer::quote_identifier(internal_name)
+    ))?;
+    stmt.step()?;
+    Ok(stmt.column_int64(0))
+}
+
+fn count_untyped_rows(db: Database, name: &str) -> Result<i64> {
+    // language=SQLite
+    let stmt = db.prepare_v2("SELECT count(*) FROM ps_untyped WHERE type = ?")?;
+    stmt.bind_text(1, name, sqlite::Destructor::STATIC)?;
+    stmt.step()?;
+    Ok(stmt.column_int64(0))
+}
+
+fn table_diff(db: Database, plan: &TablePlan) -> Result<Vec<TableDiff>> {
+    let mut diff = vec![];
+
+    for planned in &plan.tables {
+        let table = planned.table;
+        let untyped_rows = if table.local_only() {
+            0
+        } else {
+            count_untyped_rows(db, &table.name)?
+        };
+
+        let previous_name = planned.renamed_from.as_ref().and_then(|internal_name| {
+            ExistingTable::external_name(internal_name).map(|(name, _)| name.into())
+        });
+
+        match &planned.action {
+            TableAction::Keep => {
+                if let Some(renamed_from) = &planned.renamed_from {
+                    diff.push(TableDiff {
+                        name: table.name.clone(),
+                        previous_name,
+                        action: TableDiffAction::Rename,
+                        rows: count_rows(db, renamed_from)?,
+                        untyped_rows,
+                        deletes_rows: false,
+                    });
+                }
+            }
+            TableAction::Create => {
+                // If there's an existing table of the other kind, it's dropped as part of creating
+                // the new table.
+                let replaced = plan.dropped.iter().find(|t| t.name == table.name);
+                diff.push(match replaced {
+                    Some(replaced) => TableDiff {
+                        name: table.name.clone(),
+                        previous_name: None,
+                        action: if table.local_only() {
+                            TableDiffAction::ConvertToLocalOnly
+                        } else {
+                            TableDiffAction::ConvertToSynced
+                        },
+                        rows: count_rows(db, &replaced.internal_name)?,
+                        untyped_rows,
+                        deletes_rows: replaced.local_only,
+                    },
+                    None => TableDiff {
+                        name: table.name.clone(),
+                        previous_name: None,
+                        action: TableDiffAction::Create,
+                        rows: 0,
+                        untyped_rows,
+                        deletes_rows: false,
+                    },
+                });
+            }
+            TableAction::Migrate(existing) => diff.push(TableDiff {
+                name: table.name.clone(),
+                previous_name,
+                action: TableDiffAction::ChangeStorage,
+                rows: count_rows(db, &existing.internal_name)?,
+                untyped_rows: 0,
+                deletes_rows: false,
+            }),
+        }
+    }
+
+    for dropped in &plan.dropped {
+        let is_converted = diff.iter().any(|t| {
+            t.name == dropped.name
+                && matches!(
+                    t.action,
+                    TableDiffAction::ConvertToLocalOnly | TableDiffAction::ConvertToSynced
+                )
+        });
+        if is_converted {
+            continue;
+        }
+
+        diff.push(TableDiff {
+            name: dropped.name.clone(),
+            previous_name: None,
+            action: TableDiffAction::Drop,
+            rows: count_rows(db, &dropped.internal_name)?,
+            untyped_rows: 0,
+            // Rows of synced tables are moved into ps_untyped.
+            deletes_rows: dropped.local_only,
+        });
+    }
+
+    Ok(diff)
+}
+
+fn view_diff(db: Database, schema: &Schema, plan: &TablePlan) -> Result<Vec<ViewDiff>> {
+    let existing = ExistingView::list(db)?;
+    let mut existing = {
+        let mut map = BTreeMap::new();
+        for entry in &existing {
+            map.insert(&*entry.name, entry);
+        }
+        map
+    };
+
+    let mut diff = vec![];
+    for table in &schema.tables {
+        let wanted = wanted_view(table)?;
+
+        let Some(actual) = existing.remove(table.view_name()) else {
+            diff.push(ViewDiff {
+                name: wanted.name,
+                action: ViewDiffAction::Create,
+                changed: vec![],
+            });
+            continue;
+        };
+
+        let mut changed = vec![];
+        if actual.sql != wanted.sql {
+            changed.push("view");
+        }
+        if actual.insert_trigger_sql != wanted.insert_trigger_sql {
+            changed.push("insert_trigger");
+        }
+        if actual.update_trigger_sql != wanted.update_trigger_sql {
+            changed.push("update_trigger");
+        }
+        if actual.delete_trigger_sql != wanted.delete_trigger_sql {
+            changed.push("delete_trigger");
+        }
+
+        if !changed.is_empty() || plan.drops_views() {
+            diff.push(ViewDiff {
+                name: wanted.name,
+                action: ViewDiffAction::Recreate,
+                changed,
+            });
+        }
+    }
+
+    for remaining in existing.values() {
+        diff.push(ViewDiff {
+            name: remaining.name.clone(),
+            action: ViewDiffAction::Drop,
+            changed: vec![],
+        });
+    }
+
+    Ok(diff)
+}
+
+fn schema_diff(db: Database, schema: &Schema) -> Result<SchemaDiff> {
+    let plan = TablePlan::compute(db, schema)?;
+
+    Ok(SchemaDiff {
+        tables: table_diff(db, &plan)?,
+        views: view_diff(db, schema, &plan)?,
+        indexes: index_changes(db, schema, Some(&plan))?
+            .into_iter()
+            .map(|change| IndexDiff {
+                name: change.name,
+                action: match (change.drop, change.create.is_some()) {
+                    (true, true) => IndexDiffAction::Recreate,
+                    (true, false) => IndexDiffAction::Drop,
+                    _ => IndexDiffAction::Create,
+                },
+            })
+            .collect(),
+    })
+}
+
+extern "C" fn powersync_schema_diff(
+    ctx: *mut sqlite::context,
+    argc: c_int,
+    argv: *mut *mut sqlite::value,
+) {
+    let args = args!(argc, argv);
+    let result = (|| -> Result<String> {
+        let schema = serde_json::from_str::<Schema>(args[0].text())
+            .map_err(PowerSyncError::as_argument_error)?;
+        let diff = schema_diff(ctx.db_handle().into(), &schema)?;
+
+        serde_json::to_string(&diff).map_err(PowerSyncError::internal)
+    })();
+
+    match result {
+        Ok(diff) => {
+            ctx.result_text_transient(&diff);
+            ctx.result_subtype(SUBTYPE_JSON);
+        }
+        Err(e) => e.apply_to_ctx("powersync_schema_diff", ctx),
+    }
+}
+
+pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode> {
+    db.create_function_v2(
+        "powersync_schema_diff",
+        1,
+        sqlite::UTF8 | sqlite::DIRECTONLY | SQLITE_RESULT_SUBTYPE,
+        None,
+        Some(powersync_schema_diff),
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(())
+}
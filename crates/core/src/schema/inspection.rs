@@ -3,6 +3,7 @@ use alloc::{format, vec};
 use alloc::{string::String, vec::Vec};
 
 use crate::error::Result;
+use crate::schema::storage::TableStorage;
 use crate::utils::SqlBuffer;
 use crate::utils::database::Database;
 
@@ -83,27 +84,35 @@ pub struct ExistingTable {
     pub name: String,
     pub internal_name: String,
     pub local_only: bool,
+    pub storage: TableStorage,
 }
 
 impl ExistingTable {
     pub fn list(db: Database) -> Result<Vec<Self>> {
-        let mut results = vec![];
-        let stmt = db.prepare_v2(
-            "
+        let mut names = vec![];
+        {
+            let stmt = db.prepare_v2(
+                "
 SELECT name FROM sqlite_master WHERE type = 'table' AND name GLOB 'ps_data_*';
         ",
-        )?;
+            )?;
 
-        while stmt.step()? {
-            let internal_name = stmt.column_text(0)?;
-            let Some((name, local_only)) = Self::external_name(internal_name) else {
+            while stmt.step()? {
+                names.push(stmt.column_text(0)?.to_owned());
+            }
+        }
+
+        let mut results = vec![];
+        for internal_name in names {
+            let Some((name, local_only)) = Self::external_name(&internal_name) else {
                 continue;
             };
 
             results.push(ExistingTable {
-                internal_name: internal_name.to_owned(),
                 name: name.to_owned(),
                 local_only: local_only,
+                storage: TableStorage::read_from_database(db, &internal_name)?,
+                internal_name,
             });
         }
 
@@ -0,0 +1,170 @@
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use serde::{Deserialize, de::Error};
+use serde_json::Value;
+
+use crate::error::PowerSyncError;
+
+/// A path into nested JSON data, like `$.address.city` or `$.tags[0]`.
+///
+/// This supports the subset of SQLite's JSON path syntax consisting of object keys (optionally
+/// quoted) and array indices.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, PowerSyncError> {
+        let invalid = || PowerSyncError::argument_error(format!("Invalid JSON path: {path}"));
+
+        let Some(mut remaining) = path.strip_prefix('$') else {
+            return Err(invalid());
+        };
+        let mut segments = Vec::new();
+
+        while let Some(next) = remaining.chars().next() {
+            match next {
+                '.' => {
+                    let key = &remaining[1..];
+                    if let Some(quoted) = key.strip_prefix('"') {
+                        let end = quoted.find('"').ok_or_else(invalid)?;
+                        segments.push(PathSegment::Key(quoted[..end].to_string()));
+                        remaining = &quoted[end + 1..];
+                    } else {
+                        let end = key.find(['.', '[']).unwrap_or(key.len());
+                        if end == 0 {
+                            return Err(invalid());
+                        }
+
+                        segments.push(PathSegment::Key(key[..end].to_string()));
+                        remaining = &key[end..];
+                    }
+                }
+                '[' => {
+                    let end = remaining.find(']').ok_or_else(invalid)?;
+                    let index = remaining[1..end].parse().map_err(|_| invalid())?;
+                    segments.push(PathSegment::Index(index));
+                    remaining = &remaining[end + 1..];
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// The top-level key of the row referenced by this path, if any.
+    pub fn top_level_key(&self) -> Option<&str> {
+        match self.segments.first() {
+            Some(PathSegment::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Resolves this path in the given object, returning `None` if it doesn't exist.
+    ///
+    /// Since nested data is typically synced as JSON-encoded text, string values are decoded when
+    /// the path continues into them.
+    pub fn resolve<'a>(
+        &self,
+        object: &'a serde_json::Map<String, Value>,
+    ) -> Option<Cow<'a, Value>> {
+        let mut segments = self.segments.iter();
+        let mut current = match segments.next() {
+            None => return None,
+            Some(PathSegment::Key(key)) => Cow::Borrowed(object.get(key)?),
+            Some(PathSegment::Index(_)) => return None,
+        };
+
+        for segment in segments {
+            if let Value::String(encoded) = current.as_ref() {
+                current = Cow::Owned(serde_json::from_str(encoded).ok()?);
+            }
+
+            current = match current {
+                Cow::Borrowed(value) => Cow::Borrowed(segment.select(value)?),
+                Cow::Owned(value) => Cow::Owned(segment.select(&value)?.clone()),
+            };
+        }
+
+        Some(current)
+    }
+}
+
+impl PathSegment {
+    fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        match (self, value) {
+            (PathSegment::Key(key), Value::Object(object)) => object.get(key),
+            (PathSegment::Index(index), Value::Array(array)) => array.get(*index),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let path = <Cow<'de, str>>::deserialize(deserializer)?;
+        Self::parse(&path).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolve(path: &str, value: Value) -> Option<Value> {
+        let Value::Object(object) = value else {
+            unreachable!()
+        };
+
+        JsonPath::parse(path)
+            .unwrap()
+            .resolve(&object)
+            .map(Cow::into_owned)
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert!(JsonPath::parse("$").unwrap().segments.is_empty());
+        assert_eq!(
+            JsonPath::parse("$.a.\"b.c\"[2]").unwrap().segments,
+            [
+                PathSegment::Key("a".to_string()),
+                PathSegment::Key("b.c".to_string()),
+                PathSegment::Index(2)
+            ]
+        );
+
+        for invalid in ["", "a", "$.", "$..a", "$[a]", "$[1", "$.\"a", "$a"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn resolves_paths() {
+        let row = json!({"a": {"b": [1, 2]}, "encoded": "{\"c\": true}"});
+
+        assert_eq!(resolve("$.a.b[1]", row.clone()), Some(json!(2)));
+        assert_eq!(resolve("$.a", row.clone()), Some(json!({"b": [1, 2]})));
+        assert_eq!(resolve("$.encoded.c", row.clone()), Some(json!(true)));
+        assert_eq!(resolve("$.a.b[2]", row.clone()), None);
+        assert_eq!(resolve("$.missing", row.clone()), None);
+        assert_eq!(resolve("$", row), None);
+    }
+}
@@ -16,7 +16,10 @@ use sqlite::{Connection, ResultCode, Value};
 use crate::create_sqlite_text_fn;
 use crate::error::{PowerSyncError, Result};
 use crate::schema::inspection::{ExistingTable, ExistingView};
-use crate::schema::table_info::Index;
+use crate::schema::raw_table::InferredTableStructure;
+use crate::schema::storage::TableStorage;
+use crate::schema::stored::NormalizedSchema;
+use crate::schema::table_info::{Column, Index, Table};
 use crate::state::DatabaseState;
 use crate::utils::database::Database;
 use crate::utils::{SqlBuffer, verify_in_transaction};
@@ -27,88 +30,302 @@ use crate::views::{
 
 use super::Schema;
 
-fn update_tables(db: Database, schema: &Schema) -> Result<()> {
-    let existing_tables = ExistingTable::list(db)?;
-    let mut existing_tables = {
-        let mut map = BTreeMap::new();
-        for table in &existing_tables {
-            map.insert(&*table.name, table);
+/// How [update_tables] changes the internal table of a table in the schema.
+pub(super) enum TableAction {
+    /// A compatible table exists already.
+    Keep,
+    /// The internal table is created, moving rows from `ps_untyped` for synced tables.
+    Create,
+    /// The existing table is migrated into a different storage layout.
+    Migrate(ExistingTable),
+}
+
+pub(super) struct PlannedTable<'a> {
+    pub table: &'a Table,
+    pub storage: TableStorage,
+    /// The internal name of an existing table with one of the [Table::previous_names], which is
+    /// renamed before applying [Self::action].
+    pub renamed_from: Option<String>,
+    pub action: TableAction,
+}
+
+/// The changes to internal tables needed to apply a schema.
+pub(super) struct TablePlan<'a> {
+    pub tables: Vec<PlannedTable<'a>>,
+    /// Existing tables to drop. Rows of synced tables are moved into `ps_untyped` first.
+    pub dropped: Vec<ExistingTable>,
+}
+
+impl<'a> TablePlan<'a> {
+    pub fn compute(db: Database, schema: &'a Schema) -> Result<Self> {
+        let mut existing_tables = BTreeMap::new();
+        for table in ExistingTable::list(db)? {
+            existing_tables.insert(table.name.clone(), table);
         }
-        map
-    };
 
-    for table in &schema.tables {
-        if let Some(existing) = existing_tables.remove(&*table.name) {
-            if existing.local_only != table.local_only() {
-                // Migrating between local-only and synced tables. This works by deleting
-                // existing and re-creating the table from scratch. We can re-create first and
-                // delete the old table afterwards because they have a different name
-                // (local-only tables have a ps_data_local prefix).
-
-                // To delete the old existing table in the end.
-                existing_tables.insert(&existing.name, existing);
-            } else {
-                // Compatible table exists already, nothing to do.
-                continue;
+        let mut existing_for_tables: Vec<Option<ExistingTable>> = schema
+            .tables
+            .iter()
+            .map(|table| existing_tables.remove(&table.name))
+            .collect();
+        // Tables without an existing table of the same name can take over a table with one of
+        // their previous names. We only do this after matching exact names, so that a table can
+        // re-use the name another table had previously.
+        for (table, existing) in schema.tables.iter().zip(&mut existing_for_tables) {
+            if existing.is_none() {
+                *existing = table.previous_names.iter().find_map(|previous| {
+                    if existing_tables.get(previous)?.local_only == table.local_only() {
+                        existing_tables.remove(previous)
+                    } else {
+                        None
+                    }
+                });
             }
         }
 
-        // New table.
-        let quoted_internal_name = SqlBuffer::quote_identifier(&table.internal_name());
-
-        db.exec_safe_str(&format!(
-            "CREATE TABLE {:}(id TEXT PRIMARY KEY NOT NULL, data TEXT)",
-            quoted_internal_name
-        ))?;
-
-        if !table.local_only() {
-            // MOVE data if any
-            db.exec_text(
-                &format!(
-                    "INSERT INTO {:}(id, data)
-    SELECT id, data
-    FROM ps_untyped
-    WHERE type = ?",
-                    quoted_internal_name
-                ),
-                &table.name,
-            )?;
-
-            // language=SQLite
-            db.exec_text("DELETE FROM ps_untyped WHERE type = ?", &table.name)?;
+        let mut tables = vec![];
+        let mut dropped = vec![];
+        for (table, existing) in schema.tables.iter().zip(existing_for_tables) {
+            TableStorage::validate(table)?;
+            let storage = TableStorage::for_table(table);
+            let renamed_from = existing
+                .as_ref()
+                .filter(|existing| existing.name != table.name)
+                .map(|existing| existing.internal_name.clone());
+
+            let action = match existing {
+                Some(existing) if existing.local_only != table.local_only() => {
+                    // Migrating between local-only and synced tables. This works by deleting
+                    // existing and re-creating the table from scratch. We can re-create first and
+                    // delete the old table afterwards because they have a different name
+                    // (local-only tables have a ps_data_local prefix).
+                    dropped.push(existing);
+                    TableAction::Create
+                }
+                Some(existing) if existing.storage != storage => TableAction::Migrate(existing),
+                // Compatible table exists already, nothing to do.
+                Some(_) => TableAction::Keep,
+                None => TableAction::Create,
+            };
+
+            tables.push(PlannedTable {
+                table,
+                storage,
+                renamed_from,
+                action,
+            });
         }
+
+        // Remaining tables are no longer part of the schema.
+        dropped.extend(existing_tables.into_values());
+        Ok(Self { tables, dropped })
     }
 
-    // Remaining tables need to be dropped. But first, we want to move their contents to
-    // ps_untyped.
-    for remaining in existing_tables.values() {
-        if !remaining.local_only {
-            db.exec_text(
-                &format!(
-                    "INSERT INTO ps_untyped(type, id, data) SELECT ?, id, data FROM {:}",
-                    SqlBuffer::quote_identifier(&remaining.internal_name)
-                ),
-                &remaining.name,
-            )?;
+    /// Whether the internal table `internal_name` is replaced when applying this plan, which also
+    /// drops its indexes.
+    pub fn recreates(&self, internal_name: &str) -> bool {
+        self.dropped
+            .iter()
+            .any(|table| table.internal_name == internal_name)
+            || self.tables.iter().any(|planned| match &planned.action {
+                TableAction::Migrate(existing) => existing.internal_name == internal_name,
+                _ => false,
+            })
+    }
+
+    /// Whether applying this plan drops all PowerSync-managed views, see [Self::apply].
+    pub fn drops_views(&self) -> bool {
+        self.tables
+            .iter()
+            .any(|planned| matches!(planned.action, TableAction::Migrate(_)))
+    }
+
+    fn apply(&self, db: Database) -> Result<()> {
+        if self.drops_views() {
+            // Migrating between storage layouts renames tables, which fails while views reference
+            // missing tables. So we drop all views first (update_views will re-create them).
+            for view in ExistingView::list(db)? {
+                ExistingView::drop_by_name(db, &view.name)?;
+            }
+        }
+
+        for PlannedTable {
+            table,
+            storage,
+            renamed_from,
+            action,
+        } in &self.tables
+        {
+            let quoted_internal_name = SqlBuffer::quote_identifier(&table.internal_name());
+
+            if let Some(previous) = renamed_from {
+                db.exec_safe_str(&format!(
+                    "ALTER TABLE {} RENAME TO {quoted_internal_name}",
+                    SqlBuffer::quote_identifier(previous)
+                ))?;
+
+                if let Some((previous_name, false)) = ExistingTable::external_name(previous) {
+                    rename_row_type(db, previous_name, &table.name)?;
+                    // Rows synced under the new name before this table existed are in ps_untyped.
+                    move_untyped_rows(db, table, storage)?;
+                }
+            }
+
+            match action {
+                TableAction::Keep => {}
+                TableAction::Create => {
+                    db.exec_safe_str(&storage.create_table_stmt(&table.internal_name()))?;
+
+                    if !table.local_only() {
+                        move_untyped_rows(db, table, storage)?;
+                    }
+                }
+                TableAction::Migrate(existing) => {
+                    // We move the existing table out of the way, create the new layout and copy
+                    // rows over.
+                    let quoted_migration_name =
+                        SqlBuffer::quote_identifier(&format!("ps_migrate__{}", table.name));
+                    db.exec_safe_str(&format!(
+                        "ALTER TABLE {quoted_internal_name} RENAME TO {quoted_migration_name}"
+                    ))?;
+                    db.exec_safe_str(&storage.create_table_stmt(&table.internal_name()))?;
+
+                    let mut copy = SqlBuffer::new();
+                    let _ = write!(&mut copy, "INSERT INTO {quoted_internal_name}");
+                    storage.write_insert_from_json(&mut copy, "id", "data");
+                    copy.push_str(" FROM (SELECT id, ");
+                    existing
+                        .storage
+                        .write_row_to_json(&mut copy, &quoted_migration_name)?;
+                    let _ = write!(&mut copy, " AS data FROM {quoted_migration_name})");
+                    db.exec_safe_str(&copy.sql)?;
+
+                    db.exec_safe_str(&format!("DROP TABLE {quoted_migration_name}"))?;
+                }
+            }
+        }
+
+        // Dropped tables need their contents moved to ps_untyped first.
+        for remaining in &self.dropped {
+            if !remaining.local_only {
+                let quoted_internal_name = SqlBuffer::quote_identifier(&remaining.internal_name);
+                let mut sql = SqlBuffer::new();
+                sql.push_str("INSERT INTO ps_untyped(type, id, data) SELECT ?, id, ");
+                remaining
+                    .storage
+                    .write_row_to_json(&mut sql, &quoted_internal_name)?;
+                let _ = write!(&mut sql, " FROM {quoted_internal_name}");
+
+                db.exec_text(&sql.sql, &remaining.name)?;
+            }
+        }
+
+        // We cannot have any open queries on sqlite_master at the point that we drop tables,
+        // otherwise we get "table is locked" errors.
+        for remaining in &self.dropped {
+            let q = format!(
+                "DROP TABLE {:}",
+                SqlBuffer::quote_identifier(&remaining.internal_name)
+            );
+            db.exec_safe_str(&q)?;
         }
+
+        Ok(())
     }
+}
 
-    // We cannot have any open queries on sqlite_master at the point that we drop tables, otherwise
-    // we get "table is locked" errors.
-    for remaining in existing_tables.values() {
-        let q = format!(
-            "DROP TABLE {:}",
-            SqlBuffer::quote_identifier(&remaining.internal_name)
-        );
-        db.exec_safe_str(&q)?;
+/// Updates references to synced rows of type `from` so that they use the type `to`.
+///
+/// This includes oplog entries (so that later sync lines for the new type supersede them) and
+/// pending local writes, which are uploaded with the new type.
+fn rename_row_type(db: Database, from: &str, to: &str) -> Result<()> {
+    // language=SQLite
+    const STATEMENTS: [&str; 3] = [
+        "UPDATE ps_oplog SET row_type = ?2, key = ?2 || substr(key, length(?1) + 1) WHERE row_type = ?1",
+        // The same row might already be pending under the new name, so we can't update the
+        // primary key in place.
+        "INSERT INTO ps_updated_rows(row_type, row_id, local_write) SELECT ?2, row_id, local_write FROM ps_updated_rows WHERE row_type = ?1 ON CONFLICT DO UPDATE SET local_write = local_write OR excluded.local_write",
+        "UPDATE ps_crud SET data = json_set(data, '$.type', ?2) WHERE json_extract(data, '$.type') = ?1",
+    ];
+
+    for sql in STATEMENTS {
+        let stmt = db.prepare_v2(sql)?;
+        stmt.bind_text(1, from, sqlite::Destructor::STATIC)?;
+        stmt.bind_text(2, to, sqlite::Destructor::STATIC)?;
+        stmt.exec()?;
+    }
+
+    // language=SQLite
+    db.exec_text("DELETE FROM ps_updated_rows WHERE row_type = ?", from)?;
+    Ok(())
+}
+
+/// Moves rows of the given synced table from `ps_untyped` into its internal table.
+fn move_untyped_rows(db: Database, table: &Table, storage: &TableStorage) -> Result<()> {
+    // The table may already contain rows with the same id, e.g. when another table has been
+    // renamed to this name. Those rows are updated, but we don't resolve conflicts on other
+    // unique indexes: Such rows fail the schema update instead of deleting other rows.
+    let mut move_untyped = SqlBuffer::new();
+    let _ = write!(
+        &mut move_untyped,
+        "INSERT INTO {}",
+        SqlBuffer::quote_identifier(&table.internal_name())
+    );
+    storage.write_insert_from_json(&mut move_untyped, "id", "data");
+    move_untyped.push_str(" FROM ps_untyped WHERE type = ?");
+    storage.write_upsert_clause(&mut move_untyped);
+    db.exec_text(&move_untyped.sql, &table.name)?;
+
+    // language=SQLite
+    db.exec_text("DELETE FROM ps_untyped WHERE type = ?", &table.name)?;
+    Ok(())
+}
+
+fn update_tables(db: Database, schema: &Schema) -> Result<()> {
+    TablePlan::compute(db, schema)?.apply(db)
+}
+
+fn update_raw_tables(db: Database, schema: &Schema) -> Result<()> {
+    // language=SQLite
+    let table_exists =
+        db.prepare_v2("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?;
+
+    for table in &schema.raw_tables {
+        if !table.schema.add_missing_columns || table.put.is_some() {
+            continue;
+        }
+
+        // Raw tables are created by the app, which may do that after applying the schema. Columns
+        // are then added when syncing into the table.
+        let table_name = table.require_table_name()?;
+        table_exists.bind_text(1, table_name, sqlite::Destructor::STATIC)?;
+        let exists = table_exists.step()?;
+        table_exists.reset()?;
+        if !exists {
+            continue;
+        }
+
+        let mut structure =
+            InferredTableStructure::read_from_database(table_name, db, &table.schema)?;
+        structure.add_missing_columns_from_oplog(db, table)?;
     }
 
     Ok(())
 }
 
-fn create_index_stmt(table_name: &str, index_name: &str, index: &Index) -> String {
+fn create_index_stmt(
+    table_name: &str,
+    storage: &TableStorage,
+    columns: &[Column],
+    index_name: &str,
+    index: &Index,
+) -> String {
     let mut sql = SqlBuffer::new();
-    sql.push_str("CREATE INDEX ");
+    sql.push_str(if index.unique {
+        "CREATE UNIQUE INDEX "
+    } else {
+        "CREATE INDEX "
+    });
     let _ = sql.identifier().write_str(&index_name);
     sql.push_str(" ON ");
     let _ = sql.identifier().write_str(&table_name);
@@ -117,7 +334,22 @@ fn create_index_stmt(table_name: &str, index_name: &str, index: &Index) -> Strin
         let mut sql = sql.comma_separated();
         for indexed_column in &index.columns {
             let sql = sql.element();
-            sql.json_extract_and_cast("data", &indexed_column.name, &indexed_column.type_name);
+            if let Some(expression) = &indexed_column.expression {
+                sql.push_char('(');
+                rewrite_column_references(sql, expression, storage, columns);
+                sql.push_char(')');
+            } else {
+                storage.write_column_reference(
+                    sql,
+                    &indexed_column.name,
+                    &indexed_column.type_name,
+                );
+            }
+
+            if let Some(collate) = &indexed_column.collate {
+                sql.push_str(" COLLATE ");
+                let _ = sql.identifier().write_str(collate);
+            }
 
             if !indexed_column.ascending {
                 sql.push_str(" DESC");
@@ -126,57 +358,161 @@ fn create_index_stmt(table_name: &str, index_name: &str, index: &Index) -> Strin
     }
     sql.push_char(')');
 
+    if let Some(where_clause) = &index.where_clause {
+        sql.push_str(" WHERE ");
+        rewrite_column_references(&mut sql, where_clause, storage, columns);
+    }
+
     sql.sql
 }
 
-fn update_indexes(db: Database, schema: &Schema) -> Result<()> {
-    let mut statements: Vec<String> = alloc::vec![];
-    let mut expected_index_names: Vec<String> = vec![];
+/// Writes an SQL `expression` referencing columns of a PowerSync-managed table by name, rewriting
+/// those references into expressions on the internal table (`json_extract` calls for tables stored
+/// as JSON).
+///
+/// String literals, function names and other identifiers are copied without changes.
+fn rewrite_column_references(
+    sql: &mut SqlBuffer,
+    expression: &str,
+    storage: &TableStorage,
+    columns: &[Column],
+) {
+    let bytes = expression.as_bytes();
+    let is_identifier_byte =
+        |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80;
+    // Finds the end of a literal or quoted identifier starting at `start`, treating doubled quotes
+    // as escapes.
+    let quoted_end = |start: usize| {
+        let quote = bytes[start];
+        let mut i = start + 1;
+        while i < bytes.len() {
+            if bytes[i] == quote {
+                if bytes.get(i + 1) == Some(&quote) {
+                    i += 2;
+                    continue;
+                }
+                return i + 1;
+            }
+            i += 1;
+        }
+        bytes.len()
+    };
 
-    {
-        // In a block so that the statement is finalized before dropping indexes
-        // language=SQLite
-        let find_index =
-            db.prepare_v2("SELECT sql FROM sqlite_master WHERE name = ? AND type = 'index'")?;
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let name = match bytes[i] {
+            b'\'' => {
+                i = quoted_end(start);
+                None
+            }
+            b'"' => {
+                i = quoted_end(start);
+                let inner = &expression[start + 1..(i - 1).max(start + 1)];
+                Some(inner.replace("\"\"", "\""))
+            }
+            b if b.is_ascii_digit() => {
+                // Numbers, including ones like 1e5 or 0x1F that would otherwise look like
+                // identifiers.
+                while i < bytes.len() && (is_identifier_byte(bytes[i]) || bytes[i] == b'.') {
+                    i += 1;
+                }
+                None
+            }
+            b if is_identifier_byte(b) => {
+                while i < bytes.len() && is_identifier_byte(bytes[i]) {
+                    i += 1;
+                }
+                Some(expression[start..i].to_owned())
+            }
+            _ => {
+                i += 1;
+                None
+            }
+        };
 
-        for table in &schema.tables {
-            let table_name = table.internal_name();
+        let is_function_call = expression[i..].trim_start().starts_with('(');
+        let column = name.filter(|_| !is_function_call).and_then(|name| {
+            columns
+                .iter()
+                .find(|column| column.name.eq_ignore_ascii_case(&name))
+        });
 
-            for index in &table.indexes {
-                let index_name = format!("{}__{}", table_name, &index.name);
+        match column {
+            Some(column) => storage.write_column_reference(sql, &column.name, &column.type_name),
+            None => sql.push_str(&expression[start..i]),
+        }
+    }
+}
 
-                let existing_sql = {
-                    find_index.reset()?;
-                    find_index.bind_text(1, &index_name, sqlite::Destructor::STATIC)?;
+/// A change to an index on an internal table.
+pub(super) struct IndexChange {
+    pub name: String,
+    /// Whether an existing index with this name is dropped.
+    pub drop: bool,
+    /// The `CREATE INDEX` statement, if the index is (re-)created.
+    pub create: Option<String>,
+}
 
-                    let result = if find_index.step()? {
-                        Some(find_index.column_text(0)?)
-                    } else {
-                        None
-                    };
+/// Finds indexes to create or drop for the current state of internal tables.
+///
+/// When `tables` is set, indexes on tables that would be re-created by that plan are reported as
+/// dropped.
+pub(super) fn index_changes(
+    db: Database,
+    schema: &Schema,
+    tables: Option<&TablePlan>,
+) -> Result<Vec<IndexChange>> {
+    let mut changes: Vec<IndexChange> = alloc::vec![];
+    let mut expected_index_names: Vec<String> = vec![];
 
-                    result
-                };
+    // language=SQLite
+    let find_index =
+        db.prepare_v2("SELECT sql FROM sqlite_master WHERE name = ? AND type = 'index'")?;
 
-                let sql = create_index_stmt(&table_name, &index_name, index);
-                if existing_sql.is_none() {
-                    statements.push(sql);
-                } else if existing_sql != Some(&sql) {
-                    statements.push(format!(
-                        "DROP INDEX {}",
-                        SqlBuffer::quote_identifier(&index_name)
-                    ));
-                    statements.push(sql);
-                }
+    for table in &schema.tables {
+        let table_name = table.internal_name();
+        let storage = TableStorage::for_table(table);
+        let recreated = tables.is_some_and(|plan| plan.recreates(&table_name));
+
+        for index in &table.indexes {
+            let index_name = format!("{}__{}", table_name, &index.name);
 
-                expected_index_names.push(index_name);
+            let existing_sql = {
+                find_index.reset()?;
+                find_index.bind_text(1, &index_name, sqlite::Destructor::STATIC)?;
+
+                let result = if find_index.step()? {
+                    Some(find_index.column_text(0)?)
+                } else {
+                    None
+                };
+
+                result
+            };
+
+            let sql = create_index_stmt(&table_name, &storage, &table.columns, &index_name, index);
+            if existing_sql.is_none() {
+                changes.push(IndexChange {
+                    name: index_name.clone(),
+                    drop: false,
+                    create: Some(sql),
+                });
+            } else if recreated || existing_sql != Some(&sql) {
+                changes.push(IndexChange {
+                    name: index_name.clone(),
+                    drop: true,
+                    create: Some(sql),
+                });
             }
+
+            expected_index_names.push(index_name);
         }
+    }
 
-        // In a block so that the statement is finalized before dropping indexes
-        // language=SQLite
-        let statement = db.prepare_v2(
-            "\
+    // language=SQLite
+    let statement = db.prepare_v2(
+        "\
 SELECT
     sqlite_master.name as index_name
       FROM sqlite_master
@@ -184,27 +520,53 @@ SELECT
             AND sqlite_master.name GLOB 'ps_data_*'
             AND sqlite_master.name NOT IN (SELECT value FROM json_each(?))
 ",
-        )?;
-        let json_names = serde_json::to_string(&expected_index_names)
-            .map_err(PowerSyncError::as_argument_error)?;
-        statement.bind_text(1, &json_names, sqlite::Destructor::STATIC)?;
+    )?;
+    let json_names =
+        serde_json::to_string(&expected_index_names).map_err(PowerSyncError::as_argument_error)?;
+    statement.bind_text(1, &json_names, sqlite::Destructor::STATIC)?;
+
+    while statement.step()? {
+        changes.push(IndexChange {
+            name: statement.column_text(0)?.to_owned(),
+            drop: true,
+            create: None,
+        });
+    }
 
-        while statement.step()? {
-            let name = statement.column_text(0)?;
+    Ok(changes)
+}
 
-            statements.push(format!("DROP INDEX {}", SqlBuffer::quote_identifier(name)));
+fn update_indexes(db: Database, schema: &Schema) -> Result<()> {
+    // The statements used in index_changes are finalized after it returns. We cannot have any open
+    // queries on sqlite_master at the point that we drop indexes, otherwise we get
+    // "database table is locked (code 6)" errors.
+    for change in index_changes(db, schema, None)? {
+        if change.drop {
+            db.exec_safe_str(&format!(
+                "DROP INDEX {}",
+                SqlBuffer::quote_identifier(&change.name)
+            ))?;
         }
-    }
 
-    // We cannot have any open queries on sqlite_master at the point that we drop indexes, otherwise
-    // we get "database table is locked (code 6)" errors.
-    for statement in &statements {
-        db.exec_safe_str(statement)?;
+        if let Some(create) = &change.create {
+            db.exec_safe_str(create)?;
+        }
     }
 
     Ok(())
 }
 
+/// The view and triggers [update_views] creates for a table.
+pub(super) fn wanted_view(table: &Table) -> Result<ExistingView> {
+    Ok(ExistingView {
+        name: table.view_name().to_owned(),
+        sql: powersync_view_sql(table),
+        delete_trigger_sql: powersync_trigger_delete_sql(table)?,
+        insert_trigger_sql: powersync_trigger_insert_sql(table)?,
+        update_trigger_sql: powersync_trigger_update_sql(table)?,
+    })
+}
+
 fn update_views(db: Database, schema: &Schema) -> Result<()> {
     // First, find all existing views and index them by name.
     let existing = ExistingView::list(db)?;
@@ -217,18 +579,7 @@ fn update_views(db: Database, schema: &Schema) -> Result<()> {
     };
 
     for table in &schema.tables {
-        let view_sql = powersync_view_sql(table);
-        let delete_trigger_sql = powersync_trigger_delete_sql(table)?;
-        let insert_trigger_sql = powersync_trigger_insert_sql(table)?;
-        let update_trigger_sql = powersync_trigger_update_sql(table)?;
-
-        let wanted_view = ExistingView {
-            name: table.view_name().to_owned(),
-            sql: view_sql,
-            delete_trigger_sql,
-            insert_trigger_sql,
-            update_trigger_sql,
-        };
+        let wanted_view = wanted_view(table)?;
 
         if let Some(actual_view) = existing.remove(table.view_name()) {
             if *actual_view == wanted_view {
@@ -269,8 +620,10 @@ fn powersync_replace_schema_impl(
     db.exec_safe(c"SELECT powersync_init()")?;
 
     update_tables(db, &parsed_schema)?;
+    update_raw_tables(db, &parsed_schema)?;
     update_indexes(db, &parsed_schema)?;
     update_views(db, &parsed_schema)?;
+    NormalizedSchema::from_json(schema)?.store(db)?;
 
     state.set_schema(parsed_schema);
     Ok(String::from(""))
@@ -304,35 +657,112 @@ pub fn register(
 mod test {
     use alloc::{string::ToString, vec};
 
-    use crate::schema::table_info::{Index, IndexedColumn};
+    use crate::schema::storage::TableStorage;
+    use crate::schema::table_info::{Column, Index, IndexedColumn};
 
     use super::create_index_stmt;
 
+    fn indexed_column(name: &str, ascending: bool, type_name: &str) -> IndexedColumn {
+        IndexedColumn {
+            name: name.to_string(),
+            ascending,
+            type_name: type_name.to_string(),
+            expression: None,
+            collate: None,
+        }
+    }
+
     #[test]
     fn test_create_index() {
         let stmt = create_index_stmt(
             "table",
+            &TableStorage::Json,
+            &[],
+            "index",
+            &Index {
+                name: "unused".to_string(),
+                columns: vec![
+                    indexed_column("a", true, "text"),
+                    indexed_column("b", false, "integer"),
+                ],
+                unique: false,
+                where_clause: None,
+            },
+        );
+
+        assert_eq!(
+            stmt,
+            r#"CREATE INDEX "index" ON "table"(CAST(json_extract(data, '$.a') as text), CAST(json_extract(data, '$.b') as integer) DESC)"#
+        )
+    }
+
+    #[test]
+    fn test_create_unique_partial_index() {
+        let columns = [
+            Column {
+                name: "email".to_string(),
+                type_name: "text".to_string(),
+            },
+            Column {
+                name: "archived".to_string(),
+                type_name: "integer".to_string(),
+            },
+        ];
+
+        let stmt = create_index_stmt(
+            "table",
+            &TableStorage::Json,
+            &columns,
             "index",
             &Index {
                 name: "unused".to_string(),
                 columns: vec![
                     IndexedColumn {
-                        name: "a".to_string(),
-                        ascending: true,
-                        type_name: "text".to_string(),
+                        collate: Some("NOCASE".to_string()),
+                        ..indexed_column("email", true, "text")
                     },
                     IndexedColumn {
-                        name: "b".to_string(),
-                        ascending: false,
-                        type_name: "integer".to_string(),
+                        expression: Some("lower(\"email\") || 'archived'".to_string()),
+                        ..indexed_column("", true, "")
                     },
                 ],
+                unique: true,
+                where_clause: Some("archived = 0 AND id != 'x'".to_string()),
             },
         );
 
         assert_eq!(
             stmt,
-            r#"CREATE INDEX "index" ON "table"(CAST(json_extract(data, '$.a') as text), CAST(json_extract(data, '$.b') as integer) DESC)"#
+            r#"CREATE UNIQUE INDEX "index" ON "table"(CAST(json_extract(data, '$.email') as text) COLLATE "NOCASE", (lower(CAST(json_extract(data, '$.email') as text)) || 'archived')) WHERE CAST(json_extract(data, '$.archived') as integer) = 0 AND id != 'x'"#
+        )
+    }
+
+    #[test]
+    fn test_create_index_on_typed_table() {
+        let columns = vec![Column {
+            name: "email".to_string(),
+            type_name: "text".to_string(),
+        }];
+
+        let stmt = create_index_stmt(
+            "table",
+            &TableStorage::Typed(columns.clone()),
+            &columns,
+            "index",
+            &Index {
+                name: "unused".to_string(),
+                columns: vec![
+                    indexed_column("email", true, "text"),
+                    indexed_column("other", true, "integer"),
+                ],
+                unique: false,
+                where_clause: Some("email IS NOT NULL".to_string()),
+            },
+        );
+
+        assert_eq!(
+            stmt,
+            r#"CREATE INDEX "index" ON "table"("email", CAST(json_extract(_rest, '$.other') as integer)) WHERE "email" IS NOT NULL"#
         )
     }
 }
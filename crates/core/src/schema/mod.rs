@@ -1,7 +1,11 @@
 mod common;
+mod diff;
 pub mod inspection;
+mod json_path;
 mod management;
 mod raw_table;
+pub mod storage;
+mod stored;
 mod table_info;
 
 use alloc::{rc::Rc, vec::Vec};
@@ -31,6 +35,8 @@ pub struct Schema {
 
 pub fn register(db: *mut sqlite::sqlite3, state: Rc<DatabaseState>) -> Result<(), ResultCode> {
     management::register(db, state)?;
+    diff::register(db)?;
+    stored::register(db)?;
 
     {
         fn create_trigger(
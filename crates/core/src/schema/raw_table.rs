@@ -15,13 +15,19 @@ use powersync_sqlite_nostd::Destructor;
 
 use crate::{
     error::{PowerSyncError, Result},
-    schema::{ColumnFilter, PendingStatement, PendingStatementValue, RawTable, SchemaTable},
+    schema::{
+        ColumnFilter, PendingStatement, PendingStatementValue, RawTable, SchemaTable,
+        table_info::RawTableSchema,
+    },
+    sync::ValueType,
     utils::{InsertIntoCrud, SqlBuffer, WriteType, database::Database},
     views::table_columns_to_json_object,
 };
 
 pub struct InferredTableStructure {
     pub name: String,
+    /// Columns forming the key of the table, or `None` if the table is keyed by its `id` column.
+    pub key_columns: Option<Vec<String>>,
     pub columns: Vec<String>,
 }
 
@@ -29,19 +35,22 @@ impl InferredTableStructure {
     pub fn read_from_database(
         table_name: &str,
         db: Database,
-        synced_columns: &Option<ColumnFilter>,
+        schema: &RawTableSchema,
     ) -> Result<Self> {
         let stmt = db.prepare_v2("select name from pragma_table_info(?)")?;
         stmt.bind_text(1, table_name, Destructor::STATIC)?;
 
-        let mut has_id_column = false;
+        let mut found_table = false;
+        let mut found_key_columns = 0usize;
         let mut columns = vec![];
 
         while stmt.step()? {
+            found_table = true;
+
             let name = stmt.column_text(0)?;
-            if name == "id" {
-                has_id_column = true;
-            } else if let Some(filter) = synced_columns
+            if is_key_column(&schema.local_key, name) {
+                found_key_columns += 1;
+            } else if let Some(filter) = &schema.synced_columns
                 && !filter.matches(name)
             {
                 // This column isn't part of the synced columns, skip.
@@ -50,57 +59,159 @@ impl InferredTableStructure {
             }
         }
 
-        if !has_id_column && columns.is_empty() {
+        if !found_table {
             Err(PowerSyncError::argument_error(format!(
                 "Could not find {table_name} in local schema."
             )))
-        } else if !has_id_column {
+        } else if let Some(key) = &schema.local_key
+            && found_key_columns != key.len()
+        {
+            Err(PowerSyncError::argument_error(format!(
+                "Table {table_name} is missing columns of its local key."
+            )))
+        } else if found_key_columns == 0 {
             Err(PowerSyncError::argument_error(format!(
                 "Table {table_name} has no id column."
             )))
         } else {
             Ok(Self {
                 name: table_name.to_string(),
+                key_columns: schema.local_key.clone(),
                 columns,
             })
         }
     }
 
+    /// Adds columns for keys of synced rows that don't exist in the local table yet.
+    ///
+    /// `observed` yields keys of synced rows along with the type of their value. Returns whether the
+    /// table has been altered.
+    pub fn add_missing_columns<'a>(
+        &mut self,
+        db: Database,
+        synced_columns: &Option<ColumnFilter>,
+        observed: impl IntoIterator<Item = (&'a str, ValueType)>,
+    ) -> Result<bool> {
+        let mut altered = false;
+
+        for (key, value_type) in observed {
+            if key == "id"
+                || is_key_column(&self.key_columns, key)
+                || self.columns.iter().any(|c| c.eq_ignore_ascii_case(key))
+            {
+                continue;
+            }
+            if let Some(filter) = synced_columns
+                && !filter.matches(key)
+            {
+                continue;
+            }
+            let Some(column_type) = value_type.column_type() else {
+                // Null values don't tell us which type to use, wait for a non-null value.
+                continue;
+            };
+
+            let mut buffer = SqlBuffer::new();
+            buffer.push_str("ALTER TABLE ");
+            let _ = buffer.identifier().write_str(&self.name);
+            buffer.push_str(" ADD COLUMN ");
+            let _ = buffer.identifier().write_str(key);
+            let _ = write!(&mut buffer, " {column_type}");
+            db.exec_safe_str(&buffer.sql)?;
+
+            self.columns.push(key.to_string());
+            altered = true;
+        }
+
+        Ok(altered)
+    }
+
+    /// Adds columns for keys of rows in `ps_oplog` that don't exist in the local table yet.
+    ///
+    /// This is used when applying a schema, new keys observed while syncing are added in
+    /// `sync_local` instead.
+    pub fn add_missing_columns_from_oplog(
+        &mut self,
+        db: Database,
+        table: &RawTable,
+    ) -> Result<bool> {
+        // Collect keys first, we can't alter the table while the statement is active.
+        let mut observed = vec![];
+        {
+            // language=SQLite
+            let stmt = db.prepare_v2(
+                "\
+SELECT e.key, e.type FROM ps_oplog o, json_each(o.data) e
+  WHERE o.row_type = ? AND e.type != 'null'
+  GROUP BY e.key",
+            )?;
+            stmt.bind_text(1, &table.name, Destructor::STATIC)?;
+
+            while stmt.step()? {
+                observed.push((
+                    stmt.column_text(0)?.to_string(),
+                    stmt.column_text(1)?.to_string(),
+                ));
+            }
+        }
+
+        self.add_missing_columns(
+            db,
+            &table.schema.synced_columns,
+            observed
+                .iter()
+                .map(|(k, t)| (k.as_str(), ValueType::from_json_type(t))),
+        )
+    }
+
     /// Generates a statement of the form `INSERT INTO $tbl ($cols) VALUES (?, ...) ON CONFLICT (id)
     /// DO UPDATE SET ...` for the sync client.
-    pub fn infer_put_stmt(&self) -> PendingStatement {
+    ///
+    /// Columns are bound to the synced column of the same name, unless a different source is
+    /// configured in `column_sources`. For tables with a local key, the key columns are used
+    /// instead of `id`.
+    pub fn infer_put_stmt(
+        &self,
+        column_sources: &BTreeMap<String, PendingStatementValue>,
+    ) -> PendingStatement {
         let mut buffer = SqlBuffer::new();
-        let mut params = vec![];
+        let mut params = self.key_params();
+        let key_count = params.len();
 
         buffer.push_str("INSERT INTO ");
         let _ = buffer.identifier().write_str(&self.name);
-        buffer.push_str(" (id");
+        buffer.push_str(" (");
+        self.write_key_columns(&mut buffer);
         for column in &self.columns {
             buffer.comma();
             let _ = buffer.identifier().write_str(column);
         }
-        buffer.push_str(") VALUES (?1");
-        params.push(PendingStatementValue::Id);
+        buffer.push_str(") VALUES (");
+        for i in 0..key_count {
+            if i != 0 {
+                buffer.comma();
+            }
+            let _ = write!(&mut buffer, "?{}", i + 1);
+        }
         for (i, column) in self.columns.iter().enumerate() {
             buffer.comma();
-            let _ = write!(&mut buffer, "?{}", i + 2);
-            params.push(PendingStatementValue::Column(column.clone()));
+            let _ = write!(&mut buffer, "?{}", i + key_count + 1);
+            params.push(match column_sources.get(column) {
+                Some(source) => source.clone(),
+                None => PendingStatementValue::Column(column.clone()),
+            });
         }
-        buffer.push_str(") ON CONFLICT (id) DO UPDATE SET ");
+        buffer.push_str(") ON CONFLICT (");
+        self.write_key_columns(&mut buffer);
+        buffer.push_str(") DO UPDATE SET ");
         let mut do_update = buffer.comma_separated();
-        // Generated an "x" = ? for all synced columns to update them without affecting local-only
-        // columns.
         for (i, column) in self.columns.iter().enumerate() {
             let entry = do_update.element();
             let _ = entry.identifier().write_str(column);
-            let _ = write!(entry, " = ?{}", i + 2);
+            let _ = write!(entry, " = ?{}", i + key_count + 1);
         }
 
-        PendingStatement {
-            sql: buffer.sql,
-            params,
-            named_parameters_index: None,
-        }
+        PendingStatement::new(buffer.sql, params)
     }
 
     /// Generates a statement of the form `DELETE FROM $tbl WHERE id = ?` for the sync client.
@@ -108,16 +219,55 @@ impl InferredTableStructure {
         let mut buffer = SqlBuffer::new();
         buffer.push_str("DELETE FROM ");
         let _ = buffer.identifier().write_str(&self.name);
-        buffer.push_str(" WHERE id = ?");
+        buffer.push_str(" WHERE ");
+        match &self.key_columns {
+            None => buffer.push_str("id = ?"),
+            Some(columns) => {
+                for (i, column) in columns.iter().enumerate() {
+                    if i != 0 {
+                        buffer.push_str(" AND ");
+                    }
+                    let _ = buffer.identifier().write_str(column);
+                    let _ = write!(&mut buffer, " = ?{}", i + 1);
+                }
+            }
+        }
+
+        PendingStatement::new(buffer.sql, self.key_params())
+    }
+
+    fn key_params(&self) -> Vec<PendingStatementValue> {
+        match &self.key_columns {
+            None => vec![PendingStatementValue::Id],
+            Some(columns) => columns
+                .iter()
+                .map(|c| PendingStatementValue::KeyColumn(c.clone()))
+                .collect(),
+        }
+    }
 
-        PendingStatement {
-            sql: buffer.sql,
-            params: vec![PendingStatementValue::Id],
-            named_parameters_index: None,
+    fn write_key_columns(&self, buffer: &mut SqlBuffer) {
+        match &self.key_columns {
+            None => buffer.push_str("id"),
+            Some(columns) => {
+                for (i, column) in columns.iter().enumerate() {
+                    if i != 0 {
+                        buffer.comma();
+                    }
+                    let _ = buffer.identifier().write_str(column);
+                }
+            }
         }
     }
 }
 
+fn is_key_column(local_key: &Option<Vec<String>>, name: &str) -> bool {
+    match local_key {
+        None => name == "id",
+        Some(columns) => columns.iter().any(|c| c == name),
+    }
+}
+
 /// A cache of inferred raw table schema and associated put and delete statements for `sync_local`.
 ///
 /// This cache avoids having to re-generate statements on every (partial) checkpoint in the sync
@@ -141,7 +291,7 @@ impl InferredSchemaCache {
         schema_version: usize,
         tbl: &RawTable,
     ) -> Result<Rc<PendingStatement>> {
-        self.with_entry(db, schema_version, tbl, SchemaCacheEntry::put)
+        self.with_entry(db, schema_version, tbl, |entry| entry.put(tbl))
     }
 
     pub fn infer_delete_statement(
@@ -153,13 +303,29 @@ impl InferredSchemaCache {
         self.with_entry(db, schema_version, tbl, SchemaCacheEntry::delete)
     }
 
-    fn with_entry(
+    /// Adds columns for keys of the synced `row` that don't exist in the local table yet.
+    ///
+    /// When this returns `true`, the table has been altered and callers need to re-read the schema
+    /// version and drop prepared statements for the table.
+    pub fn add_missing_columns(
         &self,
         db: Database,
         schema_version: usize,
         tbl: &RawTable,
-        f: impl FnOnce(&mut SchemaCacheEntry) -> Rc<PendingStatement>,
-    ) -> Result<Rc<PendingStatement>> {
+        row: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<bool> {
+        self.with_entry(db, schema_version, tbl, |entry| {
+            entry.add_missing_columns(db, tbl, row)
+        })?
+    }
+
+    fn with_entry<T>(
+        &self,
+        db: Database,
+        schema_version: usize,
+        tbl: &RawTable,
+        f: impl FnOnce(&mut SchemaCacheEntry) -> T,
+    ) -> Result<T> {
         let mut entries = self.entries.borrow_mut();
         if let Some(value) = entries.get_mut(&tbl.name) {
             if value.schema_version != schema_version {
@@ -187,11 +353,8 @@ pub struct SchemaCacheEntry {
 impl SchemaCacheEntry {
     fn infer(db: Database, schema_version: usize, table: &RawTable) -> Result<Self> {
         let local_table_name = table.require_table_name()?;
-        let structure = InferredTableStructure::read_from_database(
-            local_table_name,
-            db,
-            &table.schema.synced_columns,
-        )?;
+        let structure =
+            InferredTableStructure::read_from_database(local_table_name, db, &table.schema)?;
 
         Ok(Self {
             schema_version,
@@ -201,12 +364,36 @@ impl SchemaCacheEntry {
         })
     }
 
-    fn put(&mut self) -> Rc<PendingStatement> {
+    fn put(&mut self, table: &RawTable) -> Rc<PendingStatement> {
         self.put_stmt
-            .get_or_insert_with(|| Rc::new(self.structure.infer_put_stmt()))
+            .get_or_insert_with(|| {
+                Rc::new(self.structure.infer_put_stmt(&table.schema.column_sources))
+            })
             .clone()
     }
 
+    fn add_missing_columns(
+        &mut self,
+        db: Database,
+        table: &RawTable,
+        row: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<bool> {
+        let altered = self.structure.add_missing_columns(
+            db,
+            &table.schema.synced_columns,
+            row.iter()
+                .map(|(key, value)| (key.as_str(), ValueType::of(value))),
+        )?;
+
+        if altered {
+            // The next lookup re-infers the entry due to the changed schema version.
+            self.put_stmt = None;
+            self.delete_stmt = None;
+        }
+
+        Ok(altered)
+    }
+
     fn delete(&mut self) -> Rc<PendingStatement> {
         self.delete_stmt
             .get_or_insert_with(|| Rc::new(self.structure.infer_delete_stmt()))
@@ -225,7 +412,7 @@ pub fn generate_raw_table_trigger(
     let local_table_name = table.require_table_name()?;
     let synced_columns = &table.schema.synced_columns;
     let resolved_table =
-        InferredTableStructure::read_from_database(local_table_name, db, synced_columns)?;
+        InferredTableStructure::read_from_database(local_table_name, db, &table.schema)?;
 
     let as_schema_table = SchemaTable::Raw {
         definition: table,
@@ -265,12 +452,23 @@ pub fn generate_raw_table_trigger(
         } else {
             // Insert-only tables use manual CRUD writes so they don't block incoming data.
             let fragment = table_columns_to_json_object("NEW", &as_schema_table)?;
-            buffer.powersync_crud_manual_put(&table.name, &fragment);
+            buffer.powersync_crud_manual_put(
+                &table.name,
+                &table.local_key_expression("NEW"),
+                &fragment,
+            );
         }
     } else {
+        let new_id = table.local_key_expression("NEW");
+        let old_id = table.local_key_expression("OLD");
+
         if write == WriteType::Update {
             // Updates must not change the id.
-            buffer.check_id_not_changed();
+            if table.schema.local_key.is_some() {
+                buffer.check_key_not_changed(&old_id, &new_id);
+            } else {
+                buffer.check_id_not_changed();
+            }
         }
 
         let json_fragment_new = table_columns_to_json_object("NEW", &as_schema_table)?;
@@ -298,9 +496,9 @@ pub fn generate_raw_table_trigger(
             op: write,
             table: &as_schema_table,
             id_expr: if write == WriteType::Delete {
-                "OLD.id"
+                old_id.as_str()
             } else {
-                "NEW.id"
+                new_id.as_str()
             },
             type_name: &table.name,
             data: match write {
@@ -318,19 +516,21 @@ pub fn generate_raw_table_trigger(
 
 #[cfg(test)]
 mod test {
-    use alloc::{string::ToString, vec};
+    use alloc::{collections::btree_map::BTreeMap, string::ToString, vec};
     use core::assert_matches;
 
     use crate::schema::{PendingStatementValue, raw_table::InferredTableStructure};
+    use crate::sync::ValueType;
 
     #[test]
     fn infer_sync_statements() {
         let structure = InferredTableStructure {
             name: "tbl".to_string(),
+            key_columns: None,
             columns: vec!["foo".to_string(), "bar".to_string()],
         };
 
-        let put = structure.infer_put_stmt();
+        let put = structure.infer_put_stmt(&BTreeMap::new());
         assert_eq!(
             put.sql,
             r#"INSERT INTO "tbl" (id, "foo", "bar") VALUES (?1, ?2, ?3) ON CONFLICT (id) DO UPDATE SET "foo" = ?2, "bar" = ?3"#
@@ -351,4 +551,69 @@ mod test {
         assert_eq!(delete.params.len(), 1);
         assert_matches!(delete.params[0], PendingStatementValue::Id);
     }
+
+    #[test]
+    fn infer_put_statement_with_sources() {
+        let structure = InferredTableStructure {
+            name: "tbl".to_string(),
+            key_columns: None,
+            columns: vec!["city".to_string(), "version".to_string()],
+        };
+        let sources =
+            serde_json::from_str(r#"{"city":{"JsonPath":"$.address.city"},"version":"OpId"}"#)
+                .unwrap();
+
+        let put = structure.infer_put_stmt(&sources);
+        assert_eq!(put.params.len(), 3);
+        assert_matches!(put.params[0], PendingStatementValue::Id);
+        assert_matches!(put.params[1], PendingStatementValue::JsonPath(_));
+        assert_matches!(put.params[2], PendingStatementValue::OpId);
+        assert!(put.requires_oplog_entry());
+    }
+
+    #[test]
+    fn infer_statements_with_local_key() {
+        let structure = InferredTableStructure {
+            name: "tbl".to_string(),
+            key_columns: Some(vec!["org_id".to_string(), "ref".to_string()]),
+            columns: vec!["foo".to_string()],
+        };
+
+        let put = structure.infer_put_stmt(&BTreeMap::new());
+        assert_eq!(
+            put.sql,
+            r#"INSERT INTO "tbl" ("org_id", "ref", "foo") VALUES (?1, ?2, ?3) ON CONFLICT ("org_id", "ref") DO UPDATE SET "foo" = ?3"#
+        );
+        assert_eq!(put.params.len(), 3);
+        assert_matches!(
+            put.params[0],
+            PendingStatementValue::KeyColumn(ref name) if name == "org_id"
+        );
+        assert_matches!(
+            put.params[1],
+            PendingStatementValue::KeyColumn(ref name) if name == "ref"
+        );
+
+        let delete = structure.infer_delete_stmt();
+        assert_eq!(
+            delete.sql,
+            r#"DELETE FROM "tbl" WHERE "org_id" = ?1 AND "ref" = ?2"#
+        );
+        assert_eq!(delete.params.len(), 2);
+    }
+
+    #[test]
+    fn column_types_for_synced_values() {
+        use serde_json::json;
+
+        let column_type = |value| ValueType::of(&value).column_type();
+        assert_eq!(column_type(json!(1)), Some("INTEGER"));
+        assert_eq!(column_type(json!(true)), Some("INTEGER"));
+        assert_eq!(column_type(json!(1.5)), Some("REAL"));
+        assert_eq!(column_type(json!("a")), Some("TEXT"));
+        assert_eq!(column_type(json!({"a": 1})), Some("TEXT"));
+        assert_eq!(column_type(json!(null)), None);
+        assert_eq!(ValueType::from_json_type("false"), ValueType::Integer);
+        assert_eq!(ValueType::from_json_type("null"), ValueType::Null);
+    }
 }
@@ -0,0 +1,328 @@
+use core::fmt::Write;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::{format, vec::Vec};
+use powersync_sqlite_nostd::Destructor;
+
+use crate::error::{PowerSyncError, Result};
+use crate::schema::{Column, Table};
+use crate::utils::SqlBuffer;
+use crate::utils::database::Database;
+use crate::views::column_names_to_json_object;
+
+/// The name of the column storing values that don't match a typed column, as a JSON object.
+const REST_COLUMN: &str = "_rest";
+
+/// How rows of a PowerSync-managed table are stored in its internal `ps_data__` table.
+#[derive(PartialEq)]
+pub enum TableStorage {
+    /// Rows are stored as JSON objects in a `data` column.
+    Json,
+    /// Rows are stored in typed columns, with remaining values of synced rows being stored as a
+    /// JSON object in a `_rest` column.
+    Typed(Vec<Column>),
+}
+
+impl TableStorage {
+    pub fn for_table(table: &Table) -> Self {
+        if table.options.flags.typed_columns() {
+            Self::Typed(table.columns.clone())
+        } else {
+            Self::Json
+        }
+    }
+
+    /// Checks that the columns of a table can be stored with the layout from [Self::for_table].
+    pub fn validate(table: &Table) -> Result<()> {
+        if table.options.flags.typed_columns()
+            && table
+                .columns
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(REST_COLUMN))
+        {
+            return Err(PowerSyncError::argument_error(format!(
+                "Table {} uses typed columns and can't have a {REST_COLUMN} column",
+                table.name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the storage layout of an existing internal table.
+    pub fn read_from_database(db: Database, internal_name: &str) -> Result<Self> {
+        // language=SQLite
+        let stmt = db.prepare_v2("SELECT name, type FROM pragma_table_info(?)")?;
+        stmt.bind_text(1, internal_name, Destructor::STATIC)?;
+
+        let mut is_typed = false;
+        let mut columns = vec![];
+        while stmt.step()? {
+            let name = stmt.column_text(0)?;
+            if name == REST_COLUMN {
+                is_typed = true;
+            } else if name != "id" {
+                columns.push(Column {
+                    name: name.to_string(),
+                    type_name: stmt.column_text(1)?.to_string(),
+                });
+            }
+        }
+
+        Ok(if is_typed {
+            Self::Typed(columns)
+        } else {
+            Self::Json
+        })
+    }
+
+    pub fn is_typed(&self) -> bool {
+        matches!(self, Self::Typed(_))
+    }
+
+    pub fn create_table_stmt(&self, internal_name: &str) -> String {
+        let mut sql = SqlBuffer::new();
+        sql.push_str("CREATE TABLE ");
+        let _ = sql.identifier().write_str(internal_name);
+        sql.push_str("(id TEXT PRIMARY KEY NOT NULL, ");
+
+        match self {
+            Self::Json => sql.push_str("data TEXT"),
+            Self::Typed(columns) => {
+                for column in columns {
+                    let _ = sql.identifier().write_str(&column.name);
+                    let _ = write!(&mut sql, " {}, ", column.type_name);
+                }
+                sql.push_str(REST_COLUMN);
+                sql.push_str(" TEXT");
+            }
+        }
+
+        sql.push_char(')');
+        sql.sql
+    }
+
+    /// Writes a reference to the value of a column, as an expression on the internal table.
+    pub fn write_column_reference(&self, sql: &mut SqlBuffer, name: &str, type_name: &str) {
+        match self {
+            Self::Typed(columns) if columns.iter().any(|c| c.name == name) => {
+                let _ = sql.identifier().write_str(name);
+            }
+            Self::Typed(_) => {
+                sql.json_extract_and_cast(REST_COLUMN, name, type_name);
+            }
+            Self::Json => sql.json_extract_and_cast("data", name, type_name),
+        }
+    }
+
+    /// Writes `(id, ...) SELECT $id, ...`, a fragment for an `INSERT` statement that writes a row
+    /// from the JSON object `json`.
+    ///
+    /// The caller may add a `FROM` clause to the statement afterwards.
+    pub fn write_insert_from_json(&self, sql: &mut SqlBuffer, id: &str, json: &str) {
+        match self {
+            Self::Json => {
+                let _ = write!(sql, "(id, data) SELECT {id}, {json}");
+            }
+            Self::Typed(columns) => {
+                sql.push_str("(id, ");
+                for column in columns {
+                    let _ = sql.identifier().write_str(&column.name);
+                    sql.comma();
+                }
+                sql.push_str(REST_COLUMN);
+
+                let _ = write!(sql, ") SELECT {id}, ");
+                for column in columns {
+                    sql.json_extract_and_cast(json, &column.name, &column.type_name);
+                    sql.comma();
+                }
+                Self::write_rest_object(sql, json, columns);
+            }
+        }
+    }
+
+    /// Like [Self::write_insert_from_json], but updates an existing row with the same id instead
+    /// of failing.
+    ///
+    /// Unlike `REPLACE`, this doesn't delete other rows violating a unique index.
+    pub fn write_upsert_from_json(&self, sql: &mut SqlBuffer, id: &str, json: &str) {
+        self.write_insert_from_json(sql, id, json);
+        // The WHERE clause avoids parsing ambiguities of upserts on INSERT ... SELECT.
+        sql.push_str(" WHERE TRUE");
+        self.write_upsert_clause(sql);
+    }
+
+    /// Writes ` ON CONFLICT(id) DO UPDATE SET ...`, replacing all values of an existing row with
+    /// the same id.
+    ///
+    /// This completes a statement started with [Self::write_insert_from_json]. When the statement
+    /// selects from a table, it must have a `WHERE` clause before the upsert clause.
+    pub fn write_upsert_clause(&self, sql: &mut SqlBuffer) {
+        sql.push_str(" ON CONFLICT(id) DO UPDATE SET ");
+
+        match self {
+            Self::Json => sql.push_str("data = excluded.data"),
+            Self::Typed(columns) => {
+                for column in columns {
+                    let _ = sql.identifier().write_str(&column.name);
+                    sql.push_str(" = excluded.");
+                    let _ = sql.identifier().write_str(&column.name);
+                    sql.comma();
+                }
+                let _ = write!(sql, "{REST_COLUMN} = excluded.{REST_COLUMN}");
+            }
+        }
+    }
+
+    /// Writes `nullif(json_remove($json, ...), '{}')`, removing all typed columns from the object.
+    fn write_rest_object(sql: &mut SqlBuffer, json: &str, columns: &[Column]) {
+        // json_remove takes at most 99 paths with the default SQLITE_MAX_FUNCTION_ARG, so we nest
+        // invocations for wide tables.
+        const MAX_PATHS: usize = 50;
+        let chunks = columns.chunks(MAX_PATHS);
+
+        sql.push_str("nullif(");
+        for _ in 0..chunks.len() {
+            sql.push_str("json_remove(");
+        }
+        sql.push_str(json);
+        for chunk in chunks {
+            for column in chunk {
+                sql.comma();
+                sql.quote_json_path(&column.name);
+            }
+            sql.push_char(')');
+        }
+        sql.push_str(", '{}')");
+    }
+
+    /// Writes an expression reconstructing the JSON object of a row in the internal table, with
+    /// `prefix` being the (quoted) name or alias of the table.
+    pub fn write_row_to_json(&self, sql: &mut SqlBuffer, prefix: &str) -> Result<()> {
+        match self {
+            Self::Json => {
+                let _ = write!(sql, "{prefix}.data");
+            }
+            Self::Typed(columns) => {
+                let object =
+                    column_names_to_json_object(prefix, columns.iter().map(|c| c.name.as_str()))?;
+                let _ = write!(
+                    sql,
+                    "json_patch(ifnull({prefix}.{REST_COLUMN}, '{{}}'), {object})"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes an expression that is true if the row with the given `id` in the internal `table`
+    /// doesn't match the JSON object `json` (or exists if `json` is `NULL`).
+    pub fn write_row_differs_from_json(
+        &self,
+        sql: &mut SqlBuffer,
+        table: &str,
+        id: &str,
+        json: &str,
+    ) {
+        match self {
+            Self::Json => {
+                let _ = write!(
+                    sql,
+                    "(SELECT data FROM {table} WHERE id = {id}) IS NOT {json}"
+                );
+            }
+            Self::Typed(columns) => {
+                let _ = write!(
+                    sql,
+                    "CASE WHEN {json} IS NULL THEN EXISTS (SELECT 1 FROM {table} WHERE id = {id}) \
+ELSE NOT EXISTS (SELECT 1 FROM {table} WHERE id = {id}"
+                );
+                for column in columns {
+                    sql.push_str(" AND ");
+                    let _ = sql.identifier().write_str(&column.name);
+                    sql.push_str(" IS ");
+                    sql.json_extract_and_cast(json, &column.name, &column.type_name);
+                }
+                let _ = write!(sql, " AND {REST_COLUMN} IS ");
+                Self::write_rest_object(sql, json, columns);
+                sql.push_str(") END");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    use super::TableStorage;
+    use crate::schema::Column;
+    use crate::utils::SqlBuffer;
+
+    fn typed() -> TableStorage {
+        TableStorage::Typed(vec![
+            Column {
+                name: "a".to_string(),
+                type_name: "TEXT".to_string(),
+            },
+            Column {
+                name: "b".to_string(),
+                type_name: "INTEGER".to_string(),
+            },
+        ])
+    }
+
+    #[test]
+    fn create_table() {
+        assert_eq!(
+            TableStorage::Json.create_table_stmt("ps_data__tbl"),
+            r#"CREATE TABLE "ps_data__tbl"(id TEXT PRIMARY KEY NOT NULL, data TEXT)"#
+        );
+        assert_eq!(
+            typed().create_table_stmt("ps_data__tbl"),
+            r#"CREATE TABLE "ps_data__tbl"(id TEXT PRIMARY KEY NOT NULL, "a" TEXT, "b" INTEGER, _rest TEXT)"#
+        );
+    }
+
+    #[test]
+    fn insert_from_json() {
+        let mut sql = SqlBuffer::new();
+        typed().write_insert_from_json(&mut sql, "?1", "?2");
+
+        assert_eq!(
+            sql.sql,
+            r#"(id, "a", "b", _rest) SELECT ?1, CAST(json_extract(?2, '$.a') as TEXT), CAST(json_extract(?2, '$.b') as INTEGER), nullif(json_remove(?2, '$.a', '$.b'), '{}')"#
+        );
+    }
+
+    #[test]
+    fn upsert_from_json() {
+        let mut sql = SqlBuffer::new();
+        TableStorage::Json.write_upsert_from_json(&mut sql, "?1", "?2");
+        assert_eq!(
+            sql.sql,
+            "(id, data) SELECT ?1, ?2 WHERE TRUE ON CONFLICT(id) DO UPDATE SET data = excluded.data"
+        );
+
+        let mut sql = SqlBuffer::new();
+        typed().write_upsert_from_json(&mut sql, "?1", "?2");
+        assert!(sql.sql.ends_with(
+            r#" WHERE TRUE ON CONFLICT(id) DO UPDATE SET "a" = excluded."a", "b" = excluded."b", _rest = excluded._rest"#
+        ));
+    }
+
+    #[test]
+    fn row_to_json() {
+        let mut sql = SqlBuffer::new();
+        typed().write_row_to_json(&mut sql, "t").unwrap();
+
+        assert_eq!(
+            sql.sql,
+            "json_patch(ifnull(t._rest, '{}'), json_object('a', powersync_strip_subtype(t.\"a\"), 'b', powersync_strip_subtype(t.\"b\")))"
+        );
+    }
+}
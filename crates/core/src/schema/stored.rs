@@ -0,0 +1,161 @@
+use core::ffi::c_int;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use powersync_sqlite_nostd as sqlite;
+use powersync_sqlite_nostd::bindings::SQLITE_RESULT_SUBTYPE;
+use powersync_sqlite_nostd::{Connection, Context, Value, args};
+use sqlite::ResultCode;
+
+use crate::constants::SUBTYPE_JSON;
+use crate::error::{PowerSyncError, Result};
+use crate::utils::database::Database;
+
+/// The `ps_kv` key storing the normalized JSON of the schema last applied with
+/// `powersync_replace_schema`.
+pub const SCHEMA_KEY: &str = "schema";
+/// The `ps_kv` key storing the [schema_hash] of the [SCHEMA_KEY] entry.
+pub const SCHEMA_HASH_KEY: &str = "schema_hash";
+
+/// A schema in a canonical JSON representation, so that equivalent schemas have the same hash
+/// regardless of formatting or the order of keys.
+pub struct NormalizedSchema {
+    pub json: String,
+    pub hash: String,
+}
+
+impl NormalizedSchema {
+    pub fn from_json(json: &str) -> Result<Self> {
+        // serde_json uses sorted maps for objects (we don't enable the preserve_order feature), so
+        // serializing the parsed value again sorts keys and removes whitespace.
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(PowerSyncError::as_argument_error)?;
+        let json = serde_json::to_string(&value).map_err(PowerSyncError::internal)?;
+        let hash = schema_hash(&json);
+
+        Ok(Self { json, hash })
+    }
+
+    /// Stores this schema in `ps_kv`.
+    pub fn store(&self, db: Database) -> Result<()> {
+        // language=SQLite
+        let stmt =
+            db.prepare_v2("INSERT OR REPLACE INTO ps_kv(key, value) VALUES (?1, ?2), (?3, ?4)")?;
+        stmt.bind_text(1, SCHEMA_KEY, sqlite::Destructor::STATIC)?;
+        stmt.bind_text(2, &self.json, sqlite::Destructor::STATIC)?;
+        stmt.bind_text(3, SCHEMA_HASH_KEY, sqlite::Destructor::STATIC)?;
+        stmt.bind_text(4, &self.hash, sqlite::Destructor::STATIC)?;
+        stmt.exec()?;
+
+        Ok(())
+    }
+}
+
+/// A 64-bit FNV-1a hash of the normalized schema, formatted as hex.
+///
+/// This is not a cryptographic hash, it only needs to be stable across versions of this
+/// extension.
+fn schema_hash(normalized: &str) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET_BASIS;
+    for byte in normalized.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+
+    format!("{hash:016x}")
+}
+
+fn read_kv(db: Database, key: &str) -> Result<Option<String>> {
+    // language=SQLite
+    let stmt = db.prepare_v2("SELECT value FROM ps_kv WHERE key = ?")?;
+    stmt.bind_text(1, key, sqlite::Destructor::STATIC)?;
+
+    Ok(if stmt.step()? {
+        Some(stmt.column_text(0)?.to_string())
+    } else {
+        None
+    })
+}
+
+extern "C" fn powersync_schema(
+    ctx: *mut sqlite::context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite::value,
+) {
+    match read_kv(ctx.db_handle().into(), SCHEMA_KEY) {
+        Ok(Some(schema)) => {
+            ctx.result_text_transient(&schema);
+            ctx.result_subtype(SUBTYPE_JSON);
+        }
+        Ok(None) => ctx.result_null(),
+        Err(e) => e.apply_to_ctx("powersync_schema", ctx),
+    }
+}
+
+extern "C" fn powersync_schema_hash(
+    ctx: *mut sqlite::context,
+    argc: c_int,
+    argv: *mut *mut sqlite::value,
+) {
+    let args = args!(argc, argv);
+    let result = if let Some(schema) = args.first() {
+        // Hash of the given schema, which SDKs can compare with the applied schema.
+        NormalizedSchema::from_json(schema.text()).map(|schema| Some(schema.hash))
+    } else {
+        read_kv(ctx.db_handle().into(), SCHEMA_HASH_KEY)
+    };
+
+    match result {
+        Ok(Some(hash)) => ctx.result_text_transient(&hash),
+        Ok(None) => ctx.result_null(),
+        Err(e) => e.apply_to_ctx("powersync_schema_hash", ctx),
+    }
+}
+
+pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode> {
+    db.create_function_v2(
+        "powersync_schema",
+        0,
+        sqlite::UTF8 | SQLITE_RESULT_SUBTYPE,
+        None,
+        Some(powersync_schema),
+        None,
+        None,
+        None,
+    )?;
+
+    for argc in [0, 1] {
+        db.create_function_v2(
+            "powersync_schema_hash",
+            argc,
+            sqlite::UTF8,
+            None,
+            Some(powersync_schema_hash),
+            None,
+            None,
+            None,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::NormalizedSchema;
+
+    #[test]
+    fn normalizes_json() {
+        let a =
+            NormalizedSchema::from_json(r#"{"tables": [{"name": "a", "columns": []}]}"#).unwrap();
+        let b = NormalizedSchema::from_json(r#"{"tables":[{"columns":[],"name":"a"}]}"#).unwrap();
+
+        assert_eq!(a.json, r#"{"tables":[{"columns":[],"name":"a"}]}"#);
+        assert_eq!(a.json, b.json);
+        assert_eq!(a.hash, b.hash);
+        assert_eq!(a.hash.len(), 16);
+    }
+}
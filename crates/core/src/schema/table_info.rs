@@ -1,11 +1,19 @@
 use alloc::rc::Rc;
 use alloc::string::ToString;
 use alloc::vec;
-use alloc::{collections::btree_set::BTreeSet, format, string::String, vec::Vec};
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
 use serde::{Deserialize, de::Visitor};
 
 use crate::error::PowerSyncError;
 use crate::schema::ColumnFilter;
+use crate::schema::json_path::JsonPath;
+use crate::utils::SqlBuffer;
 
 #[derive(Deserialize)]
 pub struct Table {
@@ -15,6 +23,12 @@ pub struct Table {
     pub columns: Vec<Column>,
     #[serde(default)]
     pub indexes: Vec<Index>,
+    /// Names this table had in earlier schemas.
+    ///
+    /// When no table with [Self::name] exists, but one with a previous name does, the existing
+    /// table is renamed instead of creating an empty table.
+    #[serde(default)]
+    pub previous_names: Vec<String>,
     #[serde(flatten)]
     pub options: CommonTableOptions,
 }
@@ -41,6 +55,22 @@ pub struct RawTableSchema {
     pub table_name: Option<String>,
     #[serde(default)]
     pub synced_columns: Option<ColumnFilter>,
+    /// Sources for columns of inferred put statements, for columns that shouldn't be bound to the
+    /// synced column of the same name.
+    #[serde(default)]
+    pub column_sources: BTreeMap<String, PendingStatementValue>,
+    /// Whether to add columns to the local table when synced rows contain keys not present in the
+    /// table.
+    ///
+    /// This only applies to tables with an inferred put statement.
+    #[serde(default)]
+    pub add_missing_columns: bool,
+    /// Columns forming the key of the local table, for tables that aren't keyed by an `id` column.
+    ///
+    /// For a single column, its value is used as the synced row id. For multiple columns, the
+    /// synced row id is a JSON array containing the value of each key column.
+    #[serde(default)]
+    pub local_key: Option<Vec<String>>,
     #[serde(flatten)]
     pub options: CommonTableOptions,
 }
@@ -90,9 +120,62 @@ impl RawTable {
         };
         Ok(local_table_name)
     }
+
+    /// Decodes a synced row id into the values of [RawTableSchema::local_key] columns, or returns
+    /// `None` for tables keyed by their `id` column.
+    pub fn decode_local_key(
+        &self,
+        id: &str,
+    ) -> Result<Option<Vec<serde_json::Value>>, PowerSyncError> {
+        let Some(columns) = &self.schema.local_key else {
+            return Ok(None);
+        };
+
+        if let [_] = columns.as_slice() {
+            return Ok(Some(vec![serde_json::Value::String(id.to_string())]));
+        }
+
+        match serde_json::from_str::<Vec<serde_json::Value>>(id) {
+            Ok(values) if values.len() == columns.len() => Ok(Some(values)),
+            _ => Err(PowerSyncError::argument_error(format!(
+                "Expected id of {} to be a JSON array with {} elements, got {id}",
+                self.name,
+                columns.len(),
+            ))),
+        }
+    }
+
+    /// An SQL expression computing the synced row id from the local key of a row in triggers,
+    /// with `prefix` being either `NEW` or `OLD`.
+    pub fn local_key_expression(&self, prefix: &str) -> String {
+        let mut buffer = SqlBuffer::new();
+        match self.schema.local_key.as_deref() {
+            None => {
+                let _ = write!(&mut buffer, "{prefix}.id");
+            }
+            Some([column]) => {
+                let _ = write!(&mut buffer, "CAST({prefix}.");
+                let _ = buffer.identifier().write_str(column);
+                buffer.push_str(" AS TEXT)");
+            }
+            Some(columns) => {
+                buffer.push_str("json_array(");
+                for (i, column) in columns.iter().enumerate() {
+                    if i != 0 {
+                        buffer.push_str(", ");
+                    }
+                    let _ = write!(&mut buffer, "{prefix}.");
+                    let _ = buffer.identifier().write_str(column);
+                }
+                buffer.push_str(")");
+            }
+        }
+
+        buffer.sql
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
     #[serde(rename = "type")]
@@ -103,14 +186,34 @@ pub struct Column {
 pub struct Index {
     pub name: String,
     pub columns: Vec<IndexedColumn>,
+    /// Whether to create a `UNIQUE` index.
+    ///
+    /// Synced rows conflicting with another row are retried after the rest of the checkpoint has
+    /// been applied, so a checkpoint may e.g. delete a row and re-create it under a new id. Rows
+    /// that still violate the constraint then fail the sync instead of replacing the conflicting
+    /// row.
+    #[serde(default)]
+    pub unique: bool,
+    /// An SQL expression making this a partial index. Columns of the table can be referenced by
+    /// their name.
+    #[serde(default, rename = "where")]
+    pub where_clause: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct IndexedColumn {
+    #[serde(default)]
     pub name: String,
     pub ascending: bool,
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default)]
     pub type_name: String,
+    /// An SQL expression to index instead of the column [Self::name]. Columns of the table can be
+    /// referenced by their name.
+    #[serde(default)]
+    pub expression: Option<String>,
+    /// The collating sequence to use for this column, like `NOCASE`.
+    #[serde(default)]
+    pub collate: Option<String>,
 }
 
 pub enum DiffIncludeOld {
@@ -190,6 +293,7 @@ impl TableInfoFlags {
     pub const INCLUDE_METADATA: u32 = 4;
     pub const INCLUDE_OLD_ONLY_WHEN_CHANGED: u32 = 8;
     pub const IGNORE_EMPTY_UPDATE: u32 = 16;
+    pub const TYPED_COLUMNS: u32 = 32;
 
     pub const fn local_only(self) -> bool {
         self.0 & Self::LOCAL_ONLY != 0
@@ -217,6 +321,16 @@ impl TableInfoFlags {
         self.0 & Self::IGNORE_EMPTY_UPDATE != 0
     }
 
+    pub const fn typed_columns(self) -> bool {
+        // Local-only tables are never synced, so they always use the JSON layout that's cheaper to
+        // migrate.
+        if self.local_only() {
+            return false;
+        }
+
+        self.0 & Self::TYPED_COLUMNS != 0
+    }
+
     const fn with_flag(self, flag: u32) -> Self {
         Self(self.0 | flag)
     }
@@ -270,6 +384,7 @@ impl<'de> Deserialize<'de> for TableInfoFlags {
                                 TableInfoFlags::INCLUDE_OLD_ONLY_WHEN_CHANGED
                             }
                             "ignore_empty_update" => TableInfoFlags::IGNORE_EMPTY_UPDATE,
+                            "typed_columns" => TableInfoFlags::TYPED_COLUMNS,
                             _ => continue,
                         },
                         value,
@@ -288,6 +403,7 @@ impl<'de> Deserialize<'de> for TableInfoFlags {
                 "include_metadata",
                 "include_old_only_when_changed",
                 "ignore_empty_update",
+                "typed_columns",
             ],
             FlagsVisitor,
         )
@@ -311,36 +427,21 @@ pub struct RestColumnIndex {
     pub rest_parameter_positions: Vec<usize>,
 }
 
-impl<'de> Deserialize<'de> for PendingStatement {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        #[derive(Deserialize)]
-        struct PendingStatementSource {
-            pub sql: String,
-            /// This vec should contain an entry for each parameter in [sql].
-            pub params: Vec<PendingStatementValue>,
-        }
-
-        let source = PendingStatementSource::deserialize(deserializer)?;
+impl PendingStatement {
+    pub fn new(sql: String, params: Vec<PendingStatementValue>) -> Self {
         let mut named_parameters_index = None;
-        if source
-            .params
+        if params
             .iter()
             .any(|s| matches!(s, PendingStatementValue::Rest))
         {
             let mut set = BTreeSet::new();
             let mut rest_parameter_positions = vec![];
-            for (i, column) in source.params.iter().enumerate() {
-                set.insert(match column {
-                    PendingStatementValue::Id => "id".to_string(),
-                    PendingStatementValue::Column(name) => name.clone(),
-                    PendingStatementValue::Rest => {
-                        rest_parameter_positions.push(i);
-                        continue;
-                    }
-                });
+            for (i, column) in params.iter().enumerate() {
+                if let PendingStatementValue::Rest = column {
+                    rest_parameter_positions.push(i);
+                } else if let Some(name) = column.referenced_column() {
+                    set.insert(name.to_string());
+                }
             }
 
             named_parameters_index = Some(RestColumnIndex {
@@ -349,15 +450,43 @@ impl<'de> Deserialize<'de> for PendingStatement {
             });
         }
 
-        return Ok(Self {
-            sql: source.sql,
-            params: source.params,
+        Self {
+            sql,
+            params,
             named_parameters_index,
-        });
+        }
+    }
+
+    /// Whether binding this statement requires the op id or bucket of the oplog entry for the
+    /// affected row.
+    pub fn requires_oplog_entry(&self) -> bool {
+        self.params.iter().any(|p| {
+            matches!(
+                p,
+                PendingStatementValue::OpId | PendingStatementValue::BucketPriority
+            )
+        })
     }
 }
 
-#[derive(Deserialize, Debug)]
+impl<'de> Deserialize<'de> for PendingStatement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct PendingStatementSource {
+            pub sql: String,
+            /// This vec should contain an entry for each parameter in [sql].
+            pub params: Vec<PendingStatementValue>,
+        }
+
+        let source = PendingStatementSource::deserialize(deserializer)?;
+        Ok(Self::new(source.sql, source.params))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub enum PendingStatementValue {
     /// Bind to the PowerSync row id of the affected row.
     Id,
@@ -366,5 +495,37 @@ pub enum PendingStatementValue {
     /// Bind to a JSON object containing all columns from the synced row that haven't been matched
     /// by other statement values.
     Rest,
-    // TODO: Stuff like a raw object of put data?
+    /// Bind to the synced row as a JSON object.
+    Data,
+    /// Bind to a value nested in the synced row, like `$.address.city`.
+    ///
+    /// Nested values that are objects or arrays are bound as JSON text.
+    JsonPath(JsonPath),
+    /// Bind to a constant value.
+    Constant(serde_json::Value),
+    /// Like [Self::Column], but binding to a default value if the synced row doesn't have the
+    /// column.
+    ColumnWithDefault {
+        name: String,
+        default: serde_json::Value,
+    },
+    /// Bind to the priority of the bucket containing the synced row.
+    BucketPriority,
+    /// Bind to the id of the operation that has last written the synced row.
+    OpId,
+    /// Bind to the value of a [RawTableSchema::local_key] column, decoded from the synced row id.
+    KeyColumn(String),
+}
+
+impl PendingStatementValue {
+    /// The column of the synced row this value is derived from, used to exclude that column from
+    /// [Self::Rest] objects.
+    fn referenced_column(&self) -> Option<&str> {
+        match self {
+            Self::Id => Some("id"),
+            Self::Column(name) | Self::ColumnWithDefault { name, .. } => Some(name),
+            Self::JsonPath(path) => path.top_level_key(),
+            _ => None,
+        }
+    }
 }
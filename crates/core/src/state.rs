@@ -15,6 +15,7 @@ use crate::{
     error::Result,
     schema::{InferredSchemaCache, Schema},
     sync::{SyncClient, storage_adapter::StorageAdapter},
+    update_hooks::{RowChangeBuffer, RowChangeKind},
     utils::database::Database,
 };
 
@@ -31,12 +32,18 @@ pub struct DatabaseState {
     schema: RefCell<Option<Schema>>,
     pending_updates: RefCell<BTreeSet<String>>,
     commited_updates: RefCell<BTreeSet<String>>,
+    /// Row-level changes recorded by the update hook, if enabled with
+    /// `powersync_update_hooks('track_rows', max_rows)`.
+    row_changes: RefCell<Option<RowChangeBuffer>>,
     pub storage_adapter: RefCell<Option<Rc<StorageAdapter>>>,
     pub sync_client: RefCell<Option<SyncClient>>,
     /// Cached put and delete statements for raw tables, used by the `sync_local` step of the sync
     /// client.
     pub inferred_schema_cache: InferredSchemaCache,
     pub current_transaction_id: Cell<Option<i64>>,
+    /// A JSON description of the error returned by the last `powersync_control` call, if it
+    /// failed.
+    pub last_control_error: RefCell<Option<String>>,
 }
 
 impl DatabaseState {
@@ -84,8 +91,39 @@ impl DatabaseState {
         }
     }
 
+    /// Records a change to an individual row, if row-level tracking is enabled.
+    pub fn track_row_change(&self, tbl: &str, rowid: i64, kind: RowChangeKind) {
+        if let Some(buffer) = &mut *self.row_changes.borrow_mut() {
+            buffer.track(tbl, rowid, kind, self.is_in_sync_local.get());
+        }
+    }
+
+    /// Attaches the `id` of a row in `ps_data__<type_name>` or `ps_data_local__<type_name>` to the
+    /// change recorded for the write that has just been made to it.
+    ///
+    /// Since SQLite only reports rowids to update hooks, we rely on the statements writing to data
+    /// tables to report the id of the row they've just written.
+    pub fn track_row_id(&self, type_name: &str, id: &str) {
+        if let Some(buffer) = &mut *self.row_changes.borrow_mut() {
+            buffer.attach_id(type_name, id);
+        }
+    }
+
+    /// Enables row-level change tracking with the given maximum amount of buffered rows, or
+    /// disables it if `max_rows` is `None`.
+    pub fn set_row_tracking(&self, max_rows: Option<usize>) {
+        let mut buffer = self.row_changes.borrow_mut();
+        match max_rows {
+            Some(max_rows) => buffer.get_or_insert_default().max_rows = max_rows,
+            None => *buffer = None,
+        }
+    }
+
     pub fn track_rollback(&self) {
         self.pending_updates.borrow_mut().clear();
+        if let Some(buffer) = &mut *self.row_changes.borrow_mut() {
+            buffer.rollback();
+        }
     }
 
     pub fn track_commit(&self) {
@@ -96,6 +134,10 @@ impl DatabaseState {
         for pending in pending.into_iter() {
             commited.insert(pending);
         }
+
+        if let Some(buffer) = &mut *self.row_changes.borrow_mut() {
+            buffer.commit();
+        }
     }
 
     pub fn take_updates(&self) -> BTreeSet<String> {
@@ -103,6 +145,14 @@ impl DatabaseState {
         core::mem::replace(&mut *committed, Default::default())
     }
 
+    /// Takes committed row-level changes, if row-level tracking is enabled.
+    pub fn take_row_changes(&self) -> Option<RowChangeBuffer> {
+        self.row_changes
+            .borrow_mut()
+            .as_mut()
+            .map(RowChangeBuffer::take_committed)
+    }
+
     pub fn storage_adapter(&self, db: Database) -> Result<Rc<StorageAdapter>> {
         let mut adapter = self.storage_adapter.borrow_mut();
         Ok(match *adapter {
@@ -6,6 +6,7 @@ use crate::sync::line::{BucketChecksum, BucketSubscriptionReason};
 use crate::sync::{BucketPriority, Checksum};
 use crate::utils::database::Database;
 use powersync_sqlite_nostd::{self as sqlite};
+use serde::Serialize;
 
 /// A structure cloned from [BucketChecksum]s with an owned bucket name instead of one borrowed from
 /// a sync line.
@@ -39,11 +40,18 @@ impl From<&'_ BucketChecksum<'_>> for OwnedBucketChecksum {
     }
 }
 
+/// A bucket in a checkpoint for which the downloaded data doesn't match the checksum.
+#[derive(Serialize)]
 pub struct ChecksumMismatch {
+    #[serde(rename = "bucket")]
     pub bucket_name: String,
     pub expected_checksum: Checksum,
     pub actual_op_checksum: Checksum,
     pub actual_add_checksum: Checksum,
+    /// The amount of operations in the bucket, as reported by the checkpoint.
+    pub expected_count: Option<i64>,
+    /// The amount of operations downloaded for the bucket.
+    pub actual_count: i64,
 }
 
 pub fn validate_checkpoint<'a>(
@@ -56,7 +64,8 @@ pub fn validate_checkpoint<'a>(
         "
 SELECT
     ps_buckets.add_checksum as add_checksum,
-    ps_buckets.op_checksum as oplog_checksum
+    ps_buckets.op_checksum as oplog_checksum,
+    ps_buckets.count_at_last + ps_buckets.count_since_last as count
 FROM ps_buckets WHERE name = ?;",
     )?;
 
@@ -65,12 +74,12 @@ FROM ps_buckets WHERE name = ?;",
         if bucket.is_in_priority(priority) {
             statement.bind_text(1, &bucket.bucket, sqlite::Destructor::STATIC)?;
 
-            let (add_checksum, oplog_checksum) = if statement.step()? {
+            let (add_checksum, oplog_checksum, count) = if statement.step()? {
                 let add_checksum = Checksum::from_i32(statement.column_int(0));
                 let oplog_checksum = Checksum::from_i32(statement.column_int(1));
-                (add_checksum, oplog_checksum)
+                (add_checksum, oplog_checksum, statement.column_int64(2))
             } else {
-                (Checksum::zero(), Checksum::zero())
+                (Checksum::zero(), Checksum::zero(), 0)
             };
 
             let actual = add_checksum + oplog_checksum;
@@ -81,6 +90,8 @@ FROM ps_buckets WHERE name = ?;",
                     expected_checksum: bucket.checksum,
                     actual_add_checksum: add_checksum,
                     actual_op_checksum: oplog_checksum,
+                    expected_count: bucket.count,
+                    actual_count: count,
                 });
             }
 
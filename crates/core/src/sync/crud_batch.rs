@@ -0,0 +1,243 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_with::{DisplayFromStr, PickFirst, serde_as};
+
+use crate::error::{PowerSyncError, Result};
+use crate::sync::storage_adapter::StorageAdapter;
+
+/// Options for the `next_crud_batch` command of `powersync_control`.
+#[derive(Deserialize)]
+pub struct NextCrudBatchRequest {
+    /// The maximum amount of entries to include in the batch.
+    #[serde(default = "NextCrudBatchRequest::default_limit")]
+    pub limit: usize,
+    /// The maximum total size of entries (as the length of their JSON representation in
+    /// `ps_crud.data`) to include in the batch.
+    ///
+    /// A batch always contains at least one entry, even if that entry alone exceeds this limit.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// Whether to only include entries from a single transaction, as identified by the `tx_id`
+    /// column of `ps_crud`.
+    #[serde(default)]
+    pub single_transaction: bool,
+}
+
+impl NextCrudBatchRequest {
+    pub const fn default_limit() -> usize {
+        100
+    }
+}
+
+impl Default for NextCrudBatchRequest {
+    fn default() -> Self {
+        Self {
+            limit: Self::default_limit(),
+            max_bytes: None,
+            single_transaction: false,
+        }
+    }
+}
+
+/// Options for the `complete_crud_batch` command of `powersync_control`.
+#[serde_as]
+#[derive(Deserialize)]
+pub struct CompleteCrudBatchRequest {
+    /// The `client_id` of the last entry that has been uploaded.
+    ///
+    /// All entries up to and including this one are removed from `ps_crud`.
+    pub last_id: i64,
+    /// The checkpoint request id the service will include in a checkpoint once the uploaded
+    /// changes have been processed.
+    ///
+    /// If this completes the upload queue, this is used as the target checkpoint request id that
+    /// needs to be seen before downloaded data is applied again. For 64-bit precision in JSON
+    /// clients, this can also be passed as a decimal string.
+    #[serde(default)]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub checkpoint_request_id: Option<i64>,
+}
+
+/// An entry from `ps_crud`, in the format written by the `powersync_crud` virtual table.
+#[derive(Serialize, Deserialize)]
+struct CrudEntryData<'a> {
+    #[serde(borrow)]
+    op: Cow<'a, str>,
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+    #[serde(rename = "type", borrow)]
+    row_type: Cow<'a, str>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    data: Option<&'a RawValue>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    old: Option<&'a RawValue>,
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    metadata: Option<Cow<'a, str>>,
+}
+
+#[derive(Serialize)]
+struct CrudBatchEntry<'a> {
+    client_id: i64,
+    tx_id: Option<i64>,
+    #[serde(flatten)]
+    entry: CrudEntryData<'a>,
+}
+
+#[derive(Serialize)]
+struct CrudBatch<'a> {
+    entries: Vec<CrudBatchEntry<'a>>,
+    has_more: bool,
+}
+
+/// A row read from `ps_crud`.
+struct PendingCrudRow {
+    id: i64,
+    tx_id: Option<i64>,
+    data: String,
+}
+
+/// Reads the next batch of pending local writes from `ps_crud`, returning a JSON object with the
+/// parsed `entries` and whether more entries are pending (`has_more`).
+///
+/// This doesn't remove entries from `ps_crud`, which happens with [complete_crud_batch] after a
+/// successful upload. Until then, the batch is considered to be in flight and won't be changed by
+/// `powersync_compact_crud`.
+pub fn next_crud_batch(adapter: &StorageAdapter, request: &NextCrudBatchRequest) -> Result<String> {
+    if request.limit == 0 {
+        return Err(PowerSyncError::argument_error("limit must be positive"));
+    }
+
+    // language=SQLite
+    let stmt = adapter
+        .db
+        .prepare_v2("SELECT id, tx_id, data FROM ps_crud ORDER BY id ASC")?;
+
+    let mut rows = Vec::<PendingCrudRow>::new();
+    let mut total_bytes = 0usize;
+    let mut has_more = false;
+
+    while stmt.step()? {
+        let id = stmt.column_int64(0);
+        let tx_id = stmt.column_nullable(1, || Ok(stmt.column_int64(1)))?;
+        let data = stmt.column_text(2)?;
+
+        if let Some(first) = rows.first() {
+            let exceeds_bytes = request
+                .max_bytes
+                .is_some_and(|max| total_bytes + data.len() > max);
+            let other_transaction = request.single_transaction && first.tx_id != tx_id;
+
+            if rows.len() >= request.limit || exceeds_bytes || other_transaction {
+                has_more = true;
+                break;
+            }
+        }
+
+        total_bytes += data.len();
+        rows.push(PendingCrudRow {
+            id,
+            tx_id,
+            data: data.to_string(),
+        });
+    }
+
+    adapter.set_in_flight_crud_batch(rows.last().map(|row| row.id))?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let entry: CrudEntryData = serde_json::from_str(&row.data).map_err(|e| {
+            PowerSyncError::json_local_error(e).context(format!("ps_crud entry {}", row.id))
+        })?;
+
+        entries.push(CrudBatchEntry {
+            client_id: row.id,
+            tx_id: row.tx_id,
+            entry,
+        });
+    }
+
+    serde_json::to_string(&CrudBatch { entries, has_more }).map_err(PowerSyncError::internal)
+}
+
+/// Removes uploaded entries from `ps_crud`, returning whether more entries are pending.
+///
+/// When the upload queue is empty afterwards and a checkpoint request id has been provided, that
+/// id becomes the target checkpoint request id gating downloaded data. While entries are still
+/// pending, the existing target is left unchanged.
+///
+/// Transaction ids only group pending entries, so `ps_tx` is reset once the queue is empty.
+pub fn complete_crud_batch(
+    adapter: &StorageAdapter,
+    request: &CompleteCrudBatchRequest,
+) -> Result<bool> {
+    if request.checkpoint_request_id.is_some_and(|id| id <= 0) {
+        return Err(PowerSyncError::argument_error(
+            "checkpoint request id must be a positive integer",
+        ));
+    }
+
+    // language=SQLite
+    let stmt = adapter.db.prepare_v2("DELETE FROM ps_crud WHERE id <= ?")?;
+    stmt.bind_int64(1, request.last_id)?;
+    stmt.exec()?;
+    adapter.set_in_flight_crud_batch(None)?;
+
+    // language=SQLite
+    let stmt = adapter.db.prepare_v2("SELECT 1 FROM ps_crud LIMIT 1")?;
+    let has_more = stmt.step()?;
+
+    if !has_more {
+        // language=SQLite
+        adapter
+            .db
+            .exec_safe(c"UPDATE ps_tx SET current_tx = NULL, next_tx = 1 WHERE id = 1")?;
+
+        if let Some(request_id) = request.checkpoint_request_id {
+            adapter.probe_target_checkpoint_request_id(Some(request_id))?;
+        }
+    }
+
+    Ok(has_more)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serializes_entry() {
+        let entry = CrudBatchEntry {
+            client_id: 3,
+            tx_id: Some(2),
+            entry: serde_json::from_str(
+                r#"{"op":"PUT","id":"a","type":"items","data":{"col":"x\"y"},"metadata":"m"}"#,
+            )
+            .unwrap(),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"client_id":3,"tx_id":2,"op":"PUT","id":"a","type":"items","data":{"col":"x\"y"},"metadata":"m"}"#
+        );
+    }
+
+    #[test]
+    fn parses_complete_request() {
+        let request: CompleteCrudBatchRequest =
+            serde_json::from_str(r#"{"last_id": 10, "checkpoint_request_id": "9007199254740993"}"#)
+                .unwrap();
+        assert_eq!(request.last_id, 10);
+        assert_eq!(request.checkpoint_request_id, Some(9007199254740993));
+
+        let request: CompleteCrudBatchRequest =
+            serde_json::from_str(r#"{"last_id": 10, "checkpoint_request_id": 4}"#).unwrap();
+        assert_eq!(request.checkpoint_request_id, Some(4));
+
+        let request: CompleteCrudBatchRequest = serde_json::from_str(r#"{"last_id": 10}"#).unwrap();
+        assert_eq!(request.checkpoint_request_id, None);
+    }
+}
@@ -12,7 +12,7 @@ use serde::{
 
 use crate::sync::{
     interface::{Instruction, StartSyncStream},
-    line::{DataLine, OplogData, SyncLineStr},
+    line::{DataLine, SyncLineStr},
     sync_status::{BucketProgress, DownloadSyncStatus},
 };
 
@@ -43,7 +43,8 @@ pub struct ObservedSchemaType {
     pub value_type: ValueType,
 }
 
-#[derive(Serialize, Clone, Copy, PartialEq)]
+/// The type of a synced value, as stored by SQLite.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
 pub enum ValueType {
     Null,
     String,
@@ -51,6 +52,42 @@ pub enum ValueType {
     Real,
 }
 
+impl ValueType {
+    /// The type SQLite uses to store a JSON value extracted from synced data (booleans are stored
+    /// as integers, nested objects and arrays as text).
+    pub fn of(value: &serde_json::Value) -> Self {
+        use serde_json::Value;
+
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Integer,
+            Value::Number(number) if number.is_f64() => Self::Real,
+            Value::Number(_) => Self::Integer,
+            Value::String(_) | Value::Array(_) | Value::Object(_) => Self::String,
+        }
+    }
+
+    /// Like [Self::of], but for the result of SQLite's `json_type()` function.
+    pub fn from_json_type(json_type: &str) -> Self {
+        match json_type {
+            "integer" | "true" | "false" => Self::Integer,
+            "real" => Self::Real,
+            "text" | "object" | "array" => Self::String,
+            _ => Self::Null,
+        }
+    }
+
+    /// The column type to use for a new column storing values of this type, or `None` for nulls.
+    pub fn column_type(self) -> Option<&'static str> {
+        match self {
+            Self::Null => None,
+            Self::String => Some("TEXT"),
+            Self::Integer => Some("INTEGER"),
+            Self::Real => Some("REAL"),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct DiagnosticsCollector {
     inferred_schema: BTreeMap<String, BTreeMap<String, ValueType>>,
@@ -112,13 +149,16 @@ impl DiagnosticsCollector {
 
         for op in &line.data {
             if let (Some(data), Some(object_type)) = (&op.data, &op.object_type) {
-                let OplogData::Json { data } = data;
+                // Invalid data is reported when the line is applied, diagnostics can ignore it.
+                let Ok(data) = data.to_json() else {
+                    continue;
+                };
                 let table = self
                     .inferred_schema
                     .entry(object_type.to_string())
                     .or_default();
 
-                let mut de = serde_json::Deserializer::from_str(data);
+                let mut de = serde_json::Deserializer::from_str(&data);
 
                 struct TypeInferringVisitor<'a> {
                     table_name: &'a str,
@@ -219,6 +259,13 @@ impl<'de> Deserialize<'de> for ValueToValueType {
                 Ok(ValueType::Real)
             }
 
+            fn visit_bool<E>(self, _v: bool) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(ValueType::Integer)
+            }
+
             fn visit_u64<E>(self, _v: u64) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
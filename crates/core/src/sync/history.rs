@@ -0,0 +1,182 @@
+use alloc::string::String;
+use powersync_sqlite_nostd::{self as sqlite, ResultCode};
+
+use crate::error::Result;
+use crate::sync::sync_status::TimestampMicros;
+use crate::utils::database::Database;
+use crate::vtab_util::QueryTable;
+
+/// The amount of sync iterations to keep in `ps_sync_history`.
+///
+/// This is a fixed limit: the history is meant for diagnostics of recent connection attempts, so
+/// we don't expose an option for it.
+const MAX_HISTORY_ENTRIES: i64 = 50;
+
+/// Why a sync iteration has ended, as recorded in `ps_sync_history.close_reason`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncHistoryCloseReason {
+    /// The client has stopped the iteration or started another one.
+    Stopped,
+    /// The response stream from the sync service has ended.
+    StreamEnded,
+    /// No line has been received within the configured keepalive timeout.
+    KeepaliveTimeout,
+    /// The client has refreshed credentials.
+    TokenRefreshed,
+    /// The token used to connect has expired.
+    TokenExpired,
+    /// Stream subscriptions have changed, requiring a new request.
+    SubscriptionsChanged,
+    /// Downloaded data didn't match checkpoint checksums.
+    ChecksumFailure,
+    /// The iteration was interrupted by an error.
+    Error,
+}
+
+impl SyncHistoryCloseReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::StreamEnded => "stream_ended",
+            Self::KeepaliveTimeout => "keepalive_timeout",
+            Self::TokenRefreshed => "token_refreshed",
+            Self::TokenExpired => "token_expired",
+            Self::SubscriptionsChanged => "subscriptions_changed",
+            Self::ChecksumFailure => "checksum_failure",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// Statistics for a sync iteration, tracked in memory and periodically written to its
+/// `ps_sync_history` row.
+pub struct SyncHistoryEntry {
+    pub id: i64,
+    pub downloaded_bytes: i64,
+    pub downloaded_operations: i64,
+    pub completed_checkpoints: i64,
+}
+
+impl SyncHistoryEntry {
+    /// Inserts a row for a new sync iteration, removing the oldest rows exceeding
+    /// [MAX_HISTORY_ENTRIES].
+    pub fn start(db: Database, started_at: TimestampMicros) -> Result<Self> {
+        // language=SQLite
+        let stmt =
+            db.prepare_v2("INSERT INTO ps_sync_history (started_at) VALUES (?) RETURNING id")?;
+        stmt.bind_int64(1, started_at.0)?;
+        stmt.step()?;
+        let id = stmt.column_int64(0);
+        stmt.reset()?;
+
+        // language=SQLite
+        let stmt = db.prepare_v2("DELETE FROM ps_sync_history WHERE id <= ?")?;
+        stmt.bind_int64(1, id - MAX_HISTORY_ENTRIES)?;
+        stmt.exec()?;
+
+        Ok(Self {
+            id,
+            downloaded_bytes: 0,
+            downloaded_operations: 0,
+            completed_checkpoints: 0,
+        })
+    }
+
+    /// Writes statistics collected so far.
+    pub fn save(&self, db: Database) -> Result<()> {
+        // language=SQLite
+        let stmt = db.prepare_v2(
+            "\
+UPDATE ps_sync_history
+  SET downloaded_bytes = ?2, downloaded_operations = ?3, completed_checkpoints = ?4
+  WHERE id = ?1",
+        )?;
+        stmt.bind_int64(1, self.id)?;
+        stmt.bind_int64(2, self.downloaded_bytes)?;
+        stmt.bind_int64(3, self.downloaded_operations)?;
+        stmt.bind_int64(4, self.completed_checkpoints)?;
+        stmt.exec()
+    }
+
+    /// Writes statistics and marks the iteration as completed.
+    pub fn finish(
+        &self,
+        db: Database,
+        ended_at: TimestampMicros,
+        reason: SyncHistoryCloseReason,
+    ) -> Result<()> {
+        self.save(db)?;
+        record_end(db, self.id, ended_at, reason, None)
+    }
+}
+
+/// Marks a sync iteration as completed.
+///
+/// This is also used for iterations that failed with an error, where the [SyncHistoryEntry] is no
+/// longer available.
+pub fn record_end(
+    db: Database,
+    id: i64,
+    ended_at: TimestampMicros,
+    reason: SyncHistoryCloseReason,
+    error: Option<&str>,
+) -> Result<()> {
+    // language=SQLite
+    let stmt = db.prepare_v2(
+        "UPDATE ps_sync_history SET ended_at = ?2, close_reason = ?3, error = ?4 WHERE id = ?1",
+    )?;
+    stmt.bind_int64(1, id)?;
+    stmt.bind_int64(2, ended_at.0)?;
+    stmt.bind_text(3, reason.as_str(), sqlite::Destructor::STATIC)?;
+    match error {
+        Some(error) => stmt.bind_text(4, error, sqlite::Destructor::STATIC)?,
+        None => stmt.bind_null(4)?,
+    }
+    stmt.exec()
+}
+
+/// A sync iteration that has been interrupted by an error.
+///
+/// Since `powersync_control` calls failing with an error are typically rolled back, we keep this
+/// around to record the error once the next iteration starts or the client is stopped.
+pub struct FailedIteration {
+    pub history_id: i64,
+    pub ended_at: TimestampMicros,
+    pub error: String,
+}
+
+impl FailedIteration {
+    pub fn record(&self, db: Database) -> Result<()> {
+        record_end(
+            db,
+            self.history_id,
+            self.ended_at,
+            SyncHistoryCloseReason::Error,
+            Some(&self.error),
+        )
+    }
+}
+
+static SYNC_HISTORY: QueryTable = QueryTable {
+    // language=SQLite
+    schema: "\
+CREATE TABLE powersync_sync_history (
+  id INTEGER,
+  started_at INTEGER,
+  ended_at INTEGER,
+  close_reason TEXT,
+  error TEXT,
+  downloaded_bytes INTEGER,
+  downloaded_operations INTEGER,
+  completed_checkpoints INTEGER
+)",
+    // language=SQLite
+    query: "\
+SELECT id, started_at, ended_at, close_reason, error, downloaded_bytes, downloaded_operations,
+  completed_checkpoints
+FROM ps_sync_history ORDER BY id DESC",
+};
+
+pub fn register(db: *mut sqlite::sqlite3) -> core::result::Result<(), ResultCode> {
+    SYNC_HISTORY.register(db, "powersync_sync_history")
+}
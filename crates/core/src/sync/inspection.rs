@@ -0,0 +1,54 @@
+use powersync_sqlite_nostd::{self as sqlite, ResultCode};
+
+use crate::vtab_util::QueryTable;
+
+// These tables expose sync state with a stable layout, regardless of how the underlying internal
+// tables change in migrations.
+
+static BUCKETS: QueryTable = QueryTable {
+    // language=SQLite
+    schema: "\
+CREATE TABLE powersync_buckets (
+  name TEXT,
+  priority INTEGER,
+  last_op INTEGER,
+  last_applied_op INTEGER,
+  count_at_last INTEGER,
+  count_since_last INTEGER,
+  downloaded_size INTEGER,
+  add_checksum INTEGER,
+  op_checksum INTEGER,
+  pending_delete INTEGER
+)",
+    // language=SQLite
+    query: "\
+SELECT name, priority, last_op, last_applied_op, count_at_last, count_since_last, downloaded_size,
+  add_checksum, op_checksum, pending_delete
+FROM ps_buckets ORDER BY name",
+};
+
+static STREAMS: QueryTable = QueryTable {
+    // language=SQLite
+    schema: "\
+CREATE TABLE powersync_streams (
+  name TEXT,
+  parameters TEXT,
+  priority INTEGER,
+  active INTEGER,
+  is_default INTEGER,
+  has_explicit_subscription INTEGER,
+  ttl INTEGER,
+  expires_at INTEGER,
+  last_synced_at INTEGER
+)",
+    // language=SQLite
+    query: "\
+SELECT stream_name, nullif(local_params, 'null'), local_priority, active, is_default, ttl IS NOT NULL,
+  ttl, expires_at, last_synced_at
+FROM ps_stream_subscriptions ORDER BY id",
+};
+
+pub fn register(db: *mut sqlite::sqlite3) -> Result<(), ResultCode> {
+    BUCKETS.register(db, "powersync_buckets")?;
+    STREAMS.register(db, "powersync_streams")
+}
@@ -9,7 +9,11 @@ use crate::create_sqlite_text_fn;
 use crate::error::{PowerSyncError, Result};
 use crate::schema::Schema;
 use crate::state::DatabaseState;
+use crate::sync::crud_batch::{
+    CompleteCrudBatchRequest, NextCrudBatchRequest, complete_crud_batch, next_crud_batch,
+};
 use crate::sync::diagnostics::{DiagnosticOptions, DiagnosticsEvent};
+use crate::sync::line::{split_binary_lines, split_text_lines};
 use crate::sync::subscriptions::{StreamKey, apply_subscriptions};
 use crate::utils::database::Database;
 use alloc::borrow::Cow;
@@ -26,6 +30,7 @@ use serde_with::{DisplayFromStr, serde_as};
 use sqlite::{ResultCode, Value};
 
 use crate::sync::BucketPriority;
+use crate::sync::checkpoint::ChecksumMismatch;
 use crate::utils::{JsonString, verify_in_transaction};
 
 /// Payload provided by SDKs when requesting a sync iteration.
@@ -59,6 +64,38 @@ pub struct StartSyncStream {
     /// Whether sync diagnostics with detailed download stats and inferred schema should be reported
     /// by the sync client.
     pub diagnostics: Option<DiagnosticOptions>,
+
+    /// Whether downloaded rows without pending local changes should be applied while there are
+    /// pending uploads.
+    ///
+    /// By default, the client waits for all local changes to be uploaded and acknowledged before
+    /// publishing a checkpoint. With this option, rows that don't have local changes are applied
+    /// immediately, while rows with local changes keep their local state until the upload has
+    /// been acknowledged by a write checkpoint.
+    #[serde(default)]
+    pub row_level_gating: bool,
+
+    /// How to handle buckets with data not matching the checksum of a checkpoint.
+    #[serde(default)]
+    pub checksum_failure_policy: ChecksumFailurePolicy,
+
+    /// Whether to emit [Instruction::UpdateSyncStatusDelta] instead of
+    /// [Instruction::UpdateSyncStatus], avoiding the full status to be sent on every change.
+    #[serde(default)]
+    pub status_deltas: bool,
+
+    /// If set, the sync client closes the stream when no line has been received for this amount
+    /// of milliseconds.
+    ///
+    /// The timeout is checked when receiving [SyncEvent::Tick] events, which SDKs need to send
+    /// periodically.
+    #[serde(default)]
+    pub keepalive_timeout_ms: Option<u64>,
+
+    /// If set, [Instruction::CloseSyncStream] includes a suggested delay before connecting again,
+    /// which increases with consecutive failed connections.
+    #[serde(default)]
+    pub reconnect_backoff: Option<ReconnectBackoff>,
 }
 
 impl StartSyncStream {
@@ -77,6 +114,11 @@ impl Default for StartSyncStream {
             app_metadata: Default::default(),
             checkpoint_mode: CheckpointMode::default(),
             diagnostics: Default::default(),
+            row_level_gating: false,
+            checksum_failure_policy: ChecksumFailurePolicy::default(),
+            status_deltas: false,
+            keepalive_timeout_ms: None,
+            reconnect_backoff: None,
         }
     }
 }
@@ -92,6 +134,56 @@ pub enum CheckpointMode {
     Requests,
 }
 
+/// Selects how buckets failing checksum validation are re-downloaded.
+#[derive(Default, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ChecksumFailurePolicy {
+    /// Deletes failing buckets, removing rows only synced through them until the bucket has been
+    /// downloaded again.
+    #[default]
+    Delete,
+    /// Moves the data of failing buckets into a quarantine bucket that is not part of sync
+    /// requests, so that only those buckets are fetched again.
+    ///
+    /// Applied rows stay visible while the new download is in progress. The quarantined data is
+    /// removed once a checkpoint including the new bucket has been validated.
+    Quarantine,
+}
+
+/// Options for the reconnect delay suggested in [CloseSyncStream::retry_delay_ms].
+///
+/// The delay starts at `initial_delay_ms` for the first failure and doubles with each consecutive
+/// failure, up to `max_delay_ms`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ReconnectBackoff {
+    #[serde(default = "ReconnectBackoff::default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "ReconnectBackoff::default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl ReconnectBackoff {
+    pub const fn default_initial_delay_ms() -> u64 {
+        1000
+    }
+
+    pub const fn default_max_delay_ms() -> u64 {
+        60_000
+    }
+
+    /// The delay to wait before reconnecting after `failures` consecutive failed connections.
+    pub fn delay_ms(&self, failures: i64) -> u64 {
+        if failures <= 0 {
+            return 0;
+        }
+
+        let exponent = (failures - 1).min(63) as u32;
+        self.initial_delay_ms
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_delay_ms)
+    }
+}
+
 /// A request sent from a client SDK to the [SyncClient] with a `powersync_control` invocation.
 pub enum SyncControlRequest<'a> {
     /// The client requests to start a sync iteration.
@@ -102,6 +194,23 @@ pub enum SyncControlRequest<'a> {
     StopSyncStream,
     /// The client is forwading a sync event to the core extension.
     SyncEvent(SyncEvent<'a>),
+    /// The client is forwarding multiple sync events at once.
+    ///
+    /// Events are handled in order until the sync iteration completes, instructions emitted for
+    /// them are merged.
+    SyncEvents(Vec<SyncEvent<'a>>),
+    /// The client is forwarding a chunk of the response stream from the sync service.
+    ///
+    /// Chunks don't have to align with lines, the sync client reassembles lines spanning multiple
+    /// chunks.
+    StreamChunk(StreamChunk<'a>),
+}
+
+pub enum StreamChunk<'a> {
+    /// A chunk of a newline-delimited JSON response.
+    Text(&'a [u8]),
+    /// A chunk of a response consisting of concatenated BSON documents.
+    Binary(&'a [u8]),
 }
 
 pub enum SyncEvent<'a> {
@@ -121,6 +230,8 @@ pub enum SyncEvent<'a> {
     UploadFinished,
     ConnectionEstablished,
     StreamEnded,
+    /// Sent periodically by SDKs, allowing the sync client to check for timeouts.
+    Tick,
     /// Forward a text line (JSON) received from the sync service.
     TextLine {
         data: &'a str,
@@ -151,6 +262,24 @@ pub enum Instruction {
     UpdateSyncStatus {
         status: Rc<RefCell<DownloadSyncStatus>>,
     },
+    /// Update the download status by applying changes to the previously-published status.
+    ///
+    /// This is emitted instead of [Instruction::UpdateSyncStatus] when enabled with
+    /// [StartSyncStream::status_deltas].
+    UpdateSyncStatusDelta {
+        /// Starts at zero for each sync iteration and is incremented for each delta, allowing
+        /// clients to detect missed updates.
+        sequence: u64,
+        /// Whether `changes` contains the full status instead of changes to the previous status.
+        ///
+        /// This is the case for the first update of a sync iteration.
+        snapshot: bool,
+        /// A JSON merge patch (RFC 7396) to apply to the previous status.
+        ///
+        /// Nested objects only include changed fields, arrays (like `streams` or
+        /// `priority_status`) are replaced as a whole.
+        changes: serde_json::Map<String, serde_json::Value>,
+    },
     /// Connect to the sync service using the [StreamingSyncRequest] created by the core extension,
     /// and then forward received lines via [SyncEvent::TextLine] and [SyncEvent::BinaryLine].
     EstablishSyncStream {
@@ -183,6 +312,33 @@ pub enum Instruction {
         applied_checkpoint_request_id: Option<i64>,
     },
 
+    /// Notify that downloaded data didn't match the checksums of a checkpoint.
+    ///
+    /// This is followed by a [Instruction::CloseSyncStream] to fetch the affected buckets again.
+    DidFailChecksumValidation {
+        /// The priority of the partial checkpoint that failed validation, or `None` for a complete
+        /// checkpoint.
+        priority: Option<BucketPriority>,
+        buckets: Vec<ChecksumMismatch>,
+        /// Whether the data of failing buckets has been quarantined instead of being deleted,
+        /// according to [StartSyncStream::checksum_failure_policy].
+        quarantined: bool,
+    },
+
+    /// Notify that a line passed to `lines_text`, `lines_binary` or a stream chunk could not be
+    /// handled after earlier lines of the same call have been handled.
+    ///
+    /// Lines after the failing one have not been handled. If the error has ended the sync
+    /// iteration, this is followed by a [Instruction::CloseSyncStream]. Otherwise, the error is
+    /// retryable and clients can pass the failing line and the lines after it again.
+    DidFailLine {
+        /// The index of the failing line in the call.
+        line: usize,
+        /// The [crate::error::ErrorDescription] of the error, which is also returned by
+        /// `powersync_last_error()`.
+        error: Box<RawValue>,
+    },
+
     /// Handle a diagnostic event.
     ///
     /// This instruction is only emitted if diagnostics have been enabled on [StartSyncStream].
@@ -194,6 +350,22 @@ pub struct CloseSyncStream {
     /// Whether clients should hide the brief disconnected status from the public sync status and
     /// reconnect immediately.
     pub hide_disconnect: bool,
+    /// Why the sync client is closing the stream, if it's not a regular close.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<CloseReason>,
+    /// How long clients should wait before connecting again.
+    ///
+    /// This is only set when [StartSyncStream::reconnect_backoff] is enabled and the iteration is
+    /// not closed by the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// No line has been received within [StartSyncStream::keepalive_timeout_ms].
+    KeepaliveTimeout,
 }
 
 #[derive(Serialize)]
@@ -254,11 +426,13 @@ pub fn register(
         argc: c_int,
         argv: *mut *mut sqlite::value,
     ) -> () {
+        let state = unsafe { DatabaseState::from_context(&ctx) };
+        state.last_control_error.take();
+
         let result = (|| -> Result<()> {
             let db = Database::from(ctx.db_handle());
             verify_in_transaction(db)?;
 
-            let state = unsafe { DatabaseState::from_context(&ctx) };
             let args = sqlite::args!(argc, argv);
             let [op, payload] = args else {
                 // This should be unreachable, we register the function with two arguments.
@@ -351,6 +525,49 @@ pub fn register(
                         ));
                     },
                 }),
+                "lines_text" => {
+                    SyncControlRequest::SyncEvents(if payload.value_type() == ColumnType::Text {
+                        split_text_lines(payload.text())
+                            .map(|data| SyncEvent::TextLine { data })
+                            .collect()
+                    } else {
+                        return Err(PowerSyncError::argument_error(
+                            "Second argument must be a string",
+                        ));
+                    })
+                }
+                "lines_binary" => {
+                    SyncControlRequest::SyncEvents(if payload.value_type() == ColumnType::Blob {
+                        split_binary_lines(payload.blob())?
+                            .into_iter()
+                            .map(|data| SyncEvent::BinaryLine { data })
+                            .collect()
+                    } else {
+                        return Err(PowerSyncError::argument_error(
+                            "Second argument must be a byte array",
+                        ));
+                    })
+                }
+                "chunk_text" => {
+                    SyncControlRequest::StreamChunk(StreamChunk::Text(match payload.value_type() {
+                        ColumnType::Text => payload.text().as_bytes(),
+                        ColumnType::Blob => payload.blob(),
+                        _ => {
+                            return Err(PowerSyncError::argument_error(
+                                "Second argument must be a string or a byte array",
+                            ));
+                        }
+                    }))
+                }
+                "chunk_binary" => SyncControlRequest::StreamChunk(StreamChunk::Binary(
+                    if payload.value_type() == ColumnType::Blob {
+                        payload.blob()
+                    } else {
+                        return Err(PowerSyncError::argument_error(
+                            "Second argument must be a byte array",
+                        ));
+                    },
+                )),
                 "refreshed_token" => SyncControlRequest::SyncEvent(SyncEvent::DidRefreshToken),
                 "completed_upload" => SyncControlRequest::SyncEvent(SyncEvent::UploadFinished),
                 "update_subscriptions" => {
@@ -359,6 +576,7 @@ pub fn register(
                             .map_err(PowerSyncError::as_argument_error)?,
                     })
                 }
+                "tick" => SyncControlRequest::SyncEvent(SyncEvent::Tick),
                 "connection" => SyncControlRequest::SyncEvent(match payload.text() {
                     "established" => SyncEvent::ConnectionEstablished,
                     "end" => SyncEvent::StreamEnded,
@@ -366,6 +584,67 @@ pub fn register(
                         return Err(PowerSyncError::argument_error("unknown connection event"));
                     }
                 }),
+                "next_crud_batch" => {
+                    let request = if payload.value_type() == ColumnType::Text {
+                        serde_json::from_str(payload.text())
+                            .map_err(PowerSyncError::as_argument_error)?
+                    } else {
+                        NextCrudBatchRequest::default()
+                    };
+
+                    let adapter = state.storage_adapter(db)?;
+                    let batch = next_crud_batch(&adapter, &request)?;
+                    ctx.result_text_transient(&batch);
+                    ctx.result_subtype(SUBTYPE_JSON);
+                    return Ok(());
+                }
+                "complete_crud_batch" => {
+                    let request = match payload.value_type() {
+                        ColumnType::Integer => CompleteCrudBatchRequest {
+                            last_id: payload.int64(),
+                            checkpoint_request_id: None,
+                        },
+                        ColumnType::Text => serde_json::from_str(payload.text())
+                            .map_err(PowerSyncError::as_argument_error)?,
+                        _ => {
+                            return Err(PowerSyncError::argument_error(
+                                "Second argument must be an integer or a JSON object",
+                            ));
+                        }
+                    };
+
+                    let adapter = state.storage_adapter(db)?;
+                    let has_more = complete_crud_batch(&adapter, &request)?;
+
+                    // Completing the last batch finishes the upload, which may allow applying a
+                    // checkpoint that has been blocked by local writes (like `completed_upload`).
+                    let mut instructions = Vec::new();
+                    if !has_more {
+                        let mut client = state.sync_client.borrow_mut();
+                        if let Some(client) = client.as_mut()
+                            && client.has_sync_iteration()
+                        {
+                            instructions = client.push_event(SyncControlRequest::SyncEvent(
+                                SyncEvent::UploadFinished,
+                            ))?;
+                        }
+                    }
+
+                    #[derive(Serialize)]
+                    struct CompletedCrudBatch {
+                        has_more: bool,
+                        instructions: Vec<Instruction>,
+                    }
+
+                    let formatted = serde_json::to_string(&CompletedCrudBatch {
+                        has_more,
+                        instructions,
+                    })
+                    .map_err(PowerSyncError::internal)?;
+                    ctx.result_text_transient(&formatted);
+                    ctx.result_subtype(SUBTYPE_JSON);
+                    return Ok(());
+                }
                 "subscriptions" => {
                     let adapter = state.storage_adapter(db)?;
                     let request = serde_json::from_str(payload.text())
@@ -399,10 +678,31 @@ pub fn register(
         })();
 
         if let Err(e) = result {
+            // Serializing the description can't reasonably fail, and we'd rather report the
+            // original error if it does.
+            if let Ok(description) = serde_json::to_string(&e.describe()) {
+                state.last_control_error.replace(Some(description));
+            }
+
             e.apply_to_ctx("powersync_control", ctx);
         }
     }
 
+    extern "C" fn last_error(
+        ctx: *mut sqlite::context,
+        _argc: c_int,
+        _argv: *mut *mut sqlite::value,
+    ) -> () {
+        let state = unsafe { DatabaseState::from_context(&ctx) };
+        match &*state.last_control_error.borrow() {
+            Some(description) => {
+                ctx.result_text_transient(description);
+                ctx.result_subtype(SUBTYPE_JSON);
+            }
+            None => ctx.result_null(),
+        }
+    }
+
     db.create_function_v2(
         "powersync_control",
         2,
@@ -414,6 +714,17 @@ pub fn register(
         Some(DatabaseState::destroy_rc),
     )?;
 
+    db.create_function_v2(
+        "powersync_last_error",
+        0,
+        sqlite::UTF8 | sqlite::DIRECTONLY | SQLITE_RESULT_SUBTYPE,
+        Some(Rc::into_raw(state.clone()) as *mut c_void),
+        Some(last_error),
+        None,
+        None,
+        Some(DatabaseState::destroy_rc),
+    )?;
+
     db.create_function_v2(
         "powersync_offline_sync_status",
         0,
@@ -503,3 +814,25 @@ fn parse_positive_i64_payload(
     NonZeroI64::new(value)
         .ok_or_else(|| PowerSyncError::argument_error(format!("{name} must be a positive integer")))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reconnect_backoff() {
+        let backoff: ReconnectBackoff =
+            serde_json::from_str(r#"{"initial_delay_ms": 500, "max_delay_ms": 3000}"#).unwrap();
+
+        assert_eq!(backoff.delay_ms(0), 0);
+        assert_eq!(backoff.delay_ms(1), 500);
+        assert_eq!(backoff.delay_ms(2), 1000);
+        assert_eq!(backoff.delay_ms(3), 2000);
+        assert_eq!(backoff.delay_ms(4), 3000);
+        assert_eq!(backoff.delay_ms(100), 3000);
+
+        let backoff: ReconnectBackoff = serde_json::from_str("{}").unwrap();
+        assert_eq!(backoff.delay_ms(1), 1000);
+        assert_eq!(backoff.delay_ms(10), 60_000);
+    }
+}
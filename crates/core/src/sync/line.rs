@@ -7,7 +7,7 @@ use serde::de::{Error, IgnoredAny, VariantAccess, Visitor};
 use serde_with::{DisplayFromStr, serde_as};
 
 use crate::bson;
-use crate::error::PowerSyncError;
+use crate::error::{PowerSyncError, PowerSyncErrorCause};
 
 use super::Checksum;
 use super::bucket_priority::BucketPriority;
@@ -61,6 +61,40 @@ impl<'a> SyncLineWithSource<'a> {
     }
 }
 
+/// Splits newline-delimited JSON into individual text lines, skipping empty lines.
+pub fn split_text_lines(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+/// Splits concatenated BSON documents into individual binary lines.
+///
+/// BSON documents start with their total length, so they don't need additional framing.
+pub fn split_binary_lines(mut source: &[u8]) -> Result<Vec<&[u8]>, PowerSyncError> {
+    let mut lines = Vec::new();
+    while !source.is_empty() {
+        let size = bson::document_size(source)
+            .map_err(|e| PowerSyncError::sync_protocol_error("invalid binary line", e))?;
+        match size {
+            Some(length) if length <= source.len() => {
+                let (line, rest) = source.split_at(length);
+                lines.push(line);
+                source = rest;
+            }
+            _ => {
+                return Err(PowerSyncError::sync_protocol_error(
+                    "incomplete binary line",
+                    PowerSyncErrorCause::Unknown,
+                ));
+            }
+        }
+    }
+
+    Ok(lines)
+}
+
 #[derive(Debug)]
 
 pub enum SyncLine<'a> {
@@ -330,7 +364,26 @@ pub struct OplogEntry<'a> {
 pub enum OplogData<'a> {
     /// A string encoding a well-formed JSON object representing values of the row.
     Json { data: Cow<'a, str> },
-    //    BsonDocument { data: Cow<'a, [u8]> },
+    /// An embedded BSON document representing values of the row.
+    ///
+    /// This is only used for binary sync lines, and only when the sync service sends row data as
+    /// documents instead of JSON strings.
+    BsonDocument { data: Cow<'a, [u8]> },
+}
+
+impl<'a> OplogData<'a> {
+    /// Returns the JSON representation of this data, as stored in `ps_oplog`.
+    ///
+    /// For JSON data, this is free. BSON documents are converted with [bson::to_json].
+    pub fn to_json(&self) -> Result<Cow<'_, str>, PowerSyncError> {
+        Ok(match self {
+            OplogData::Json { data } => Cow::Borrowed(data.as_ref()),
+            OplogData::BsonDocument { data } => Cow::Owned(
+                bson::to_json(data)
+                    .map_err(|e| PowerSyncError::sync_protocol_error("invalid oplog data", e))?,
+            ),
+        })
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -360,12 +413,58 @@ impl<'a, 'de: 'a> Deserialize<'de> for OplogData<'a> {
     where
         D: serde::Deserializer<'de>,
     {
-        // For now, we will always get oplog data as a string. In the future, there may be the
-        // option of the sync service sending BSON-encoded data lines too, but that's not relevant
-        // for now.
-        return Ok(OplogData::Json {
-            data: Deserialize::deserialize(deserializer)?,
-        });
+        // JSON sync lines always embed oplog data as a string.
+        if deserializer.is_human_readable() {
+            return Ok(OplogData::Json {
+                data: Deserialize::deserialize(deserializer)?,
+            });
+        }
+
+        // BSON sync lines either embed data as a JSON string or as a document. We ask the BSON
+        // deserializer to give us a view of embedded documents instead of parsing them so that we
+        // can transform them into JSON directly.
+        struct OplogDataVisitor;
+
+        impl<'de> Visitor<'de> for OplogDataVisitor {
+            type Value = OplogData<'de>;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(formatter, "a JSON string or an embedded document")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(OplogData::Json {
+                    data: Cow::Borrowed(v),
+                })
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(OplogData::Json {
+                    data: Cow::Owned(v.into()),
+                })
+            }
+
+            fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(OplogData::BsonDocument {
+                    data: Cow::Borrowed(v),
+                })
+            }
+        }
+
+        deserializer.deserialize_enum(
+            bson::Deserializer::SPECIAL_CASE_EMBEDDED_DOCUMENT,
+            &[],
+            OplogDataVisitor,
+        )
     }
 }
 
@@ -381,6 +480,34 @@ mod tests {
         serde_json::from_str(source).expect("Should have deserialized")
     }
 
+    #[test]
+    fn splits_text_lines() {
+        let lines: Vec<&str> = split_text_lines("{\"a\":1}\r\n\n{\"b\":2}\n").collect();
+        assert_eq!(lines, ["{\"a\":1}", "{\"b\":2}"]);
+    }
+
+    #[test]
+    fn splits_binary_lines() {
+        let empty = [5u8, 0, 0, 0, 0];
+        let with_int = [12u8, 0, 0, 0, 0x10, b'a', 0, 1, 0, 0, 0, 0];
+
+        let mut source = Vec::new();
+        source.extend_from_slice(&empty);
+        source.extend_from_slice(&with_int);
+        source.extend_from_slice(&empty);
+
+        assert_eq!(
+            split_binary_lines(&source).unwrap(),
+            [&empty[..], &with_int[..], &empty[..]]
+        );
+        assert!(split_binary_lines(&[]).unwrap().is_empty());
+
+        // Truncated documents and invalid lengths
+        assert!(split_binary_lines(&with_int[..11]).is_err());
+        assert!(split_binary_lines(&with_int[..3]).is_err());
+        assert!(split_binary_lines(&[0, 0, 0, 0]).is_err());
+    }
+
     #[test]
     fn parse_token_expires_in() {
         assert_matches!(
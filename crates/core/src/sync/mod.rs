@@ -4,11 +4,15 @@ use powersync_sqlite_nostd::{self as sqlite, ResultCode};
 mod bucket_priority;
 pub mod checkpoint;
 mod checksum;
+mod crud_batch;
 mod diagnostics;
+mod history;
+mod inspection;
 mod interface;
 pub mod line;
 pub mod operations;
 pub mod storage_adapter;
+mod stream_buffer;
 mod streaming_sync;
 mod subscriptions;
 mod sync_local;
@@ -16,10 +20,13 @@ mod sync_status;
 
 pub use bucket_priority::BucketPriority;
 pub use checksum::Checksum;
+pub use diagnostics::ValueType;
 
 use crate::state::DatabaseState;
 pub use streaming_sync::SyncClient;
 
 pub fn register(db: *mut sqlite::sqlite3, state: Rc<DatabaseState>) -> Result<(), ResultCode> {
-    interface::register(db, state)
+    interface::register(db, state)?;
+    history::register(db)?;
+    inspection::register(db)
 }
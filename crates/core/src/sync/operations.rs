@@ -1,3 +1,4 @@
+use alloc::borrow::Cow;
 use alloc::format;
 use alloc::string::String;
 use num_traits::Zero;
@@ -6,7 +7,6 @@ use powersync_sqlite_nostd::{self as sqlite};
 use crate::error::Result;
 
 use super::Checksum;
-use super::line::OplogData;
 use super::{
     line::{DataLine, OpType},
     storage_adapter::{BucketInfo, StorageAdapter},
@@ -136,9 +136,15 @@ INSERT OR IGNORE INTO ps_updated_rows(row_type, row_id) VALUES(?1, ?2)",
                 insert_statement.bind_null(5)?;
             }
             if let Some(data) = op_data {
-                let OplogData::Json { data } = data;
-
-                insert_statement.bind_text(6, data, sqlite::Destructor::STATIC)?;
+                match data.to_json()? {
+                    Cow::Borrowed(data) => {
+                        insert_statement.bind_text(6, data, sqlite::Destructor::STATIC)?
+                    }
+                    // Converted from BSON, SQLite needs to copy the data.
+                    Cow::Owned(data) => {
+                        insert_statement.bind_text(6, &data, sqlite::Destructor::TRANSIENT)?
+                    }
+                };
             } else {
                 insert_statement.bind_null(6)?;
             }
@@ -1,6 +1,11 @@
 use core::fmt::Display;
 
-use alloc::{rc::Rc, string::ToString, vec::Vec};
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
 use powersync_sqlite_nostd::{self as sqlite};
 use serde::Serialize;
 
@@ -11,10 +16,12 @@ use crate::{
     state::DatabaseState,
     sync::{
         checkpoint::{ChecksumMismatch, validate_checkpoint},
-        interface::{RequestedStreamSubscription, StreamSubscriptionRequest},
+        interface::{
+            ChecksumFailurePolicy, RequestedStreamSubscription, StreamSubscriptionRequest,
+        },
         streaming_sync::{OwnedStreamDescription, RequestedStreamSubscriptions},
         subscriptions::{LocallyTrackedSubscription, StreamKey},
-        sync_local::{PartialSyncOperation, SyncOperation},
+        sync_local::{PartialSyncOperation, SyncApplyResult, SyncOperation},
         sync_status::{
             ActiveStreamSubscription, DownloadSyncStatus, SyncPriorityStatus, TimestampMicros,
         },
@@ -38,6 +45,14 @@ pub const LAST_APPLIED_CHECKPOINT_REQUEST_ID_KEY: &str = "last_applied_checkpoin
 // checkpoint request id also stored in LAST_REQUESTED_CHECKPOINT_REQUEST_ID_KEY.
 pub const TARGET_CHECKPOINT_REQUEST_ID_KEY: &str = "target_checkpoint_request_id";
 
+// The amount of consecutive sync iterations closed due to a lost connection, used to suggest
+// reconnect delays. This is reset when a checkpoint is applied.
+pub const CONSECUTIVE_SYNC_FAILURES_KEY: &str = "consecutive_sync_failures";
+
+// The client_id of the last ps_crud entry returned by next_crud_batch, until the batch has been
+// completed. Entries up to this id may be uploading, so powersync_compact_crud must not touch them.
+pub const IN_FLIGHT_CRUD_BATCH_KEY: &str = "in_flight_crud_batch";
+
 /// An adapter for storing sync state.
 ///
 /// This is used to encapsulate some SQL queries used for the sync implementation, making the code
@@ -191,6 +206,107 @@ WHERE bucket = ?1",
         Ok(())
     }
 
+    /// Moves the data of the given buckets into quarantine buckets, which are not included in
+    /// sync requests and will thus cause the original buckets to be downloaded from scratch.
+    ///
+    /// Unlike [Self::delete_buckets], this keeps rows synced through those buckets around until
+    /// the new download has been validated, at which point [Self::release_quarantined_buckets]
+    /// removes the old data.
+    pub fn quarantine_buckets<'a>(&self, buckets: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        // language=SQLite
+        let quarantine = self.db.prepare_v2(
+            "UPDATE OR IGNORE ps_buckets SET name = ?2, pending_delete = 1 WHERE name = ?1 RETURNING id",
+        )?;
+
+        // Operations downloaded after the last validated checkpoint are part of the download that
+        // failed validation, so they must never be applied. Remove them and reset the bucket to
+        // its state at that checkpoint.
+        // language=SQLite
+        let reset_bucket = self.db.prepare_v2(
+            "\
+UPDATE ps_buckets SET
+    op_checksum = (op_checksum - coalesce(
+        (SELECT sum(hash) FROM ps_oplog WHERE bucket = ?1 AND op_id > ps_buckets.last_applied_op),
+        0
+    )) & 0xffffffff,
+    last_op = last_applied_op,
+    count_since_last = 0
+WHERE id = ?1",
+        )?;
+        // language=SQLite
+        let delete_unvalidated = self.db.prepare_v2(
+            "\
+DELETE FROM ps_oplog
+WHERE bucket = ?1 AND op_id > (SELECT last_applied_op FROM ps_buckets WHERE id = ?1)",
+        )?;
+
+        for bucket in buckets {
+            let quarantine_name = Self::quarantine_name(bucket);
+            quarantine.bind_text(1, bucket, sqlite::Destructor::STATIC)?;
+            quarantine.bind_text(2, &quarantine_name, sqlite::Destructor::STATIC)?;
+            let renamed = if quarantine.step()? {
+                Some(quarantine.column_int64(0))
+            } else {
+                None
+            };
+            quarantine.reset()?;
+
+            if let Some(bucket_id) = renamed {
+                reset_bucket.bind_int64(1, bucket_id)?;
+                reset_bucket.exec()?;
+                delete_unvalidated.bind_int64(1, bucket_id)?;
+                delete_unvalidated.exec()?;
+            } else {
+                // Renaming is skipped if there already is a quarantined copy of this bucket,
+                // meaning that the new download failed validation as well. The old data is still
+                // there, so we can simply delete the new download.
+                self.delete_buckets([bucket])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes quarantined data for buckets in the given checkpoint that have just been
+    /// validated.
+    ///
+    /// For complete checkpoints, this also deletes quarantined buckets that are no longer part of
+    /// the checkpoint.
+    fn release_quarantined_buckets(
+        &self,
+        checkpoint: &OwnedCheckpoint,
+        priority: Option<BucketPriority>,
+    ) -> Result<()> {
+        // language=SQLite
+        let stmt = self
+            .db
+            .prepare_v2("SELECT name FROM ps_buckets WHERE pending_delete = 1")?;
+
+        let mut to_delete = Vec::<String>::new();
+        while stmt.step()? {
+            let name = stmt.column_text(0)?;
+            let Some(bucket) = name.strip_prefix(Self::QUARANTINE_PREFIX) else {
+                continue;
+            };
+
+            let validated = match checkpoint.buckets.get(bucket) {
+                Some(bucket) => bucket.is_in_priority(priority),
+                None => priority.is_none(),
+            };
+            if validated {
+                to_delete.push(name.to_string());
+            }
+        }
+
+        self.delete_buckets(to_delete.iter().map(|name| name.as_str()))
+    }
+
+    const QUARANTINE_PREFIX: &str = "$quarantine/";
+
+    fn quarantine_name(bucket: &str) -> String {
+        format!("{}{bucket}", Self::QUARANTINE_PREFIX)
+    }
+
     pub fn step_progress(&'_ self) -> Result<Option<PersistedBucketProgress<'_>>> {
         if self.progress_stmt.step()? {
             let bucket = self.progress_stmt.column_text(0)?;
@@ -245,26 +361,37 @@ WHERE bucket = ?1",
         checkpoint: &OwnedCheckpoint,
         priority: Option<BucketPriority>,
         schema: &Schema,
+        row_level_gating: bool,
+        checksum_failure_policy: ChecksumFailurePolicy,
     ) -> Result<SyncLocalResult> {
         let mismatched_checksums =
             validate_checkpoint(checkpoint.buckets.values(), priority, self.db)?;
 
         if !mismatched_checksums.is_empty() {
-            self.delete_buckets(mismatched_checksums.iter().map(|i| i.bucket_name.as_str()))?;
+            let failed_buckets = mismatched_checksums.iter().map(|i| i.bucket_name.as_str());
+            match checksum_failure_policy {
+                ChecksumFailurePolicy::Delete => self.delete_buckets(failed_buckets)?,
+                ChecksumFailurePolicy::Quarantine => self.quarantine_buckets(failed_buckets)?,
+            }
 
             return Ok(SyncLocalResult::ChecksumFailure(CheckpointResult {
                 failed_buckets: mismatched_checksums,
             }));
         }
 
+        // Buckets with valid checksums replace earlier data that has been quarantined. Deleting
+        // those marks affected rows as updated, so they're restored from the new data.
+        self.release_quarantined_buckets(checkpoint, priority)?;
+
         let update_bucket = self
             .db
-            .prepare_v2("UPDATE ps_buckets SET last_op = ? WHERE name = ?")?;
+            .prepare_v2("UPDATE ps_buckets SET last_op = ?, priority = ? WHERE name = ?")?;
 
         for bucket in checkpoint.buckets.values() {
             if bucket.is_in_priority(priority) {
                 update_bucket.bind_int64(1, checkpoint.last_op_id)?;
-                update_bucket.bind_text(2, &bucket.bucket, sqlite::Destructor::STATIC)?;
+                update_bucket.bind_int(2, bucket.priority.into())?;
+                update_bucket.bind_text(3, &bucket.bucket, sqlite::Destructor::STATIC)?;
                 update_bucket.exec()?;
             }
         }
@@ -284,6 +411,9 @@ WHERE bucket = ?1",
             None => {
                 let mut sync = SyncOperation::new(state, self.db, None, now);
                 sync.use_schema(schema);
+                if row_level_gating {
+                    sync.enable_row_level_gating();
+                }
                 sync.apply()
             }
             Some(priority) => {
@@ -315,11 +445,14 @@ WHERE bucket = ?1",
                     now,
                 );
                 sync.use_schema(schema);
+                if row_level_gating {
+                    sync.enable_row_level_gating();
+                }
                 sync.apply()
             }
         }?;
 
-        if sync_result == 1 {
+        if sync_result == SyncApplyResult::Applied {
             if priority.is_none() {
                 // Reset progress counters. We only do this for a complete sync, as we want a
                 // download progress to always cover a complete checkpoint instead of resetting for
@@ -341,7 +474,9 @@ WHERE bucket = ?1",
 
             Ok(SyncLocalResult::ChangesApplied { timestamp: now })
         } else {
-            Ok(SyncLocalResult::PendingLocalChanges)
+            Ok(SyncLocalResult::PendingLocalChanges {
+                rows_applied: sync_result == SyncApplyResult::PartiallyApplied,
+            })
         }
     }
 
@@ -580,6 +715,37 @@ RETURNING value",
         }
     }
 
+    /// Increments and returns the amount of consecutive failed sync iterations.
+    pub fn record_sync_failure(&self) -> Result<i64> {
+        let statement = self.db.prepare_v2(
+            "INSERT INTO ps_kv(key, value)
+VALUES(?1, 1)
+ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + 1
+RETURNING value",
+        )?;
+        statement.bind_text(1, CONSECUTIVE_SYNC_FAILURES_KEY, sqlite::Destructor::STATIC)?;
+
+        if statement.step()? {
+            Ok(statement.column_int64(0))
+        } else {
+            Err(PowerSyncError::unknown_internal())
+        }
+    }
+
+    /// Resets the counter of consecutive failed sync iterations after a checkpoint was applied.
+    pub fn reset_sync_failures(&self) -> Result<()> {
+        self.delete_kv(CONSECUTIVE_SYNC_FAILURES_KEY)
+    }
+
+    /// Records the `client_id` of the last entry in a crud batch handed out for uploading, or
+    /// clears it when no batch is in flight.
+    pub fn set_in_flight_crud_batch(&self, last_id: Option<i64>) -> Result<()> {
+        match last_id {
+            Some(last_id) => self.write_i64_kv(IN_FLIGHT_CRUD_BATCH_KEY, last_id),
+            None => self.delete_kv(IN_FLIGHT_CRUD_BATCH_KEY),
+        }
+    }
+
     /// Returns whether the local checkpoint request counter has been initialized.
     pub fn has_checkpoint_request_id(&self) -> Result<bool> {
         Ok(self.last_checkpoint_request_id()?.is_some())
@@ -654,7 +820,7 @@ pub struct BucketInfo {
 }
 
 pub struct CheckpointResult {
-    failed_buckets: Vec<ChecksumMismatch>,
+    pub failed_buckets: Vec<ChecksumMismatch>,
 }
 
 impl CheckpointResult {
@@ -702,7 +868,10 @@ pub enum SyncLocalResult {
     ChecksumFailure(CheckpointResult),
     /// Changes could not be applied because they would break consistency - we need to wait for
     /// pending local CRUD data to be uploaded and acknowledged in a write checkpoint.
-    PendingLocalChanges,
+    PendingLocalChanges {
+        /// Whether rows without local changes have been applied with row-level gating.
+        rows_applied: bool,
+    },
     /// The checkpoint has been applied and changes have been published.
     ChangesApplied { timestamp: TimestampMicros },
 }
@@ -0,0 +1,205 @@
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::bson;
+use crate::error::{PowerSyncError, PowerSyncErrorCause, Result};
+use crate::sync::interface::{StreamChunk, SyncEvent};
+use crate::sync::line::{split_binary_lines, split_text_lines};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ChunkKind {
+    Text,
+    Binary,
+}
+
+/// Reassembles sync lines from chunks of the response stream sent by the sync service.
+///
+/// Chunks can split lines at arbitrary positions. We keep incomplete lines around until the chunk
+/// completing them has been received.
+#[derive(Default)]
+pub struct StreamBuffer {
+    kind: Option<ChunkKind>,
+    pending: Vec<u8>,
+}
+
+impl StreamBuffer {
+    /// Appends a chunk to the buffer, returning lines completed by it.
+    pub fn push(&mut self, chunk: StreamChunk<'_>) -> Result<CompleteLines> {
+        let (kind, data) = match chunk {
+            StreamChunk::Text(data) => (ChunkKind::Text, data),
+            StreamChunk::Binary(data) => (ChunkKind::Binary, data),
+        };
+
+        match self.kind {
+            Some(existing) if existing != kind => {
+                return Err(PowerSyncError::argument_error(
+                    "Can't mix text and binary chunks in a sync iteration",
+                ));
+            }
+            _ => self.kind = Some(kind),
+        }
+
+        let previous_len = self.pending.len();
+        self.pending.extend_from_slice(data);
+
+        let complete = match kind {
+            // Lines spanning multiple chunks have no line break in earlier chunks, so we only need
+            // to search the new chunk.
+            ChunkKind::Text => data
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map(|index| previous_len + index + 1)
+                .unwrap_or(0),
+            ChunkKind::Binary => {
+                let mut offset = 0;
+                loop {
+                    let size = bson::document_size(&self.pending[offset..]).map_err(|e| {
+                        PowerSyncError::sync_protocol_error("invalid binary line", e)
+                    })?;
+
+                    match size {
+                        Some(size) if offset + size <= self.pending.len() => offset += size,
+                        _ => break offset,
+                    }
+                }
+            }
+        };
+
+        let remaining = self.pending.split_off(complete);
+        Ok(CompleteLines {
+            kind,
+            data: mem::replace(&mut self.pending, remaining),
+        })
+    }
+
+    /// Asserts that the buffer doesn't contain a partial line, to be called when the response
+    /// stream has ended.
+    pub fn finish(&mut self) -> Result<()> {
+        let pending = mem::take(&mut self.pending);
+        let is_incomplete = match self.kind {
+            Some(ChunkKind::Text) => !pending.trim_ascii().is_empty(),
+            _ => !pending.is_empty(),
+        };
+
+        if is_incomplete {
+            Err(PowerSyncError::sync_protocol_error(
+                "stream ended with an incomplete line",
+                PowerSyncErrorCause::Unknown,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Complete lines taken out of a [StreamBuffer].
+pub struct CompleteLines {
+    kind: ChunkKind,
+    data: Vec<u8>,
+}
+
+impl CompleteLines {
+    pub fn events(&self) -> Result<Vec<SyncEvent<'_>>> {
+        Ok(match self.kind {
+            ChunkKind::Text => {
+                // We only split after line breaks, so this doesn't cut UTF-8 sequences.
+                let text = str::from_utf8(&self.data).map_err(|_| {
+                    PowerSyncError::sync_protocol_error(
+                        "invalid text line",
+                        PowerSyncErrorCause::Unknown,
+                    )
+                })?;
+
+                split_text_lines(text)
+                    .map(|data| SyncEvent::TextLine { data })
+                    .collect()
+            }
+            ChunkKind::Binary => split_binary_lines(&self.data)?
+                .into_iter()
+                .map(|data| SyncEvent::BinaryLine { data })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text_lines(lines: &CompleteLines) -> Vec<&str> {
+        lines
+            .events()
+            .unwrap()
+            .into_iter()
+            .map(|event| match event {
+                SyncEvent::TextLine { data } => data,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassembles_text_lines() {
+        let mut buffer = StreamBuffer::default();
+
+        let lines = buffer.push(StreamChunk::Text(b"{\"a\"")).unwrap();
+        assert!(text_lines(&lines).is_empty());
+
+        let lines = buffer
+            .push(StreamChunk::Text(b":1}\n{\"b\":2}\n{"))
+            .unwrap();
+        assert_eq!(text_lines(&lines), ["{\"a\":1}", "{\"b\":2}"]);
+
+        // Split in the middle of a multi-byte character
+        let lines = buffer.push(StreamChunk::Text(b"\"\xc3")).unwrap();
+        assert!(text_lines(&lines).is_empty());
+        let lines = buffer.push(StreamChunk::Text(b"\xa4\":1}\r\n\n")).unwrap();
+        assert_eq!(text_lines(&lines), ["{\"ä\":1}"]);
+
+        buffer.finish().unwrap();
+    }
+
+    #[test]
+    fn reassembles_binary_lines() {
+        let mut buffer = StreamBuffer::default();
+        let document = [12u8, 0, 0, 0, 0x10, b'a', 0, 1, 0, 0, 0, 0];
+
+        let lines = buffer.push(StreamChunk::Binary(&document[..2])).unwrap();
+        assert!(lines.events().unwrap().is_empty());
+
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&document[2..]);
+        chunk.extend_from_slice(&document[..6]);
+        let lines = buffer.push(StreamChunk::Binary(&chunk)).unwrap();
+        assert_eq!(lines.events().unwrap().len(), 1);
+
+        let lines = buffer.push(StreamChunk::Binary(&document[6..])).unwrap();
+        assert_eq!(lines.events().unwrap().len(), 1);
+
+        buffer.finish().unwrap();
+    }
+
+    #[test]
+    fn rejects_partial_line_at_end() {
+        let mut buffer = StreamBuffer::default();
+        buffer
+            .push(StreamChunk::Binary(&[12, 0, 0, 0, 0x10]))
+            .unwrap();
+        assert!(buffer.finish().is_err());
+
+        let mut buffer = StreamBuffer::default();
+        buffer.push(StreamChunk::Text(b"{}\n{")).unwrap();
+        assert!(buffer.finish().is_err());
+
+        let mut buffer = StreamBuffer::default();
+        buffer.push(StreamChunk::Text(b"{}\n\r\n")).unwrap();
+        assert!(buffer.finish().is_ok());
+    }
+
+    #[test]
+    fn rejects_mixed_chunks() {
+        let mut buffer = StreamBuffer::default();
+        buffer.push(StreamChunk::Text(b"{}\n")).unwrap();
+        assert!(buffer.push(StreamChunk::Binary(&[])).is_err());
+    }
+}
@@ -2,6 +2,7 @@ use core::{
     fmt::Write,
     future::Future,
     marker::PhantomData,
+    mem,
     pin::Pin,
     task::{Context, Poll, Waker},
 };
@@ -16,6 +17,7 @@ use alloc::{
     vec::Vec,
 };
 use futures_lite::FutureExt;
+use serde_json::value::to_raw_value;
 
 use crate::{
     error::{PowerSyncError, PowerSyncErrorCause, Result},
@@ -25,9 +27,10 @@ use crate::{
         BucketPriority,
         checkpoint::OwnedBucketChecksum,
         diagnostics::DiagnosticsCollector,
+        history::{FailedIteration, SyncHistoryCloseReason, SyncHistoryEntry},
         interface::{
-            CheckpointMode, CheckpointRequestPayload, CloseSyncStream, StartSyncStream,
-            StreamSubscriptionRequest,
+            CheckpointMode, CheckpointRequestPayload, ChecksumFailurePolicy, CloseReason,
+            CloseSyncStream, StartSyncStream, StreamSubscriptionRequest,
         },
         line::{
             BucketSubscriptionReason, DataLine, StreamDescription, StreamSubscriptionError,
@@ -43,7 +46,8 @@ use super::{
     interface::{Instruction, LogSeverity, StreamingSyncRequest, SyncControlRequest, SyncEvent},
     line::{Checkpoint, CheckpointDiff, SyncLine},
     operations::insert_bucket_operations,
-    storage_adapter::{StorageAdapter, SyncLocalResult},
+    storage_adapter::{CheckpointResult, StorageAdapter, SyncLocalResult},
+    stream_buffer::StreamBuffer,
     sync_status::{SyncDownloadProgress, SyncProgressFromCheckpoint, SyncStatusContainer},
 };
 
@@ -58,6 +62,9 @@ pub struct SyncClient {
     db_state: Weak<DatabaseState>,
     /// The current [ClientState] (essentially an optional [StreamingSyncIteration]).
     state: ClientState,
+    /// The last sync iteration, if it has been interrupted by an error that hasn't been recorded
+    /// in `ps_sync_history` yet.
+    failed_iteration: Option<FailedIteration>,
 }
 
 impl SyncClient {
@@ -69,6 +76,7 @@ impl SyncClient {
             adapter,
             db_state: Rc::downgrade(state),
             state: ClientState::Idle,
+            failed_iteration: None,
         })
     }
 
@@ -76,12 +84,15 @@ impl SyncClient {
         match event {
             SyncControlRequest::StartSyncStream(options) => {
                 self.state.tear_down()?;
+                self.record_failed_iteration()?;
 
+                let history = SyncHistoryEntry::start(self.db, self.adapter.now()?)?;
                 let mut handle = SyncIterationHandle::new(
                     self.db,
                     options,
                     self.adapter.clone(),
                     self.db_state.clone(),
+                    history,
                 );
                 let instructions = handle.initialize()?;
                 self.state = ClientState::IterationActive(handle);
@@ -89,32 +100,163 @@ impl SyncClient {
                 Ok(instructions)
             }
             SyncControlRequest::SyncEvent(sync_event) => {
-                let mut active = ActiveEvent::new(sync_event);
+                if let (SyncEvent::StreamEnded, ClientState::IterationActive(handle)) =
+                    (&sync_event, &mut self.state)
+                {
+                    if let Err(e) = handle.buffer.finish() {
+                        return Err(self.fail_iteration(e));
+                    }
+                }
 
+                let received_at = self.event_timestamp()?;
+                self.push_sync_event(sync_event, received_at)
+            }
+            SyncControlRequest::SyncEvents(sync_events) => self.push_sync_events(sync_events),
+            SyncControlRequest::StreamChunk(chunk) => {
                 let ClientState::IterationActive(handle) = &mut self.state else {
                     return Err(PowerSyncError::state_error("No iteration is active"));
                 };
 
-                match handle.run(&mut active) {
-                    Err(e) => {
-                        self.state = ClientState::Idle;
-                        return Err(e);
-                    }
-                    Ok(done) => {
-                        if done {
-                            self.state = ClientState::Idle;
-                        }
-                    }
+                let lines = match handle.buffer.push(chunk) {
+                    Ok(lines) => lines,
+                    Err(e) => return Err(self.fail_iteration(e)),
                 };
 
-                if let Some(recoverable) = active.recoverable_error.take() {
-                    Err(recoverable)
-                } else {
-                    Ok(active.instructions)
+                match lines.events() {
+                    Ok(events) => self.push_sync_events(events),
+                    Err(e) => Err(self.fail_iteration(e)),
+                }
+            }
+            SyncControlRequest::StopSyncStream => {
+                let instructions = self.state.tear_down()?;
+                self.record_failed_iteration()?;
+                Ok(instructions)
+            }
+        }
+    }
+
+    fn push_sync_events<'a>(
+        &mut self,
+        sync_events: Vec<SyncEvent<'a>>,
+    ) -> Result<Vec<Instruction>> {
+        if !self.has_sync_iteration() {
+            return Err(PowerSyncError::state_error("No iteration is active"));
+        }
+
+        let received_at = self.event_timestamp()?;
+        let mut instructions = Vec::new();
+        for (index, sync_event) in sync_events.into_iter().enumerate() {
+            if !self.has_sync_iteration() {
+                // An earlier event has completed the iteration, so the remaining events are no
+                // longer relevant.
+                break;
+            }
+
+            match self.push_sync_event(sync_event, received_at) {
+                Ok(added) => instructions.extend(added),
+                // Earlier lines have been handled already, so we can't fail the whole command
+                // without losing their instructions. We report the error as an instruction
+                // instead, regardless of whether it has ended the iteration.
+                Err(e) if index > 0 => {
+                    coalesce_status_updates(&mut instructions);
+                    instructions.push(self.report_line_error(index, &e)?);
+                    if !self.has_sync_iteration() {
+                        instructions.push(Instruction::CloseSyncStream(Default::default()));
+                    }
+                    return Ok(instructions);
                 }
+                Err(e) => return Err(e),
             }
-            SyncControlRequest::StopSyncStream => self.state.tear_down(),
         }
+
+        coalesce_status_updates(&mut instructions);
+        Ok(instructions)
+    }
+
+    fn push_sync_event<'a>(
+        &mut self,
+        sync_event: SyncEvent<'a>,
+        received_at: Option<TimestampMicros>,
+    ) -> Result<Vec<Instruction>> {
+        let mut active = ActiveEvent::new(sync_event);
+        active.received_at = received_at;
+
+        let ClientState::IterationActive(handle) = &mut self.state else {
+            return Err(PowerSyncError::state_error("No iteration is active"));
+        };
+
+        match handle.run(&mut active) {
+            Err(e) => {
+                return Err(self.fail_iteration(e));
+            }
+            Ok(done) => {
+                if done {
+                    self.state = ClientState::Idle;
+                }
+            }
+        };
+
+        if let Some(recoverable) = active.recoverable_error.take() {
+            Err(recoverable)
+        } else {
+            Ok(active.instructions)
+        }
+    }
+
+    /// Creates an [Instruction::DidFailLine] for an error handling a line of a batch, and makes
+    /// the error available through `powersync_last_error()`.
+    fn report_line_error(&self, line: usize, error: &PowerSyncError) -> Result<Instruction> {
+        let error = to_raw_value(&error.describe()).map_err(PowerSyncError::internal)?;
+        if let Some(state) = self.db_state.upgrade() {
+            state
+                .last_control_error
+                .replace(Some(error.get().to_string()));
+        }
+
+        Ok(Instruction::DidFailLine { line, error })
+    }
+
+    /// The time at which events of a `powersync_control` invocation have been received.
+    ///
+    /// This is only needed for keepalive timeouts. Since reading the time is a query, we only read
+    /// it once for all lines passed to an invocation.
+    fn event_timestamp(&self) -> Result<Option<TimestampMicros>> {
+        Ok(match &self.state {
+            ClientState::IterationActive(handle) if handle.tracks_keepalive => {
+                Some(self.adapter.now()?)
+            }
+            _ => None,
+        })
+    }
+
+    /// Stops the current sync iteration after it has failed with the given error.
+    fn fail_iteration(&mut self, error: PowerSyncError) -> PowerSyncError {
+        if let ClientState::IterationActive(handle) =
+            mem::replace(&mut self.state, ClientState::Idle)
+        {
+            if let Ok(ended_at) = self.adapter.now() {
+                let failed = FailedIteration {
+                    history_id: handle.history_id,
+                    ended_at,
+                    error: error.to_string(),
+                };
+
+                // This write is lost if the SDK rolls back the transaction, which is why we record
+                // the error again when the next iteration starts.
+                let _ = failed.record(self.db);
+                self.failed_iteration = Some(failed);
+            }
+        }
+
+        error
+    }
+
+    fn record_failed_iteration(&mut self) -> Result<()> {
+        if let Some(failed) = self.failed_iteration.take() {
+            failed.record(self.db)?;
+        }
+
+        Ok(())
     }
 
     /// Whether a sync iteration is currently active on the connection.
@@ -123,6 +265,24 @@ impl SyncClient {
     }
 }
 
+/// Removes all but the last [Instruction::UpdateSyncStatus] from `instructions`.
+///
+/// All status updates of a sync iteration reference the same status, which is only serialized
+/// after all events have been handled. So earlier updates would only repeat the final status.
+fn coalesce_status_updates(instructions: &mut Vec<Instruction>) {
+    let is_status_update = |i: &Instruction| matches!(i, Instruction::UpdateSyncStatus { .. });
+    let Some(last) = instructions.iter().rposition(is_status_update) else {
+        return;
+    };
+
+    let mut index = 0;
+    instructions.retain(|instruction| {
+        let keep = index >= last || !is_status_update(instruction);
+        index += 1;
+        keep
+    });
+}
+
 enum ClientState {
     /// No sync iteration is currently active.
     Idle,
@@ -151,6 +311,13 @@ impl ClientState {
 /// render [Instruction]s to return from the function).
 struct SyncIterationHandle {
     future: Pin<Box<dyn Future<Output = Result<CloseSyncStream>>>>,
+    /// Partial lines received through [SyncControlRequest::StreamChunk].
+    buffer: StreamBuffer,
+    /// The id of the `ps_sync_history` row of this iteration.
+    history_id: i64,
+    /// Whether [StartSyncStream::keepalive_timeout_ms] is set, meaning that events need a
+    /// [ActiveEvent::received_at] timestamp.
+    tracks_keepalive: bool,
 }
 
 impl SyncIterationHandle {
@@ -161,18 +328,33 @@ impl SyncIterationHandle {
         options: StartSyncStream,
         adapter: Rc<StorageAdapter>,
         state: Weak<DatabaseState>,
+        history: SyncHistoryEntry,
     ) -> Self {
+        let status = if options.status_deltas {
+            SyncStatusContainer::with_deltas()
+        } else {
+            SyncStatusContainer::new()
+        };
+        let history_id = history.id;
+        let tracks_keepalive = options.keepalive_timeout_ms.is_some();
         let runner = StreamingSyncIteration {
             db,
             validated_but_not_applied: None,
             diagnostics: DiagnosticsCollector::for_options(&options),
+            last_line_at: None,
+            history,
             options,
             state,
             adapter,
-            status: SyncStatusContainer::new(),
+            status,
         };
         let future = runner.run().boxed_local();
-        Self { future }
+        Self {
+            future,
+            buffer: StreamBuffer::default(),
+            history_id,
+            tracks_keepalive,
+        }
     }
 
     /// Forwards a [SyncEvent::Initialize] to the current sync iteration, returning the initial
@@ -224,6 +406,8 @@ struct ActiveEvent<'a> {
     recoverable_error: Option<PowerSyncError>,
     /// Instructions to forward to the client when the `powersync_control` invocation completes.
     instructions: Vec<Instruction>,
+    /// When the event has been received, only set if the iteration tracks a keepalive timeout.
+    received_at: Option<TimestampMicros>,
 }
 
 impl<'a> ActiveEvent<'a> {
@@ -233,6 +417,7 @@ impl<'a> ActiveEvent<'a> {
             event,
             recoverable_error: None,
             instructions: Vec::new(),
+            received_at: None,
         }
     }
 }
@@ -248,6 +433,11 @@ struct StreamingSyncIteration {
     // that it has finished uploading changes.
     validated_but_not_applied: Option<OwnedCheckpoint>,
     diagnostics: Option<DiagnosticsCollector>,
+    /// When we've last received a line (or started connecting), only tracked if
+    /// [StartSyncStream::keepalive_timeout_ms] is set.
+    last_line_at: Option<TimestampMicros>,
+    /// Statistics for this iteration, recorded in `ps_sync_history`.
+    history: SyncHistoryEntry,
 }
 
 impl StreamingSyncIteration {
@@ -341,13 +531,22 @@ impl StreamingSyncIteration {
                             severity: LogSeverity::WARNING,
                             line: format!("Could not apply checkpoint, {checkpoint_result}").into(),
                         });
-                        SyncStateMachineTransition::CloseIteration(Default::default())
+                        self.report_checksum_failure(event, None, checkpoint_result);
+                        SyncStateMachineTransition::CloseIteration(
+                            Default::default(),
+                            SyncHistoryCloseReason::ChecksumFailure,
+                        )
                     }
-                    SyncLocalResult::PendingLocalChanges => {
+                    SyncLocalResult::PendingLocalChanges { rows_applied } => {
+                        let line = if rows_applied {
+                            "Applied rows without local changes. Will apply remaining rows at completed upload or next checkpoint."
+                        } else {
+                            "Could not apply checkpoint due to local data. Will retry at completed upload or next checkpoint."
+                        };
                         event.instructions.push(Instruction::LogLine {
-                                    severity: LogSeverity::INFO,
-                                    line: "Could not apply checkpoint due to local data. Will retry at completed upload or next checkpoint.".into(),
-                                });
+                            severity: LogSeverity::INFO,
+                            line: line.into(),
+                        });
 
                         SyncStateMachineTransition::SyncLocalFailedDueToPendingCrud {
                             validated_but_not_applied: target.clone(),
@@ -395,9 +594,13 @@ impl StreamingSyncIteration {
                             )
                             .into(),
                         });
-                        SyncStateMachineTransition::CloseIteration(Default::default())
+                        self.report_checksum_failure(event, Some(priority), checkpoint_result);
+                        SyncStateMachineTransition::CloseIteration(
+                            Default::default(),
+                            SyncHistoryCloseReason::ChecksumFailure,
+                        )
                     }
-                    SyncLocalResult::PendingLocalChanges => {
+                    SyncLocalResult::PendingLocalChanges { .. } => {
                         // If we have pending uploads, we can't complete new checkpoints outside
                         // of priority 0. We'll resolve this for a complete checkpoint later.
                         SyncStateMachineTransition::Empty
@@ -426,7 +629,10 @@ impl StreamingSyncIteration {
                         .instructions
                         .push(Instruction::FetchCredentials { did_expire: true });
 
-                    SyncStateMachineTransition::CloseIteration(Default::default())
+                    SyncStateMachineTransition::CloseIteration(
+                        Default::default(),
+                        SyncHistoryCloseReason::TokenExpired,
+                    )
                 } else if token.should_prefetch() {
                     event
                         .instructions
@@ -440,9 +646,13 @@ impl StreamingSyncIteration {
                         .adapter
                         .collect_subscription_requests(self.options.include_defaults)?;
                     if updated_request.request != target.explicit_stream_subscriptions().request {
-                        SyncStateMachineTransition::CloseIteration(CloseSyncStream {
-                            hide_disconnect: true,
-                        })
+                        SyncStateMachineTransition::CloseIteration(
+                            CloseSyncStream {
+                                hide_disconnect: true,
+                                ..Default::default()
+                            },
+                            SyncHistoryCloseReason::SubscriptionsChanged,
+                        )
                     } else {
                         SyncStateMachineTransition::Empty
                     }
@@ -464,7 +674,7 @@ impl StreamingSyncIteration {
         target: &mut SyncTarget,
         event: &mut ActiveEvent,
         transition: SyncStateMachineTransition,
-    ) -> Option<CloseSyncStream> {
+    ) -> Option<(CloseSyncStream, SyncHistoryCloseReason)> {
         match transition {
             SyncStateMachineTransition::StartTrackingCheckpoint {
                 progress,
@@ -490,6 +700,7 @@ impl StreamingSyncIteration {
                 }
             }
             SyncStateMachineTransition::DataLineSaved { line } => {
+                self.history.downloaded_operations += line.data.len() as i64;
                 self.status
                     .update(|s| s.track_line(&line), &mut event.instructions);
 
@@ -498,7 +709,9 @@ impl StreamingSyncIteration {
                     diagnostics.handle_data_line(line, &*status, &mut event.instructions);
                 }
             }
-            SyncStateMachineTransition::CloseIteration(close) => return Some(close),
+            SyncStateMachineTransition::CloseIteration(close, reason) => {
+                return Some((close, reason));
+            }
             SyncStateMachineTransition::SyncLocalFailedDueToPendingCrud {
                 validated_but_not_applied,
             } => {
@@ -535,7 +748,7 @@ impl StreamingSyncIteration {
         target: &mut SyncTarget,
         event: &mut ActiveEvent,
         line: &SyncLineWithSource,
-    ) -> Result<Option<CloseSyncStream>> {
+    ) -> Result<Option<(CloseSyncStream, SyncHistoryCloseReason)>> {
         let transition = self.prepare_handling_sync_line(target, event, line)?;
         Ok(self.apply_transition(target, event, transition))
     }
@@ -545,7 +758,7 @@ impl StreamingSyncIteration {
     async fn run(mut self) -> Result<CloseSyncStream> {
         let mut target = SyncTarget::BeforeCheckpoint(self.prepare_request().await?);
 
-        let hide_disconnect = loop {
+        let (mut close, reason) = loop {
             let event = Self::receive_event().await;
 
             let line: SyncLineWithSource = match event.event {
@@ -555,12 +768,14 @@ impl StreamingSyncIteration {
                 SyncEvent::TearDown => {
                     self.status
                         .update(|s| s.disconnect(), &mut event.instructions);
-                    break false;
+                    break (CloseSyncStream::default(), SyncHistoryCloseReason::Stopped);
                 }
                 SyncEvent::TextLine { data } => SyncLineWithSource::from_text(data)?,
                 SyncEvent::BinaryLine { data } => SyncLineWithSource::from_binary(data)?,
                 SyncEvent::UploadFinished => {
+                    let completed_checkpoints = self.history.completed_checkpoints;
                     self.try_applying_write_after_completed_upload(event)?;
+                    self.save_history_if_checkpoint_completed(completed_checkpoints)?;
 
                     continue;
                 }
@@ -572,7 +787,13 @@ impl StreamingSyncIteration {
 
                     if new_request.request != target.explicit_stream_subscriptions().request {
                         // This changes stream requests, start another iteration.
-                        break true;
+                        break (
+                            CloseSyncStream {
+                                hide_disconnect: true,
+                                ..Default::default()
+                            },
+                            SyncHistoryCloseReason::SubscriptionsChanged,
+                        );
                     } else {
                         // Stream request unchanged, but update our references so that we don't
                         // extend the expiry date of previous subscriptions.
@@ -583,25 +804,58 @@ impl StreamingSyncIteration {
                 SyncEvent::ConnectionEstablished => {
                     self.status
                         .update(|s| s.mark_connected(), &mut event.instructions);
+                    self.track_line_received(event.received_at)?;
                     continue;
                 }
                 SyncEvent::StreamEnded => {
                     self.status
                         .update(|s| s.disconnect(), &mut event.instructions);
-                    break false;
+                    break (
+                        CloseSyncStream::default(),
+                        SyncHistoryCloseReason::StreamEnded,
+                    );
                 }
                 SyncEvent::DidRefreshToken => {
                     // Break so that the client SDK starts another iteration.
-                    break true;
+                    break (
+                        CloseSyncStream {
+                            hide_disconnect: true,
+                            ..Default::default()
+                        },
+                        SyncHistoryCloseReason::TokenRefreshed,
+                    );
+                }
+                SyncEvent::Tick => {
+                    if self.has_keepalive_timeout_passed(event.received_at)? {
+                        event.instructions.push(Instruction::LogLine {
+                            severity: LogSeverity::WARNING,
+                            line: "No sync line received within keepalive timeout, closing stream"
+                                .into(),
+                        });
+                        self.status
+                            .update(|s| s.disconnect(), &mut event.instructions);
+                        break (
+                            CloseSyncStream {
+                                reason: Some(CloseReason::KeepaliveTimeout),
+                                ..Default::default()
+                            },
+                            SyncHistoryCloseReason::KeepaliveTimeout,
+                        );
+                    }
+
+                    continue;
                 }
             };
 
             self.status.update_only(|s| s.mark_connected());
+            self.track_line_received(event.received_at)?;
+            self.history.downloaded_bytes += line.source.len() as i64;
 
+            let completed_checkpoints = self.history.completed_checkpoints;
             match self.handle_line(&mut target, event, &line) {
                 Ok(end_iteration) => {
-                    if let Some(options) = end_iteration {
-                        break options.hide_disconnect;
+                    if let Some(end) = end_iteration {
+                        break end;
                     } else {
                         ()
                     }
@@ -611,11 +865,67 @@ impl StreamingSyncIteration {
                 }
                 Err(e) => return Err(e),
             };
+            self.save_history_if_checkpoint_completed(completed_checkpoints)?;
 
             self.status.emit_changes(&mut event.instructions);
         };
 
-        Ok(CloseSyncStream { hide_disconnect })
+        if let Some(backoff) = &self.options.reconnect_backoff {
+            match reason {
+                SyncHistoryCloseReason::Stopped => {}
+                SyncHistoryCloseReason::StreamEnded | SyncHistoryCloseReason::KeepaliveTimeout => {
+                    close.retry_delay_ms =
+                        Some(backoff.delay_ms(self.adapter.record_sync_failure()?));
+                }
+                _ => close.retry_delay_ms = Some(0),
+            }
+        }
+
+        self.history.finish(self.db, self.adapter.now()?, reason)?;
+        Ok(close)
+    }
+
+    /// Persists statistics in `ps_sync_history` after a checkpoint has been applied.
+    ///
+    /// We don't update the history for every line, but since applied checkpoints are committed
+    /// anyway, this keeps the history reasonably up-to-date if the iteration is interrupted.
+    fn save_history_if_checkpoint_completed(&self, completed_before: i64) -> Result<()> {
+        if self.history.completed_checkpoints != completed_before {
+            self.history.save(self.db)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the time the last line has been received, if a keepalive timeout is enabled.
+    ///
+    /// `received_at` is the [ActiveEvent::received_at] timestamp of the event, the current time
+    /// is read if it's not set.
+    fn track_line_received(&mut self, received_at: Option<TimestampMicros>) -> Result<()> {
+        if self.options.keepalive_timeout_ms.is_some() {
+            self.last_line_at = Some(self.event_time(received_at)?);
+        }
+
+        Ok(())
+    }
+
+    /// Whether [StartSyncStream::keepalive_timeout_ms] has passed since receiving the last line.
+    fn has_keepalive_timeout_passed(&self, now: Option<TimestampMicros>) -> Result<bool> {
+        let (Some(timeout), Some(last_line_at)) =
+            (self.options.keepalive_timeout_ms, self.last_line_at)
+        else {
+            return Ok(false);
+        };
+
+        let elapsed_micros = self.event_time(now)?.0.saturating_sub(last_line_at.0);
+        Ok(elapsed_micros >= (timeout as i64).saturating_mul(1000))
+    }
+
+    fn event_time(&self, received_at: Option<TimestampMicros>) -> Result<TimestampMicros> {
+        match received_at {
+            Some(timestamp) => Ok(timestamp),
+            None => self.adapter.now(),
+        }
     }
 
     fn load_progress(&self, checkpoint: &OwnedCheckpoint) -> Result<SyncDownloadProgress> {
@@ -658,6 +968,15 @@ impl StreamingSyncIteration {
                 }
                 self.handle_checkpoint_applied(event, timestamp, checkpoint.write_checkpoint);
             }
+            SyncLocalResult::PendingLocalChanges { rows_applied: true } => {
+                // Rows with local changes written after the upload started are still gated, so we
+                // need to try applying this checkpoint again.
+                event.instructions.push(Instruction::LogLine {
+                    severity: LogSeverity::DEBUG,
+                    line: "Applied rows without local changes after completed upload".into(),
+                });
+                self.validated_but_not_applied = Some(checkpoint);
+            }
             _ => {
                 event.instructions.push(Instruction::LogLine {
                     severity: LogSeverity::WARNING,
@@ -858,11 +1177,20 @@ impl StreamingSyncIteration {
             None => return Err(PowerSyncError::unknown_internal()),
         };
 
-        let result = self
-            .adapter
-            .sync_local(&*state, target, priority, &self.options.schema)?;
+        let result = self.adapter.sync_local(
+            &*state,
+            target,
+            priority,
+            &self.options.schema,
+            self.options.row_level_gating,
+            self.options.checksum_failure_policy,
+        )?;
 
         if let SyncLocalResult::ChangesApplied { timestamp } = result {
+            if self.options.reconnect_backoff.is_some() {
+                self.adapter.reset_sync_failures()?;
+            }
+
             // Update affected stream subscriptions to mark them as synced.
             let mut status = self.status.inner().borrow_mut();
 
@@ -885,6 +1213,22 @@ impl StreamingSyncIteration {
         Ok(result)
     }
 
+    fn report_checksum_failure(
+        &self,
+        event: &mut ActiveEvent,
+        priority: Option<BucketPriority>,
+        result: CheckpointResult,
+    ) {
+        event
+            .instructions
+            .push(Instruction::DidFailChecksumValidation {
+                priority,
+                buckets: result.failed_buckets,
+                quarantined: self.options.checksum_failure_policy
+                    == ChecksumFailurePolicy::Quarantine,
+            });
+    }
+
     /// Prepares a sync iteration by handling the initial [SyncEvent::Initialize].
     ///
     /// This prepares a [StreamingSyncRequest] by fetching local sync state and the requested bucket
@@ -945,6 +1289,9 @@ impl StreamingSyncIteration {
             request,
             checkpoint_request,
         });
+        // Connecting counts towards the keepalive timeout as well, so that we also detect streams
+        // that don't deliver any line.
+        self.track_line_received(event.received_at)?;
         Ok(BeforeCheckpoint {
             local_buckets: local_bucket_names,
             stream_subscriptions: stream_subscriptions,
@@ -972,6 +1319,7 @@ impl StreamingSyncIteration {
         event.instructions.push(Instruction::DidCompleteSync {
             applied_checkpoint_request_id,
         });
+        self.history.completed_checkpoints += 1;
 
         self.status.update(
             |status| status.applied_checkpoint(timestamp, applied_checkpoint_request_id),
@@ -1156,6 +1504,6 @@ enum SyncStateMachineTransition<'a> {
         partial: Option<BucketPriority>,
         timestamp: TimestampMicros,
     },
-    CloseIteration(CloseSyncStream),
+    CloseIteration(CloseSyncStream, SyncHistoryCloseReason),
     Empty,
 }
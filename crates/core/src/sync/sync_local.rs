@@ -1,12 +1,16 @@
+use alloc::borrow::Cow;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use serde::Serialize;
 use serde::ser::SerializeMap;
 
 use crate::error::{PowerSyncError, Result};
 use crate::schema::inspection::ExistingTable;
+use crate::schema::storage::TableStorage;
 use crate::schema::{
     InferredSchemaCache, PendingStatement, PendingStatementValue, RawTable, Schema,
 };
@@ -29,12 +33,25 @@ pub struct PartialSyncOperation<'a> {
     pub args: &'a str,
 }
 
+/// The outcome of [SyncOperation::apply].
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyncApplyResult {
+    /// Nothing has been applied, since there are pending local changes.
+    Blocked,
+    /// Rows without local changes have been applied with row-level gating, but the checkpoint
+    /// hasn't been applied completely.
+    PartiallyApplied,
+    /// All rows have been applied.
+    Applied,
+}
+
 pub struct SyncOperation<'a> {
     state: &'a DatabaseState,
     db: Database,
     schema: ParsedDatabaseSchema<'a>,
     partial: Option<PartialSyncOperation<'a>>,
     time: TimestampMicros,
+    row_level_gating: bool,
 }
 
 impl<'a> SyncOperation<'a> {
@@ -50,6 +67,7 @@ impl<'a> SyncOperation<'a> {
             schema: ParsedDatabaseSchema::new(),
             partial,
             time,
+            row_level_gating: false,
         }
     }
 
@@ -57,6 +75,16 @@ impl<'a> SyncOperation<'a> {
         self.schema.add_from_schema(schema);
     }
 
+    /// Enables applying downloaded rows while there are pending uploads.
+    ///
+    /// By default, no data is published while local changes haven't been acknowledged by the
+    /// sync service. With row-level gating, rows without local changes are applied right away,
+    /// while rows with pending or unacknowledged uploads keep their local version. These rows are
+    /// then updated once the checkpoint can be applied completely.
+    pub fn enable_row_level_gating(&mut self) {
+        self.row_level_gating = true;
+    }
+
     fn can_apply_sync_changes(&self) -> Result<bool> {
         // Don't publish downloaded data until the upload queue is empty (except for downloaded data
         // in priority 0, which is published earlier).
@@ -89,17 +117,52 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
         Ok(true)
     }
 
-    pub fn apply(&mut self) -> Result<i64> {
-        let guard = self.state.sync_local_guard();
+    /// Collects rows with pending local changes, which must not be overwritten by downloaded data
+    /// while row-level gating is active.
+    ///
+    /// These are rows with an entry in `ps_crud`, as well as rows written locally since the last
+    /// complete checkpoint. The latter are still gated after their upload has completed, until the
+    /// write checkpoint acknowledging the upload has been applied. Entries in `ps_updated_rows`
+    /// caused by downloaded data (e.g. removed buckets) don't have `local_write` set and are not
+    /// gated.
+    fn rows_with_local_changes(&self) -> Result<BTreeMap<String, BTreeSet<String>>> {
+        // language=SQLite
+        let statement = self.db.prepare_v2(
+            "\
+SELECT data ->> 'type', data ->> 'id' FROM ps_crud WHERE data ->> 'type' IS NOT NULL
+UNION SELECT row_type, row_id FROM ps_updated_rows WHERE local_write",
+        )?;
 
-        if !self.can_apply_sync_changes()? {
-            return Ok(0);
+        let mut rows = BTreeMap::<String, BTreeSet<String>>::new();
+        while statement.step()? {
+            let row_type = statement.column_text(0)?;
+            let row_id = statement.column_text(1)?;
+
+            rows.entry(row_type.to_string())
+                .or_default()
+                .insert(row_id.to_string());
         }
 
+        Ok(rows)
+    }
+
+    pub fn apply(&mut self) -> Result<SyncApplyResult> {
+        let guard = self.state.sync_local_guard();
+
+        // When we can't apply the checkpoint as a whole, row-level gating may still allow applying
+        // rows without local changes.
+        let gated_rows = if self.can_apply_sync_changes()? {
+            None
+        } else if self.row_level_gating {
+            Some(self.rows_with_local_changes()?)
+        } else {
+            return Ok(SyncApplyResult::Blocked);
+        };
+
         self.collect_tables()?;
         let statement = self.collect_full_operations()?;
         // We're in a transaction, so the schem can't change while we're applying changes.
-        let schema_version = InferredSchemaCache::current_schema_version(self.db)?;
+        let mut schema_version = InferredSchemaCache::current_schema_version(self.db)?;
         let schema_cache = &self.state.inferred_schema_cache;
 
         // We cache the last insert and delete statements for each row
@@ -113,17 +176,40 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
 
         let mut untyped_delete_statement: Option<Statement> = None;
         let mut untyped_insert_statement: Option<Statement> = None;
+        let mut mark_updated_statement: Option<Statement> = None;
+        let mut oplog_entry_statement: Option<Statement> = None;
+        let mut deferred_rows = Vec::<DeferredRow>::new();
 
         while statement.step()? {
             let type_name = statement.column_text(0)?;
             let id = statement.column_text(1)?;
             let data = statement.column_text(2);
 
+            if let Some(gated) = &gated_rows {
+                if gated.get(type_name).is_some_and(|ids| ids.contains(id)) {
+                    // Keep the local version of this row. Since we're marking the operation as
+                    // applied, we need to make sure the row is revisited in the next complete sync
+                    // and stays gated until then.
+                    let mark_updated = match &mark_updated_statement {
+                        Some(stmt) => stmt,
+                        None => mark_updated_statement.insert(self.db.prepare_v2(
+                            "INSERT INTO ps_updated_rows(row_type, row_id, local_write) VALUES(?, ?, 1)
+  ON CONFLICT DO UPDATE SET local_write = 1",
+                        )?),
+                    };
+
+                    mark_updated.reset()?;
+                    mark_updated.bind_text(1, type_name, sqlite::Destructor::STATIC)?;
+                    mark_updated.bind_text(2, id, sqlite::Destructor::STATIC)?;
+                    mark_updated.exec()?;
+                    continue;
+                }
+            }
+
             if let Some(known) = self.schema.tables.get_mut(type_name) {
                 if let Some(raw) = &mut known.raw {
                     match data {
                         Ok(data) => {
-                            let stmt = raw.put_statement(self.db, schema_version, schema_cache)?;
                             let parsed: serde_json::Value = serde_json::from_str(data)
                                 .map_err(PowerSyncError::json_local_error)?;
                             let json_object = parsed.as_object().ok_or_else(|| {
@@ -132,14 +218,51 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
                                 )
                             })?;
 
+                            if raw.definition.schema.add_missing_columns
+                                && raw.definition.put.is_none()
+                                && schema_cache.add_missing_columns(
+                                    self.db,
+                                    schema_version,
+                                    raw.definition,
+                                    json_object,
+                                )?
+                            {
+                                schema_version =
+                                    InferredSchemaCache::current_schema_version(self.db)?;
+                                raw.clear_cached_statements();
+                            }
+
+                            let key = LocalKey::decode(raw.definition, id)?;
+                            let stmt = raw.put_statement(self.db, schema_version, schema_cache)?;
+
+                            let oplog_entry = if stmt.definition.requires_oplog_entry() {
+                                Self::find_oplog_entry(
+                                    self.db,
+                                    self.partial.as_ref(),
+                                    &mut oplog_entry_statement,
+                                    type_name,
+                                    id,
+                                )?
+                            } else {
+                                None
+                            };
+
                             let rest = stmt.render_rest_object(json_object)?;
-                            stmt.bind_for_put(id, &json_object, &rest)?;
+                            stmt.bind_for_put(
+                                id,
+                                key.as_ref(),
+                                data,
+                                &json_object,
+                                &rest,
+                                oplog_entry.as_ref(),
+                            )?;
                             stmt.exec(type_name, id, Some(&parsed))?;
                         }
                         Err(_) => {
+                            let key = LocalKey::decode(raw.definition, id)?;
                             let stmt =
                                 raw.delete_statement(self.db, schema_version, schema_cache)?;
-                            stmt.bind_for_delete(id)?;
+                            stmt.bind_for_delete(id, key.as_ref())?;
                             stmt.exec(type_name, id, None)?;
                         }
                     }
@@ -171,6 +294,7 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
                         delete_statement.reset()?;
                         delete_statement.bind_text(1, id, sqlite::Destructor::STATIC)?;
                         delete_statement.exec()?;
+                        self.state.track_row_id(type_name, id);
                     } else {
                         // INSERT/UPDATE
                         let insert_statement = match &last_insert {
@@ -178,9 +302,7 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
                             _ => {
                                 // Prepare statement when the table changed
                                 let mut statement = SqlBuffer::new();
-                                statement.push_str("REPLACE INTO ");
-                                statement.quote_internal_name(type_name, false);
-                                statement.push_str("(id, data) VALUES (?, ?)");
+                                Self::write_upsert(&mut statement, type_name, &known.storage);
 
                                 let statement = self.db.prepare_v2(&statement.sql)?;
 
@@ -193,10 +315,23 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
                             }
                         };
 
+                        let data = data?;
                         insert_statement.reset()?;
                         insert_statement.bind_text(1, id, sqlite::Destructor::STATIC)?;
-                        insert_statement.bind_text(2, data?, sqlite::Destructor::STATIC)?;
-                        insert_statement.exec()?;
+                        insert_statement.bind_text(2, data, sqlite::Destructor::STATIC)?;
+                        match insert_statement.exec() {
+                            Ok(()) => self.state.track_row_id(type_name, id),
+                            Err(e) if e.is_constraint_violation() => {
+                                // The row might conflict with a row that is deleted or updated
+                                // later in this checkpoint, so try again at the end.
+                                deferred_rows.push(DeferredRow {
+                                    type_name: type_name.to_string(),
+                                    id: id.to_string(),
+                                    data: data.to_string(),
+                                });
+                            }
+                            Err(e) => return Err(e),
+                        }
                     }
                 }
             } else {
@@ -238,11 +373,122 @@ WHERE target.key = '{TARGET_CHECKPOINT_REQUEST_ID_KEY}'
             }
         }
 
+        self.apply_deferred_rows(deferred_rows)?;
+
         self.set_last_applied_op()?;
+        if gated_rows.is_some() {
+            // Rows with local changes have not been applied, so this checkpoint isn't complete.
+            return Ok(SyncApplyResult::PartiallyApplied);
+        }
+
         self.mark_completed()?;
 
         drop(guard);
-        Ok(1)
+        Ok(SyncApplyResult::Applied)
+    }
+
+    /// Writes an upsert statement for the internal table of `type_name`, binding the id to `?1`
+    /// and the JSON data to `?2`.
+    ///
+    /// We use an upsert instead of `REPLACE`, since `REPLACE` would silently delete other rows
+    /// violating a unique index.
+    fn write_upsert(sql: &mut SqlBuffer, type_name: &str, storage: &TableStorage) {
+        sql.push_str("INSERT INTO ");
+        sql.quote_internal_name(type_name, false);
+        storage.write_upsert_from_json(sql, "?1", "?2");
+    }
+
+    /// Applies rows that have violated a constraint when they were first written.
+    ///
+    /// Rows are applied one at a time, so a row can temporarily conflict with another row on a
+    /// unique index, e.g. when a row is deleted and re-created under a new id. At this point,
+    /// all deletes and other writes of the checkpoint have been applied. Since deferred rows may
+    /// also depend on each other, we retry as long as some rows can be written. If none of the
+    /// remaining rows can be written, the checkpoint violates a constraint and we fail.
+    fn apply_deferred_rows(&self, mut rows: Vec<DeferredRow>) -> Result<()> {
+        while !rows.is_empty() {
+            let attempted = rows.len();
+            let mut remaining = Vec::new();
+            let mut error = None;
+
+            for row in rows {
+                let Some(known) = self.schema.tables.get(&row.type_name) else {
+                    continue;
+                };
+
+                let mut sql = SqlBuffer::new();
+                Self::write_upsert(&mut sql, &row.type_name, &known.storage);
+                let stmt = self.db.prepare_v2(&sql.sql)?;
+                stmt.bind_text(1, &row.id, sqlite::Destructor::STATIC)?;
+                stmt.bind_text(2, &row.data, sqlite::Destructor::STATIC)?;
+
+                match stmt.exec() {
+                    Ok(()) => self.state.track_row_id(&row.type_name, &row.id),
+                    Err(e) if e.is_constraint_violation() => {
+                        error.get_or_insert(e.context(format!(
+                            "Could not apply synced row {}/{}",
+                            row.type_name, row.id
+                        )));
+                        remaining.push(row);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if remaining.len() == attempted
+                && let Some(error) = error
+            {
+                return Err(error);
+            }
+
+            rows = remaining;
+        }
+
+        Ok(())
+    }
+
+    /// Finds the latest oplog entry for a row, for raw table statements binding the op id or
+    /// bucket priority.
+    fn find_oplog_entry(
+        db: Database,
+        partial: Option<&PartialSyncOperation>,
+        statement: &mut Option<Statement>,
+        type_name: &str,
+        id: &str,
+    ) -> Result<Option<OplogEntrySource>> {
+        let statement = match statement {
+            Some(stmt) => stmt,
+            None => {
+                // language=SQLite
+                let stmt = db.prepare_v2(
+                    "\
+SELECT r.op_id, b.priority FROM ps_oplog r
+    INNER JOIN ps_buckets b ON b.id = r.bucket
+  WHERE r.row_type = ?1 AND r.row_id = ?2
+    AND (?3 IS NULL OR b.name IN (SELECT value FROM json_each(json_extract(?3, '$.buckets'))))
+  ORDER BY r.op_id DESC
+  LIMIT 1",
+                )?;
+                if let Some(partial) = partial {
+                    stmt.bind_text(3, partial.args, Destructor::STATIC)?;
+                }
+
+                statement.insert(stmt)
+            }
+        };
+
+        statement.reset()?;
+        statement.bind_text(1, type_name, Destructor::STATIC)?;
+        statement.bind_text(2, id, Destructor::STATIC)?;
+
+        Ok(if statement.step()? {
+            Some(OplogEntrySource {
+                op_id: statement.column_int64(0),
+                bucket_priority: statement.column_nullable(1, || Ok(statement.column_int(1)))?,
+            })
+        } else {
+            None
+        })
     }
 
     fn collect_tables(&mut self) -> Result<()> {
@@ -382,6 +628,14 @@ SELECT
     }
 }
 
+/// A synced row that couldn't be written due to a constraint violation, see
+/// [SyncOperation::apply_deferred_rows].
+struct DeferredRow {
+    type_name: String,
+    id: String,
+    data: String,
+}
+
 struct ParsedDatabaseSchema<'a> {
     tables: BTreeMap<String, ParsedSchemaTable<'a>>,
 }
@@ -407,7 +661,7 @@ impl<'a> ParsedDatabaseSchema<'a> {
                 let visible_name = table.name;
 
                 self.tables
-                    .insert(visible_name, ParsedSchemaTable::json_table());
+                    .insert(visible_name, ParsedSchemaTable::json_table(table.storage));
             }
         }
 
@@ -417,6 +671,8 @@ impl<'a> ParsedDatabaseSchema<'a> {
 
 struct ParsedSchemaTable<'a> {
     raw: Option<RawTableWithCachedStatements<'a>>,
+    /// The layout of the `ps_data__` table, for tables that aren't raw tables.
+    storage: TableStorage,
 }
 
 struct RawTableWithCachedStatements<'a> {
@@ -440,6 +696,11 @@ impl<'a> RawTableWithCachedStatements<'a> {
         })
     }
 
+    fn clear_cached_statements(&mut self) {
+        self.cached_put = None;
+        self.cached_delete = None;
+    }
+
     fn put_statement(
         &'_ mut self,
         db: Database,
@@ -474,8 +735,8 @@ impl<'a> RawTableWithCachedStatements<'a> {
 }
 
 impl<'a> ParsedSchemaTable<'a> {
-    pub const fn json_table() -> Self {
-        Self { raw: None }
+    pub const fn json_table(storage: TableStorage) -> Self {
+        Self { raw: None, storage }
     }
 
     pub fn raw(definition: &'a RawTable) -> Self {
@@ -485,10 +746,40 @@ impl<'a> ParsedSchemaTable<'a> {
                 cached_put: None,
                 cached_delete: None,
             }),
+            storage: TableStorage::Json,
         }
     }
 }
 
+/// Values of [PendingStatementValue::KeyColumn] sources, decoded from the synced row id of a raw
+/// table with a local key.
+struct LocalKey<'a> {
+    columns: &'a [String],
+    values: Vec<serde_json::Value>,
+}
+
+impl<'a> LocalKey<'a> {
+    fn decode(table: &'a RawTable, id: &str) -> Result<Option<Self>> {
+        Ok(
+            match (&table.schema.local_key, table.decode_local_key(id)?) {
+                (Some(columns), Some(values)) => Some(Self { columns, values }),
+                _ => None,
+            },
+        )
+    }
+
+    fn value(&self, column: &str) -> Option<&serde_json::Value> {
+        let index = self.columns.iter().position(|c| c == column)?;
+        self.values.get(index)
+    }
+}
+
+/// The oplog entry that has last written a synced row.
+struct OplogEntrySource {
+    op_id: i64,
+    bucket_priority: Option<i32>,
+}
+
 struct PreparedPendingStatement {
     stmt: Statement,
     definition: Rc<PendingStatement>,
@@ -559,14 +850,55 @@ impl PreparedPendingStatement {
         })
     }
 
+    /// Binds a JSON value to the parameter at index `i`.
+    ///
+    /// Objects and arrays are bound as JSON text, strings are bound with the given `destructor`.
+    fn bind_json_value(
+        &self,
+        i: i32,
+        value: Option<&serde_json::Value>,
+        destructor: Destructor,
+    ) -> Result<()> {
+        use serde_json::Value;
+
+        match value {
+            Some(Value::Bool(value)) => self.stmt.bind_int(i, if *value { 1 } else { 0 }),
+            Some(Value::Number(value)) => {
+                if let Some(value) = value.as_f64() {
+                    self.stmt.bind_double(i, value)
+                } else if let Some(value) = value.as_u64() {
+                    self.stmt.bind_int64(i, value as i64)
+                } else {
+                    self.stmt.bind_int64(i, value.as_i64().unwrap())
+                }
+            }
+            Some(Value::String(source)) => self.stmt.bind_text(i, &source, destructor),
+            Some(value @ (Value::Array(_) | Value::Object(_))) => {
+                self.stmt
+                    .bind_text(i, &value.to_string(), Destructor::TRANSIENT)
+            }
+            Some(Value::Null) | None => self.stmt.bind_null(i),
+        }
+    }
+
+    /// Binds a [PendingStatementValue::KeyColumn] source.
+    fn bind_key_column(&self, i: i32, key: Option<&LocalKey>, column: &str) -> Result<()> {
+        let value = key.and_then(|key| key.value(column)).ok_or_else(|| {
+            PowerSyncError::argument_error(format!("{column} is not part of the local key"))
+        })?;
+
+        self.bind_json_value(i, Some(value), Destructor::TRANSIENT)
+    }
+
     pub fn bind_for_put(
         &self,
         id: &str,
+        key: Option<&LocalKey>,
+        data: &str,
         json_data: &serde_json::Map<String, serde_json::Value>,
         rest: &Option<String>,
+        oplog_entry: Option<&OplogEntrySource>,
     ) -> Result<()> {
-        use serde_json::Value;
-
         for (i, source) in self.definition.params.iter().enumerate() {
             let i = (i + 1) as i32;
 
@@ -575,29 +907,45 @@ impl PreparedPendingStatement {
                     self.stmt.bind_text(i, id, Destructor::STATIC)?;
                 }
                 PendingStatementValue::Column(column) => {
-                    match json_data.get(column) {
-                        Some(Value::Bool(value)) => {
-                            self.stmt.bind_int(i, if *value { 1 } else { 0 })
-                        }
-                        Some(Value::Number(value)) => {
-                            if let Some(value) = value.as_f64() {
-                                self.stmt.bind_double(i, value)
-                            } else if let Some(value) = value.as_u64() {
-                                self.stmt.bind_int64(i, value as i64)
-                            } else {
-                                self.stmt.bind_int64(i, value.as_i64().unwrap())
-                            }
-                        }
-                        Some(Value::String(source)) => {
-                            self.stmt.bind_text(i, &source, Destructor::STATIC)
-                        }
-                        _ => self.stmt.bind_null(i),
-                    }?;
+                    self.bind_json_value(i, json_data.get(column), Destructor::STATIC)?;
                 }
                 PendingStatementValue::Rest => {
                     // These are bound later.
                     debug_assert!(self.definition.named_parameters_index.is_some());
                 }
+                PendingStatementValue::Data => {
+                    self.stmt.bind_text(i, data, Destructor::STATIC)?;
+                }
+                PendingStatementValue::JsonPath(path) => match path.resolve(json_data) {
+                    Some(Cow::Borrowed(value)) => {
+                        self.bind_json_value(i, Some(value), Destructor::STATIC)?
+                    }
+                    // Values decoded from nested JSON text don't outlive this call.
+                    Some(Cow::Owned(value)) => {
+                        self.bind_json_value(i, Some(&value), Destructor::TRANSIENT)?
+                    }
+                    None => self.stmt.bind_null(i)?,
+                },
+                PendingStatementValue::Constant(value) => {
+                    self.bind_json_value(i, Some(value), Destructor::STATIC)?;
+                }
+                PendingStatementValue::ColumnWithDefault { name, default } => {
+                    let value = json_data.get(name).unwrap_or(default);
+                    self.bind_json_value(i, Some(value), Destructor::STATIC)?;
+                }
+                PendingStatementValue::BucketPriority => {
+                    match oplog_entry.and_then(|entry| entry.bucket_priority) {
+                        Some(priority) => self.stmt.bind_int(i, priority)?,
+                        None => self.stmt.bind_null(i)?,
+                    }
+                }
+                PendingStatementValue::OpId => match oplog_entry {
+                    Some(entry) => self.stmt.bind_int64(i, entry.op_id)?,
+                    None => self.stmt.bind_null(i)?,
+                },
+                PendingStatementValue::KeyColumn(column) => {
+                    self.bind_key_column(i, key, column)?;
+                }
             }
         }
 
@@ -614,15 +962,22 @@ impl PreparedPendingStatement {
         Ok(())
     }
 
-    pub fn bind_for_delete(&self, id: &str) -> Result<()> {
+    pub fn bind_for_delete(&self, id: &str, key: Option<&LocalKey>) -> Result<()> {
         for (i, source) in self.definition.params.iter().enumerate() {
-            if let PendingStatementValue::Id = source {
-                self.stmt
-                    .bind_text((i + 1) as i32, id, Destructor::STATIC)?;
-            } else {
-                return Err(PowerSyncError::argument_error(
-                    "Raw delete statement parameters must only reference id",
-                ));
+            let i = (i + 1) as i32;
+
+            match source {
+                PendingStatementValue::Id => {
+                    self.stmt.bind_text(i, id, Destructor::STATIC)?;
+                }
+                PendingStatementValue::KeyColumn(column) => {
+                    self.bind_key_column(i, key, column)?;
+                }
+                _ => {
+                    return Err(PowerSyncError::argument_error(
+                        "Raw delete statement parameters must only reference id or key columns",
+                    ));
+                }
             }
         }
 
@@ -19,6 +19,7 @@ use serde::{
 };
 
 use crate::{
+    diff::deep_diff,
     error::PowerSyncError,
     sync::{
         checkpoint::OwnedBucketChecksum, storage_adapter::StorageAdapter,
@@ -233,6 +234,13 @@ impl<'a> AddAssign<&'a BucketProgress> for ProgressCounters {
 pub struct SyncStatusContainer {
     status: Rc<RefCell<DownloadSyncStatus>>,
     last_published_hash: u64,
+    /// When status deltas are enabled, the last status published to the client.
+    deltas: Option<StatusDeltas>,
+}
+
+struct StatusDeltas {
+    sequence: u64,
+    last_published: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 impl SyncStatusContainer {
@@ -240,6 +248,19 @@ impl SyncStatusContainer {
         Self {
             status: Rc::new(RefCell::new(Default::default())),
             last_published_hash: 0,
+            deltas: None,
+        }
+    }
+
+    /// Creates a container emitting [Instruction::UpdateSyncStatusDelta] instead of
+    /// [Instruction::UpdateSyncStatus].
+    pub fn with_deltas() -> Self {
+        Self {
+            deltas: Some(StatusDeltas {
+                sequence: 0,
+                last_published: None,
+            }),
+            ..Self::new()
         }
     }
 
@@ -271,9 +292,34 @@ impl SyncStatusContainer {
         let hash = FxBuildHasher.hash_one(&*status);
         if hash != self.last_published_hash {
             self.last_published_hash = hash;
-            instructions.push(Instruction::UpdateSyncStatus {
-                status: self.status.clone(),
-            });
+
+            let Some(deltas) = &mut self.deltas else {
+                instructions.push(Instruction::UpdateSyncStatus {
+                    status: self.status.clone(),
+                });
+                return;
+            };
+
+            // Unlike regular updates which serialize the shared status when the instructions are
+            // serialized, deltas need to be computed now.
+            let Ok(serde_json::Value::Object(current)) = serde_json::to_value(&*status) else {
+                return;
+            };
+
+            let (snapshot, changes) = match deltas.last_published.take() {
+                None => (true, current.clone()),
+                Some(previous) => (false, deep_diff(previous, current.clone())),
+            };
+            deltas.last_published = Some(current);
+
+            if snapshot || !changes.is_empty() {
+                instructions.push(Instruction::UpdateSyncStatusDelta {
+                    sequence: deltas.sequence,
+                    snapshot,
+                    changes,
+                });
+                deltas.sequence += 1;
+            }
         }
     }
 }
@@ -469,3 +515,32 @@ impl ActiveStreamSubscription {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn emits_status_deltas() {
+        let mut container = SyncStatusContainer::with_deltas();
+        let mut instructions = Vec::new();
+
+        container.update(|s| s.start_connecting(), &mut instructions);
+        container.update(|s| s.mark_connected(), &mut instructions);
+        // Unchanged status, should not emit a delta.
+        container.update(|s| s.mark_connected(), &mut instructions);
+        container.update(
+            |s| s.applied_checkpoint(TimestampMicros(10), None),
+            &mut instructions,
+        );
+
+        assert_eq!(
+            serde_json::to_string(&instructions).unwrap(),
+            concat!(
+                r#"[{"UpdateSyncStatusDelta":{"sequence":0,"snapshot":true,"changes":{"connected":false,"connecting":true,"downloading":null,"priority_status":[],"streams":[]}}},"#,
+                r#"{"UpdateSyncStatusDelta":{"sequence":1,"snapshot":false,"changes":{"connected":true,"connecting":false}}},"#,
+                r#"{"UpdateSyncStatusDelta":{"sequence":2,"snapshot":false,"changes":{"priority_status":[{"has_synced":true,"last_synced_at":10,"priority":2147483647}]}}}]"#,
+            )
+        );
+    }
+}
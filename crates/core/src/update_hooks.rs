@@ -3,13 +3,23 @@ use core::{
     ptr::null_mut,
 };
 
-use alloc::{boxed::Box, rc::Rc};
+use alloc::{
+    boxed::Box,
+    collections::btree_set::BTreeSet,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
 use powersync_sqlite_nostd::{
-    self as sqlite, Connection, Context, ResultCode, Value, bindings::SQLITE_RESULT_SUBTYPE,
+    self as sqlite, ColumnType, Connection, Context, ResultCode, Value,
+    bindings::{SQLITE_DELETE, SQLITE_INSERT, SQLITE_RESULT_SUBTYPE, SQLITE_UPDATE},
 };
+use serde::Serialize;
 
 use crate::{
-    constants::SUBTYPE_JSON, error::PowerSyncError, pre_close_vtab::ensure_has_internal_close_vtab,
+    constants::SUBTYPE_JSON,
+    error::{PowerSyncError, Result as PowerSyncResult},
+    pre_close_vtab::ensure_has_internal_close_vtab,
     state::DatabaseState,
 };
 
@@ -20,20 +30,48 @@ use crate::{
 ///   2. `powersync_update_hooks('get')` returns a JSON array of table names that have been changed
 ///      and comitted since the last `powersync_update_hooks` call.
 ///
+/// Additionally, individual rows can be tracked:
+///
+///   1. `powersync_update_hooks('track_rows', max_rows)` enables row-level tracking, buffering at
+///      most `max_rows` changed rows between calls. Passing `NULL` disables it again.
+///   2. `powersync_update_hooks('get_rows')` returns a JSON object with the changed `tables` (like
+///      `get`), the changed `rows` and whether some rows were not `truncated` because the buffer
+///      was full.
+///
+/// Update hooks only receive rowids, so the `id` of rows in data tables is reported by the
+/// statements writing them: `powersync_crud` and `sync_local` do that for synced tables, and
+/// views of local-only tables call `powersync_track_row_id(type, id)` after writing a row.
+///
 /// The update hooks don't have to be uninstalled manually, that happens when the connection is
 /// closed (`powersync_init()` installs a vtab calling `uninstall_update_hooks()`).
 pub fn register(db: *mut sqlite::sqlite3, state: Rc<DatabaseState>) -> Result<(), ResultCode> {
-    let state = Box::new(HookState { db, state });
+    for nargs in [1, 2] {
+        let state = Box::new(HookState {
+            db,
+            state: state.clone(),
+        });
+
+        db.create_function_v2(
+            "powersync_update_hooks",
+            nargs,
+            sqlite::UTF8 | sqlite::DETERMINISTIC | SQLITE_RESULT_SUBTYPE,
+            Some(Box::into_raw(state) as *mut c_void),
+            Some(powersync_update_hooks),
+            None,
+            None,
+            Some(destroy_function),
+        )?;
+    }
 
     db.create_function_v2(
-        "powersync_update_hooks",
-        1,
-        sqlite::UTF8 | sqlite::DETERMINISTIC | SQLITE_RESULT_SUBTYPE,
-        Some(Box::into_raw(state) as *mut c_void),
-        Some(powersync_update_hooks),
+        "powersync_track_row_id",
+        2,
+        sqlite::UTF8,
+        Some(Rc::into_raw(state) as *mut c_void),
+        Some(powersync_track_row_id),
         None,
         None,
-        Some(destroy_function),
+        Some(DatabaseState::destroy_rc),
     )?;
     Ok(())
 }
@@ -96,6 +134,8 @@ extern "C" fn powersync_update_hooks(
         }
         "get" => {
             let state = unsafe { user_data.as_ref().unwrap_unchecked() };
+            // Rows are reported with get_rows, but we don't want to report them twice.
+            state.state.take_row_changes();
             let formatted = serde_json::to_string(&state.state.take_updates())
                 .map_err(PowerSyncError::internal);
             match formatted {
@@ -106,6 +146,40 @@ extern "C" fn powersync_update_hooks(
                 Err(e) => e.apply_to_ctx("powersync_update_hooks", ctx),
             }
         }
+        "track_rows" => {
+            let state = unsafe { user_data.as_ref().unwrap_unchecked() };
+            let max_rows = match args.get(1) {
+                Some(arg) if arg.value_type() != ColumnType::Null => {
+                    let max_rows = arg.int64();
+                    if max_rows <= 0 {
+                        PowerSyncError::argument_error("max_rows must be positive")
+                            .apply_to_ctx("powersync_update_hooks", ctx);
+                        return;
+                    }
+                    Some(max_rows as usize)
+                }
+                _ => None,
+            };
+
+            state.state.set_row_tracking(max_rows);
+            ctx.result_null();
+        }
+        "get_rows" => {
+            let state = unsafe { user_data.as_ref().unwrap_unchecked() };
+            let formatted = (|| {
+                let changes = state.state.take_row_changes().unwrap_or_default();
+                let tables = state.state.take_updates();
+                changes.into_json(tables)
+            })();
+
+            match formatted {
+                Ok(result) => {
+                    ctx.result_text_transient(&result);
+                    ctx.result_subtype(SUBTYPE_JSON);
+                }
+                Err(e) => e.apply_to_ctx("powersync_update_hooks", ctx),
+            }
+        }
         _ => {
             ctx.result_error("Unknown operation");
             ctx.result_error_code(ResultCode::MISUSE);
@@ -113,12 +187,26 @@ extern "C" fn powersync_update_hooks(
     };
 }
 
+extern "C" fn powersync_track_row_id(
+    ctx: *mut sqlite::context,
+    argc: c_int,
+    argv: *mut *mut sqlite::value,
+) {
+    let args = sqlite::args!(argc, argv);
+    let state = unsafe { DatabaseState::from_context(&ctx) };
+
+    if args[1].value_type() != ColumnType::Null {
+        state.track_row_id(args[0].text(), args[1].text());
+    }
+    ctx.result_null();
+}
+
 unsafe extern "C" fn update_hook_impl(
     ctx: *mut c_void,
-    _kind: c_int,
+    kind: c_int,
     _db: *const c_char,
     table: *const c_char,
-    _rowid: i64,
+    rowid: i64,
 ) {
     let state = unsafe { (ctx as *const DatabaseState).as_ref().unwrap_unchecked() };
     let table = unsafe { CStr::from_ptr(table) };
@@ -127,6 +215,9 @@ unsafe extern "C" fn update_hook_impl(
     };
 
     state.track_update(table);
+    if let Some(kind) = RowChangeKind::from_sqlite(kind) {
+        state.track_row_change(table, rowid, kind);
+    }
 }
 
 unsafe extern "C" fn commit_hook_impl(ctx: *mut c_void) -> c_int {
@@ -162,3 +253,212 @@ fn check_previous(desc: &'static str, expected: &Rc<DatabaseState>, previous: *c
         }
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl RowChangeKind {
+    fn from_sqlite(op: c_int) -> Option<Self> {
+        match op as u32 {
+            SQLITE_INSERT => Some(Self::Insert),
+            SQLITE_UPDATE => Some(Self::Update),
+            SQLITE_DELETE => Some(Self::Delete),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowChangeSource {
+    /// The write was made by the user, e.g. through a view.
+    Local,
+    /// The write was made by the sync client while applying downloaded data in `sync_local`.
+    Remote,
+}
+
+/// A change to a single row, as reported by `powersync_update_hooks('get_rows')`.
+#[derive(Debug, Serialize)]
+pub struct RowChange {
+    table: String,
+    rowid: i64,
+    /// For `ps_data__` and `ps_data_local__` tables, the `id` of the affected row.
+    ///
+    /// This is only known for writes made through views or by the sync client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    op: RowChangeKind,
+    source: RowChangeSource,
+}
+
+/// Buffered row-level changes, split into those of the current transaction and those that have
+/// been committed.
+#[derive(Default, Debug)]
+pub struct RowChangeBuffer {
+    pub max_rows: usize,
+    pending: Vec<RowChange>,
+    pending_truncated: bool,
+    committed: Vec<RowChange>,
+    committed_truncated: bool,
+}
+
+impl RowChangeBuffer {
+    /// Whether we report individual rows of the table.
+    ///
+    /// Internal PowerSync tables (like `ps_oplog`) are written frequently while syncing, so we only
+    /// track rows of data tables and user-defined tables.
+    fn is_tracked_table(table: &str) -> bool {
+        if table.starts_with("ps_data__") || table.starts_with("ps_data_local__") {
+            true
+        } else {
+            !(table.starts_with("ps_") || table.starts_with("sqlite_"))
+        }
+    }
+
+    pub fn track(&mut self, table: &str, rowid: i64, op: RowChangeKind, remote: bool) {
+        if !Self::is_tracked_table(table) {
+            return;
+        }
+
+        if self.pending.len() + self.committed.len() >= self.max_rows {
+            // Clients need to fall back to table-level updates for this transaction.
+            self.pending_truncated = true;
+            return;
+        }
+
+        self.pending.push(RowChange {
+            table: table.to_string(),
+            rowid,
+            id: None,
+            op,
+            source: if remote {
+                RowChangeSource::Remote
+            } else {
+                RowChangeSource::Local
+            },
+        });
+    }
+
+    /// Attaches the `id` of a row that has just been written to the change recorded for that
+    /// write.
+    ///
+    /// Writers report ids immediately after writing a row, so the change is the last one we've
+    /// recorded. Earlier changes are never updated, as their rowid may have been reused since.
+    pub fn attach_id(&mut self, type_name: &str, id: &str) {
+        let Some(last) = self.pending.last_mut() else {
+            return;
+        };
+
+        let is_data_table = last.table.strip_prefix("ps_data__") == Some(type_name)
+            || last.table.strip_prefix("ps_data_local__") == Some(type_name);
+        if is_data_table && last.id.is_none() {
+            last.id = Some(id.to_string());
+        }
+    }
+
+    pub fn commit(&mut self) {
+        self.committed.append(&mut self.pending);
+        self.committed_truncated |= core::mem::take(&mut self.pending_truncated);
+    }
+
+    pub fn rollback(&mut self) {
+        self.pending.clear();
+        self.pending_truncated = false;
+    }
+
+    /// Takes committed changes, leaving the buffer for pending changes and options intact.
+    pub fn take_committed(&mut self) -> Self {
+        Self {
+            max_rows: self.max_rows,
+            committed: core::mem::take(&mut self.committed),
+            committed_truncated: core::mem::take(&mut self.committed_truncated),
+            ..Default::default()
+        }
+    }
+
+    /// Serializes committed changes for `powersync_update_hooks('get_rows')`.
+    fn into_json(self, tables: BTreeSet<String>) -> PowerSyncResult<String> {
+        #[derive(Serialize)]
+        struct RowChanges {
+            tables: BTreeSet<String>,
+            rows: Vec<RowChange>,
+            truncated: bool,
+        }
+
+        serde_json::to_string(&RowChanges {
+            tables,
+            rows: self.committed,
+            truncated: self.committed_truncated,
+        })
+        .map_err(PowerSyncError::internal)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tracks_committed_rows() {
+        let mut buffer = RowChangeBuffer {
+            max_rows: 10,
+            ..Default::default()
+        };
+
+        buffer.track("ps_data__users", 1, RowChangeKind::Insert, false);
+        buffer.attach_id("users", "a");
+        buffer.track("ps_oplog", 1, RowChangeKind::Insert, true);
+        buffer.track("custom", 3, RowChangeKind::Delete, true);
+        assert!(buffer.committed.is_empty());
+
+        buffer.commit();
+        buffer.track("custom", 4, RowChangeKind::Update, false);
+        buffer.rollback();
+
+        let committed = buffer.take_committed();
+        assert_eq!(committed.committed.len(), 2);
+        assert_eq!(committed.committed[0].id.as_deref(), Some("a"));
+        assert_eq!(committed.committed[1].source, RowChangeSource::Remote);
+        assert!(buffer.committed.is_empty());
+    }
+
+    #[test]
+    fn attaches_ids_to_last_change() {
+        let mut buffer = RowChangeBuffer {
+            max_rows: 10,
+            ..Default::default()
+        };
+
+        buffer.track("ps_data_local__drafts", 1, RowChangeKind::Delete, false);
+        buffer.track("custom", 1, RowChangeKind::Insert, false);
+        buffer.attach_id("drafts", "a");
+        buffer.track("ps_data_local__drafts", 1, RowChangeKind::Insert, false);
+        buffer.attach_id("drafts", "b");
+        buffer.commit();
+
+        let committed = buffer.take_committed();
+        assert_eq!(committed.committed[0].id, None);
+        assert_eq!(committed.committed[2].id.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn truncates() {
+        let mut buffer = RowChangeBuffer {
+            max_rows: 1,
+            ..Default::default()
+        };
+
+        buffer.track("custom", 1, RowChangeKind::Insert, false);
+        buffer.track("custom", 2, RowChangeKind::Insert, false);
+        buffer.commit();
+
+        let committed = buffer.take_committed();
+        assert_eq!(committed.committed.len(), 1);
+        assert!(committed.committed_truncated);
+    }
+}
@@ -173,6 +173,10 @@ impl Statement {
         self.stmt.column_int64(i)
     }
 
+    pub fn column_value(&self, i: i32) -> Result<*mut sqlite::value> {
+        self.stmt.column_value(i).map_err(|e| self.map_error(e))
+    }
+
     pub fn reset(&self) -> Result<()> {
         self.stmt.reset().map_err(|e| self.map_error(e))?;
         Ok(())
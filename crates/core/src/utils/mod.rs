@@ -121,4 +121,15 @@ pub fn gen_uuid() -> Uuid {
     id
 }
 
+/// Returns 80 random bits, generated with the same source used for [gen_uuid].
+pub fn gen_random_bits() -> u128 {
+    // All bits of a version 4 UUID are random, except for the version (in byte 6) and the variant
+    // (in byte 8).
+    let uuid = gen_uuid().into_bytes();
+    let mut bytes = [0u8; 16];
+    bytes[6..12].copy_from_slice(&uuid[0..6]);
+    bytes[12..16].copy_from_slice(&uuid[9..13]);
+    u128::from_be_bytes(bytes)
+}
+
 pub const MAX_OP_ID: &str = "9223372036854775807";
@@ -100,6 +100,15 @@ impl SqlBuffer {
         );
     }
 
+    /// Writes a select statement throwing in triggers if the `old` and `new` key expressions
+    /// differ.
+    pub fn check_key_not_changed(&mut self, old: &str, new: &str) {
+        let _ = write!(
+            self,
+            "SELECT CASE WHEN ({old} IS NOT {new}) THEN RAISE (FAIL, 'Cannot update id') END;\n"
+        );
+    }
+
     /// Writes a select statement throwing in triggers if `NEW.id` is null or not a string.
     pub fn check_id_valid(&mut self) {
         self.push_str(
@@ -107,6 +116,16 @@ impl SqlBuffer {
         );
     }
 
+    /// Writes a statement reporting the id of a row that has just been written to the internal
+    /// table of a local-only table to update hooks.
+    ///
+    /// For synced tables, `powersync_crud` does that.
+    pub fn track_row_id(&mut self, type_name: &str, id_expr: &str) {
+        self.push_str("SELECT powersync_track_row_id(");
+        let _ = self.string_literal().write_str(type_name);
+        let _ = write!(self, ", {id_expr});\n");
+    }
+
     /// Writes an `INSERT INTO powersync_crud` statement.
     pub fn insert_into_powersync_crud(
         &mut self,
@@ -201,13 +220,13 @@ impl SqlBuffer {
         Ok(())
     }
 
-    pub fn powersync_crud_manual_put(&mut self, name: &str, json_fragment: &str) {
+    pub fn powersync_crud_manual_put(&mut self, name: &str, id_expr: &str, json_fragment: &str) {
         self.push_str("INSERT INTO powersync_crud_(data) VALUES(json_object('op', 'PUT', 'type', ");
         let _ = self.string_literal().write_str(name);
 
         let _ = write!(
             self,
-            ", 'id', NEW.id, 'data', json(powersync_diff('{{}}', {:}))));",
+            ", 'id', {id_expr}, 'data', json(powersync_diff('{{}}', {:}))));",
             json_fragment,
         );
     }
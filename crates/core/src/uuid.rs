@@ -1,16 +1,20 @@
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::string::ToString;
-use core::ffi::c_int;
+use core::cell::RefCell;
+use core::ffi::{c_int, c_void};
 
 use powersync_sqlite_nostd as sqlite;
 use powersync_sqlite_nostd::{Connection, Context};
 use sqlite::ResultCode;
+use uuid::Uuid;
 
 use crate::create_sqlite_text_fn;
 use crate::error::PowerSyncError;
-use crate::utils::gen_uuid;
+use crate::utils::database::Database;
+use crate::utils::{gen_random_bits, gen_uuid};
 
 fn uuid_v4_impl(
     _ctx: *mut sqlite::context,
@@ -22,6 +26,111 @@ fn uuid_v4_impl(
 
 create_sqlite_text_fn!(uuid_v4, uuid_v4_impl, "gen_random_uuid");
 
+/// Generates identifiers consisting of a 48-bit millisecond timestamp followed by random bits.
+///
+/// Identifiers are monotonic within a connection: When multiple ids are generated in the same
+/// millisecond (or when the clock goes backwards), we increment the random bits of the previous
+/// id instead of generating new ones.
+#[derive(Default)]
+struct MonotonicIdGenerator {
+    last_timestamp: u64,
+    last_random: u128,
+}
+
+impl MonotonicIdGenerator {
+    const TIMESTAMP_MASK: u64 = (1 << 48) - 1;
+
+    fn next(
+        &mut self,
+        timestamp: u64,
+        random_bits: u32,
+        random: impl FnOnce() -> u128,
+    ) -> (u64, u128) {
+        let timestamp = timestamp & Self::TIMESTAMP_MASK;
+        let mask = (1u128 << random_bits) - 1;
+
+        if timestamp > self.last_timestamp {
+            self.last_timestamp = timestamp;
+            self.last_random = random() & mask;
+        } else {
+            self.last_random = (self.last_random + 1) & mask;
+            if self.last_random == 0 {
+                // The random bits overflowed, so move to the next millisecond.
+                self.last_timestamp = (self.last_timestamp + 1) & Self::TIMESTAMP_MASK;
+            }
+        }
+
+        (self.last_timestamp, self.last_random)
+    }
+
+    fn next_for_connection(
+        &mut self,
+        db: Database,
+        random_bits: u32,
+    ) -> Result<(u64, u128), PowerSyncError> {
+        // language=SQLite
+        let stmt = db.prepare_v2("SELECT CAST(unixepoch('subsec') * 1000 AS INTEGER)")?;
+        stmt.step()?;
+        let now = stmt.column_int64(0);
+
+        Ok(self.next(now as u64, random_bits, gen_random_bits))
+    }
+}
+
+/// Formats a timestamp and 74 random bits as a version 7 UUID, as defined in RFC 9562.
+fn format_uuid_v7(timestamp: u64, random: u128) -> String {
+    let rand_a = (random >> 62) as u16 & 0xfff;
+    let rand_b = random as u64 & ((1 << 62) - 1);
+
+    let mut bytes = [0u8; 16];
+    bytes[0..6].copy_from_slice(&timestamp.to_be_bytes()[2..8]);
+    bytes[6..8].copy_from_slice(&(0x7000 | rand_a).to_be_bytes());
+    bytes[8..16].copy_from_slice(&((0b10 << 62) | rand_b).to_be_bytes());
+
+    Uuid::from_bytes(bytes).hyphenated().to_string()
+}
+
+/// Formats a timestamp and 80 random bits as a ULID, using Crockford's base32 alphabet.
+fn format_ulid(timestamp: u64, random: u128) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+    let value = (timestamp as u128) << 80 | random;
+    let mut encoded = String::with_capacity(26);
+    for i in 0..26 {
+        // 26 characters encode 130 bits, the first character only uses the 3 upper bits.
+        let shift = 125 - 5 * i;
+        encoded.push(ALPHABET[(value >> shift) as usize & 0x1f] as char);
+    }
+
+    encoded
+}
+
+extern "C" fn uuid_v7(ctx: *mut sqlite::context, _argc: c_int, _argv: *mut *mut sqlite::value) {
+    let generator = unsafe { &*(ctx.user_data() as *const RefCell<MonotonicIdGenerator>) };
+    match generator
+        .borrow_mut()
+        .next_for_connection(ctx.db_handle().into(), 74)
+    {
+        Ok((timestamp, random)) => ctx.result_text_transient(&format_uuid_v7(timestamp, random)),
+        Err(e) => e.apply_to_ctx("uuid_v7", ctx),
+    }
+}
+
+extern "C" fn ulid(ctx: *mut sqlite::context, _argc: c_int, _argv: *mut *mut sqlite::value) {
+    let generator = unsafe { &*(ctx.user_data() as *const RefCell<MonotonicIdGenerator>) };
+    match generator
+        .borrow_mut()
+        .next_for_connection(ctx.db_handle().into(), 80)
+    {
+        Ok((timestamp, random)) => ctx.result_text_transient(&format_ulid(timestamp, random)),
+        Err(e) => e.apply_to_ctx("ulid", ctx),
+    }
+}
+
+extern "C" fn destroy_generator(ptr: *mut c_void) {
+    drop(unsafe { Box::from_raw(ptr as *mut RefCell<MonotonicIdGenerator>) });
+}
+
 pub fn register(db: *mut sqlite::sqlite3) -> Result<(), ResultCode> {
     db.create_function_v2(
         "gen_random_uuid",
@@ -45,5 +154,66 @@ pub fn register(db: *mut sqlite::sqlite3) -> Result<(), ResultCode> {
         None,
     )?;
 
+    db.create_function_v2(
+        "uuid_v7",
+        0,
+        sqlite::UTF8,
+        Some(Box::into_raw(Box::new(RefCell::new(MonotonicIdGenerator::default()))) as *mut c_void),
+        Some(uuid_v7),
+        None,
+        None,
+        Some(destroy_generator),
+    )?;
+
+    db.create_function_v2(
+        "ulid",
+        0,
+        sqlite::UTF8,
+        Some(Box::into_raw(Box::new(RefCell::new(MonotonicIdGenerator::default()))) as *mut c_void),
+        Some(ulid),
+        None,
+        None,
+        Some(destroy_generator),
+    )?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_uuid_v7() {
+        // Example from RFC 9562, Appendix A.6
+        assert_eq!(
+            format_uuid_v7(0x017F22E279B0, (0xCC3 << 62) | 0x18C4DC0C0C07398F),
+            "017f22e2-79b0-7cc3-98c4-dc0c0c07398f"
+        );
+    }
+
+    #[test]
+    fn formats_ulid() {
+        assert_eq!(format_ulid(0, 0), "00000000000000000000000000");
+        assert_eq!(
+            format_ulid((1 << 48) - 1, (1 << 80) - 1),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+        // Timestamp from the example in the ULID specification
+        assert_eq!(format_ulid(1469918176385, 0), "01ARYZ6S410000000000000000");
+    }
+
+    #[test]
+    fn is_monotonic() {
+        let mut generator = MonotonicIdGenerator::default();
+
+        assert_eq!(generator.next(10, 8, || 0xfe), (10, 0xfe));
+        // Same timestamp, increment random bits.
+        assert_eq!(generator.next(10, 8, || unreachable!()), (10, 0xff));
+        // Overflow moves to the next millisecond.
+        assert_eq!(generator.next(10, 8, || unreachable!()), (11, 0));
+        // Clock going backwards doesn't break ordering.
+        assert_eq!(generator.next(5, 8, || unreachable!()), (11, 1));
+        assert_eq!(generator.next(12, 8, || 0x1234), (12, 0x34));
+    }
+}
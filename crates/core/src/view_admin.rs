@@ -83,8 +83,9 @@ fn powersync_clear_impl(ctx: *mut sqlite::context, args: &[*mut sqlite::value])
 DELETE FROM ps_crud;
 DELETE FROM ps_untyped;
 DELETE FROM ps_updated_rows;
-DELETE FROM ps_kv WHERE key != 'client_id';
+DELETE FROM ps_kv WHERE key NOT IN ('client_id', 'schema', 'schema_hash');
 DELETE FROM ps_stream_subscriptions;
+DELETE FROM ps_sync_history;
 ",
     )?;
     clear_has_synced(local_db)?;
@@ -6,6 +6,7 @@ use core::fmt::{Write, from_fn};
 use core::mem;
 
 use crate::error::{PowerSyncError, Result};
+use crate::schema::storage::TableStorage;
 use crate::schema::{ColumnFilter, SchemaTable, Table};
 use crate::utils::{InsertIntoCrud, SqlBuffer, WriteType};
 
@@ -14,6 +15,7 @@ pub fn powersync_view_sql(table_info: &Table) -> String {
     let view_name = &table_info.view_name();
     let local_only = table_info.options.flags.local_only();
     let include_metadata = table_info.options.flags.include_metadata();
+    let storage = TableStorage::for_table(table_info);
 
     let mut sql = SqlBuffer::new();
     sql.push_str("CREATE VIEW ");
@@ -41,7 +43,7 @@ pub fn powersync_view_sql(table_info: &Table) -> String {
         for column in &table_info.columns {
             let sql = sql.element();
 
-            sql.json_extract_and_cast("data", &column.name, &column.type_name);
+            storage.write_column_reference(sql, &column.name, &column.type_name);
         }
 
         if include_metadata {
@@ -78,7 +80,9 @@ pub fn powersync_trigger_delete_sql(table_info: &Table) -> Result<String> {
     sql.quote_internal_name(name, local_only);
     sql.push_str(" WHERE id = OLD.id;\n");
 
-    if !local_only {
+    if local_only {
+        sql.track_row_id(name, "OLD.id");
+    } else {
         // We also need to record the write in powersync_crud.
         sql.insert_into_powersync_crud(InsertIntoCrud {
             op: WriteType::Delete,
@@ -137,14 +141,30 @@ pub fn powersync_trigger_insert_sql(table_info: &Table) -> Result<String> {
     if insert_only {
         // This is using the manual powersync_crud_ instead of powersync_crud because insert-only
         // writes shouldn't prevent us from receiving new data.
-        sql.powersync_crud_manual_put(name, &json_fragment);
+        sql.powersync_crud_manual_put(name, "NEW.id", &json_fragment);
     } else {
         // Insert into the underlying data table.
         sql.push_str("INSERT INTO ");
         sql.quote_internal_name(name, local_only);
-        let _ = write!(&mut sql, " SELECT NEW.id, {json_fragment};\n");
+        if table_info.options.flags.typed_columns() {
+            sql.push_str("(id");
+            for column in &table_info.columns {
+                sql.comma();
+                let _ = sql.identifier().write_str(&column.name);
+            }
+            sql.push_str(") SELECT NEW.id");
+            for column in &table_info.columns {
+                sql.comma();
+                write_cast_new_column(&mut sql, &column.name, &column.type_name);
+            }
+            sql.push_str(";\n");
+        } else {
+            let _ = write!(&mut sql, " SELECT NEW.id, {json_fragment};\n");
+        }
 
-        if !local_only {
+        if local_only {
+            sql.track_row_id(name, "NEW.id");
+        } else {
             // Record write into powersync_crud
             sql.insert_into_powersync_crud(InsertIntoCrud {
                 op: WriteType::Insert,
@@ -193,13 +213,39 @@ pub fn powersync_trigger_update_sql(table_info: &Table) -> Result<String> {
     let json_fragment_new = table_columns_to_json_object("NEW", &as_schema_table)?;
     let json_fragment_old = table_columns_to_json_object("OLD", &as_schema_table)?;
 
-    // UPDATE {internal_name} SET data = {json_fragment_new} WHERE id = NEW.id;
-    sql.push_str("UPDATE ");
-    sql.quote_internal_name(name, local_only);
-    let _ = write!(
-        &mut sql,
-        " SET data = {json_fragment_new} WHERE id = NEW.id;\n"
-    );
+    if table_info.options.flags.typed_columns() {
+        // UPDATE {internal_name} SET "a" = CAST(NEW."a" as ...), ... WHERE id = NEW.id;
+        // Values in the _rest column are not visible in the view, so they're kept as-is.
+        if !table_info.columns.is_empty() {
+            sql.push_str("UPDATE ");
+            sql.quote_internal_name(name, local_only);
+            sql.push_str(" SET ");
+            let mut assignments = sql.comma_separated();
+            for column in &table_info.columns {
+                let sql = assignments.element();
+                let _ = sql.identifier().write_str(&column.name);
+                sql.push_str(" = ");
+                write_cast_new_column(sql, &column.name, &column.type_name);
+            }
+            sql.push_str(" WHERE id = NEW.id;\n");
+
+            if local_only {
+                sql.track_row_id(name, "NEW.id");
+            }
+        }
+    } else {
+        // UPDATE {internal_name} SET data = {json_fragment_new} WHERE id = NEW.id;
+        sql.push_str("UPDATE ");
+        sql.quote_internal_name(name, local_only);
+        let _ = write!(
+            &mut sql,
+            " SET data = {json_fragment_new} WHERE id = NEW.id;\n"
+        );
+
+        if local_only {
+            sql.track_row_id(name, "NEW.id");
+        }
+    }
 
     if !local_only {
         // Also forward write to powersync_crud vtab.
@@ -226,6 +272,13 @@ pub fn powersync_trigger_update_sql(table_info: &Table) -> Result<String> {
     Ok(sql.sql)
 }
 
+/// Writes `CAST(NEW."name" as type)`, the value to store in a typed column of an internal table.
+fn write_cast_new_column(sql: &mut SqlBuffer, name: &str, type_name: &str) {
+    sql.push_str("CAST(NEW.");
+    let _ = sql.identifier().write_str(name);
+    let _ = write!(sql, " as {type_name})");
+}
+
 /// Given a query returning column names, return a JSON object fragment for a trigger.
 ///
 /// Example output with prefix "NEW": "json_object('id', NEW.id, 'name', NEW.name, 'age', NEW.age)".
@@ -240,6 +293,19 @@ pub fn table_columns_to_json_object_with_filter<'a>(
     prefix: &str,
     table: &'a SchemaTable<'a>,
     filter: Option<&'a ColumnFilter>,
+) -> Result<String> {
+    column_names_to_json_object(
+        prefix,
+        table
+            .column_names()
+            .filter(|name| filter.is_none_or(|filter| filter.matches(name))),
+    )
+}
+
+/// Like [table_columns_to_json_object], but for an explicit list of column names.
+pub fn column_names_to_json_object<'a>(
+    prefix: &str,
+    columns: impl Iterator<Item = &'a str>,
 ) -> Result<String> {
     // floor(SQLITE_MAX_FUNCTION_ARG / 2).
     // To keep databases portable, we use the default limit of 100 args for this,
@@ -262,14 +328,7 @@ pub fn table_columns_to_json_object_with_filter<'a>(
         buffer.sql
     }
 
-    let mut columns = table.column_names();
-    while let Some(name) = columns.next() {
-        if let Some(filter) = filter
-            && !filter.matches(name)
-        {
-            continue;
-        }
-
+    for name in columns {
         total_columns += 1;
         // SQLITE_MAX_COLUMN - 1 (because of the id column)
         if total_columns > 1999 {
@@ -336,7 +395,7 @@ mod test {
     use alloc::{string::ToString, vec};
 
     use crate::{
-        schema::{Column, Table},
+        schema::{Column, Table, TableInfoFlags},
         views::{
             powersync_trigger_delete_sql, powersync_trigger_insert_sql,
             powersync_trigger_update_sql, powersync_view_sql, table_columns_to_json_object,
@@ -358,6 +417,7 @@ mod test {
                 },
             ],
             indexes: vec![],
+            previous_names: vec![],
             options: Default::default(),
         };
     }
@@ -417,4 +477,47 @@ END"#
                 .contains("powersync_crud")
         );
     }
+
+    #[test]
+    fn local_only_tracks_row_ids() {
+        let mut table = test_table();
+        table.options.flags.0 = 1; // local-only bit
+
+        assert!(
+            powersync_trigger_insert_sql(&table)
+                .unwrap()
+                .contains("SELECT powersync_track_row_id('table', NEW.id);")
+        );
+        assert!(
+            powersync_trigger_update_sql(&table)
+                .unwrap()
+                .contains("SELECT powersync_track_row_id('table', NEW.id);")
+        );
+        assert!(
+            powersync_trigger_delete_sql(&table)
+                .unwrap()
+                .contains("SELECT powersync_track_row_id('table', OLD.id);")
+        );
+    }
+
+    #[test]
+    fn typed_columns() {
+        let mut table = test_table();
+        table.options.flags.0 = TableInfoFlags::TYPED_COLUMNS;
+
+        assert_eq!(
+            powersync_view_sql(&table),
+            r#"CREATE VIEW "table"("id", "a", "b") AS SELECT id, "a", "b" FROM "ps_data__table" -- powersync-auto-generated"#
+        );
+
+        let insert = powersync_trigger_insert_sql(&table).unwrap();
+        assert!(insert.contains(
+            r#"INSERT INTO "ps_data__table"(id, "a", "b") SELECT NEW.id, CAST(NEW."a" as text), CAST(NEW."b" as integer);"#
+        ));
+
+        let update = powersync_trigger_update_sql(&table).unwrap();
+        assert!(update.contains(
+            r#"UPDATE "ps_data__table" SET "a" = CAST(NEW."a" as text), "b" = CAST(NEW."b" as integer) WHERE id = NEW.id;"#
+        ));
+    }
 }
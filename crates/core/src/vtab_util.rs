@@ -1,13 +1,16 @@
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::string::ToString;
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_void};
 
 use powersync_sqlite_nostd as sqlite;
-use powersync_sqlite_nostd::VTab;
+use powersync_sqlite_nostd::bindings::SQLITE_VTAB_DIRECTONLY;
+use powersync_sqlite_nostd::{Connection, Context, VTab};
 use sqlite::ResultCode;
 
-use crate::error::PowerSyncError;
+use crate::error::{PowerSyncError, Result};
+use crate::utils::database::{Database, Statement};
 
 // For insert-only virtual tables, there are many functions that have to be defined, even if they're
 // not intended to be used. We return MISUSE for each.
@@ -66,7 +69,7 @@ pub extern "C" fn vtab_no_close(_cursor: *mut sqlite::vtab_cursor) -> c_int {
 
 pub fn vtab_result<T, E: Into<PowerSyncError>>(
     vtab: *mut sqlite::vtab,
-    result: Result<T, E>,
+    result: core::result::Result<T, E>,
 ) -> c_int {
     if let Err(error) = result {
         let error = error.into();
@@ -77,3 +80,204 @@ pub fn vtab_result<T, E: Into<PowerSyncError>>(
         ResultCode::OK as c_int
     }
 }
+
+/// A read-only, eponymous virtual table returning rows of a SQL query.
+///
+/// This allows exposing internal state as a table-valued function (e.g.
+/// `SELECT * FROM powersync_sync_history()`) without adding views to the schema of the database.
+pub struct QueryTable {
+    /// The `CREATE TABLE` statement declaring columns of the virtual table.
+    pub schema: &'static str,
+    /// The query returning rows of the table, with columns matching [Self::schema].
+    pub query: &'static str,
+}
+
+impl QueryTable {
+    pub fn register(
+        &'static self,
+        db: *mut sqlite::sqlite3,
+        name: &str,
+    ) -> core::result::Result<(), ResultCode> {
+        db.create_module_v2(
+            name,
+            &QUERY_TABLE_MODULE,
+            Some(self as *const QueryTable as *mut c_void),
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+struct QueryVirtualTable {
+    base: sqlite::vtab,
+    db: Database,
+    table: &'static QueryTable,
+}
+
+#[repr(C)]
+struct QueryCursor {
+    base: sqlite::vtab_cursor,
+    stmt: Option<Statement>,
+    has_row: bool,
+    row_id: i64,
+}
+
+impl QueryCursor {
+    fn step(&mut self) -> Result<()> {
+        if let Some(stmt) = &self.stmt {
+            self.has_row = stmt.step()?;
+            self.row_id += 1;
+        }
+
+        Ok(())
+    }
+}
+
+extern "C" fn query_connect(
+    db: *mut sqlite::sqlite3,
+    aux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
+    vtab: *mut *mut sqlite::vtab,
+    _err: *mut *mut c_char,
+) -> c_int {
+    let table = unsafe { &*(aux as *const QueryTable) };
+    if let Err(rc) = sqlite::declare_vtab(db, table.schema) {
+        return rc as c_int;
+    }
+
+    unsafe {
+        let tab = Box::into_raw(Box::new(QueryVirtualTable {
+            base: sqlite::vtab {
+                nRef: 0,
+                pModule: core::ptr::null(),
+                zErrMsg: core::ptr::null_mut(),
+            },
+            db: db.into(),
+            table,
+        }));
+        *vtab = tab.cast::<sqlite::vtab>();
+        let _ = sqlite::vtab_config(db, SQLITE_VTAB_DIRECTONLY);
+    }
+    ResultCode::OK as c_int
+}
+
+extern "C" fn query_disconnect(vtab: *mut sqlite::vtab) -> c_int {
+    unsafe {
+        drop(Box::from_raw(vtab as *mut QueryVirtualTable));
+    }
+    ResultCode::OK as c_int
+}
+
+extern "C" fn query_best_index(
+    _vtab: *mut sqlite::vtab,
+    _index_info: *mut sqlite::index_info,
+) -> c_int {
+    // We always run the full query, SQLite evaluates constraints on the returned rows.
+    ResultCode::OK as c_int
+}
+
+extern "C" fn query_open(vtab: *mut sqlite::vtab, cursor: *mut *mut sqlite::vtab_cursor) -> c_int {
+    let c = Box::into_raw(Box::new(QueryCursor {
+        base: sqlite::vtab_cursor { pVtab: vtab },
+        stmt: None,
+        has_row: false,
+        row_id: 0,
+    }));
+    unsafe { *cursor = c.cast::<sqlite::vtab_cursor>() };
+
+    ResultCode::OK as c_int
+}
+
+extern "C" fn query_close(cursor: *mut sqlite::vtab_cursor) -> c_int {
+    unsafe {
+        drop(Box::from_raw(cursor as *mut QueryCursor));
+    }
+    ResultCode::OK as c_int
+}
+
+extern "C" fn query_filter(
+    cursor: *mut sqlite::vtab_cursor,
+    _idx_num: c_int,
+    _idx_str: *const c_char,
+    _argc: c_int,
+    _argv: *mut *mut sqlite::value,
+) -> c_int {
+    let cursor = unsafe { &mut *(cursor as *mut QueryCursor) };
+    let vtab = cursor.base.pVtab;
+    let tab = unsafe { &*(vtab as *const QueryVirtualTable) };
+
+    let result = (|| -> Result<()> {
+        cursor.stmt = Some(tab.db.prepare_v2(tab.table.query)?);
+        cursor.row_id = 0;
+        cursor.step()
+    })();
+    vtab_result(vtab, result)
+}
+
+extern "C" fn query_next(cursor: *mut sqlite::vtab_cursor) -> c_int {
+    let cursor = unsafe { &mut *(cursor as *mut QueryCursor) };
+    let vtab = cursor.base.pVtab;
+    let result = cursor.step();
+    vtab_result(vtab, result)
+}
+
+extern "C" fn query_eof(cursor: *mut sqlite::vtab_cursor) -> c_int {
+    let cursor = unsafe { &*(cursor as *const QueryCursor) };
+    (!cursor.has_row) as c_int
+}
+
+extern "C" fn query_column(
+    cursor: *mut sqlite::vtab_cursor,
+    ctx: *mut sqlite::context,
+    col_num: c_int,
+) -> c_int {
+    let cursor = unsafe { &*(cursor as *const QueryCursor) };
+    let Some(stmt) = &cursor.stmt else {
+        return ResultCode::MISUSE as c_int;
+    };
+
+    match stmt.column_value(col_num) {
+        Ok(value) => {
+            ctx.result_value(value);
+            ResultCode::OK as c_int
+        }
+        Err(e) => vtab_result(cursor.base.pVtab, Err::<(), _>(e)),
+    }
+}
+
+extern "C" fn query_rowid(cursor: *mut sqlite::vtab_cursor, row_id: *mut sqlite::int64) -> c_int {
+    let cursor = unsafe { &*(cursor as *const QueryCursor) };
+    unsafe { *row_id = cursor.row_id };
+    ResultCode::OK as c_int
+}
+
+// Eponymous-only (no xCreate) and read-only (no xUpdate) virtual table.
+static QUERY_TABLE_MODULE: sqlite::module = sqlite::module {
+    iVersion: 0,
+    xCreate: None,
+    xConnect: Some(query_connect),
+    xBestIndex: Some(query_best_index),
+    xDisconnect: Some(query_disconnect),
+    xDestroy: None,
+    xOpen: Some(query_open),
+    xClose: Some(query_close),
+    xFilter: Some(query_filter),
+    xNext: Some(query_next),
+    xEof: Some(query_eof),
+    xColumn: Some(query_column),
+    xRowid: Some(query_rowid),
+    xUpdate: None,
+    xBegin: None,
+    xSync: None,
+    xCommit: None,
+    xRollback: None,
+    xFindFunction: None,
+    xRename: None,
+    xSavepoint: None,
+    xRelease: None,
+    xRollbackTo: None,
+    xShadowName: None,
+    xIntegrity: None,
+};
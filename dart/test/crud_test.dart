@@ -62,6 +62,39 @@ void main() {
       expect(r5['diff'], equals('{"b":"test"}'));
     });
 
+    test('powersync_diff_deep', () {
+      final [row] = db.select('select powersync_diff_deep(?, ?) as diff', [
+        '{"doc":{"title":"a","tags":["x"]},"n":1}',
+        '{"doc":{"title":"b","tags":["x"]},"n":1}',
+      ]);
+      expect(row['diff'], '{"doc":{"title":"b"}}');
+    });
+
+    test('powersync_diff_json_patch and powersync_json_patch', () {
+      const old = '{"doc":{"title":"a","body":"text"},"n":1}';
+      const updated = '{"doc":{"title":"b"},"n":1,"m":null}';
+
+      final [diff] = db.select(
+          'select powersync_diff_json_patch(?, ?) as patch', [old, updated]);
+      expect(json.decode(diff['patch']), [
+        {'op': 'replace', 'path': '/doc/title', 'value': 'b'},
+        {'op': 'remove', 'path': '/doc/body'},
+        {'op': 'add', 'path': '/m', 'value': null},
+      ]);
+
+      final [patched] = db.select(
+          'select powersync_json_patch(?, ?) as doc', [old, diff['patch']]);
+      expect(json.decode(patched['doc']), json.decode(updated));
+
+      expect(
+        () => db.select('select powersync_json_patch(?, ?)', [
+          old,
+          '[{"op":"test","path":"/n","value":2}]',
+        ]),
+        throwsA(isA<SqliteException>()),
+      );
+    });
+
     var runCrudTest = (int numberOfColumns) {
       var columns = [];
       for (var i = 0; i < numberOfColumns; i++) {
@@ -268,7 +301,7 @@ INSERT INTO ps_kv(key, value) VALUES
               ]);
 
           expect(db.select('SELECT * FROM ps_updated_rows'), [
-            {'row_type': 'users', 'row_id': 'foo'}
+            {'row_type': 'users', 'row_id': 'foo', 'local_write': 1}
           ]);
           expect(db.select(r"SELECT * FROM ps_buckets WHERE name = '$local'"),
               isEmpty);
@@ -672,6 +705,45 @@ INSERT INTO ps_kv(key, value) VALUES
       expect(db.select('SELECT * FROM ps_crud'), hasLength(1));
     });
 
+    test('accepts time-ordered ids', () {
+      db
+        ..executeInTx('select powersync_replace_schema(?)', [
+          json.encode({
+            'tables': [
+              {
+                'name': 'items',
+                'columns': [
+                  {'name': 'col', 'type': 'text'}
+                ],
+              }
+            ]
+          })
+        ]);
+
+      for (var i = 0; i < 100; i++) {
+        db.execute(
+            'INSERT INTO items (id, col) VALUES (uuid_v7(), ?), (ulid(), ?)',
+            ['uuid', 'ulid']);
+      }
+
+      for (final (kind, pattern) in [
+        ('uuid', RegExp(r'^[0-9a-f]{8}-[0-9a-f]{4}-7[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$')),
+        ('ulid', RegExp(r'^[0-9A-HJKMNP-TV-Z]{26}$')),
+      ]) {
+        final ids = db
+            .select('SELECT id FROM items WHERE col = ? ORDER BY rowid', [kind])
+            .map((row) => row.columnAt(0) as String)
+            .toList();
+        expect(ids, hasLength(100));
+        expect(ids, everyElement(matches(pattern)));
+
+        // Ids are monotonic within a connection.
+        final sorted = [...ids]..sort();
+        expect(ids, sorted);
+      }
+      expect(db.select('SELECT * FROM ps_crud'), hasLength(200));
+    });
+
     test('preserves values in text column', () {
       db
         ..executeInTx('select powersync_replace_schema(?)', [
@@ -725,6 +797,204 @@ INSERT INTO ps_kv(key, value) VALUES
       ]);
     });
 
+    group('upload batches', () {
+      setUp(() {
+        db.executeInTx('select powersync_replace_schema(?)', [
+          json.encode({
+            'tables': [
+              {
+                'name': 'items',
+                'columns': [
+                  {'name': 'col', 'type': 'text'}
+                ],
+              }
+            ]
+          })
+        ]);
+
+        db.execute('BEGIN');
+        db.execute("INSERT INTO items (id, col) VALUES ('a', 'first')");
+        db.execute("INSERT INTO items (id, col) VALUES ('b', 'second')");
+        db.execute('COMMIT');
+        db.execute("UPDATE items SET col = 'updated' WHERE id = 'a'");
+      });
+
+      Object? control(String operation, Object? payload) {
+        db.execute('BEGIN');
+        try {
+          final [row] = db.select(
+              'SELECT powersync_control(?, ?) AS r', [operation, payload]);
+          db.execute('COMMIT');
+          return row['r'];
+        } on Object {
+          db.execute('ROLLBACK');
+          rethrow;
+        }
+      }
+
+      Object? nextBatch([Object? options]) {
+        final result = control(
+            'next_crud_batch', options != null ? json.encode(options) : null);
+        return json.decode(result as String);
+      }
+
+      test('returns all entries', () {
+        expect(nextBatch(), {
+          'entries': [
+            {
+              'client_id': 1,
+              'tx_id': 1,
+              'op': 'PUT',
+              'id': 'a',
+              'type': 'items',
+              'data': {'col': 'first'},
+            },
+            {
+              'client_id': 2,
+              'tx_id': 1,
+              'op': 'PUT',
+              'id': 'b',
+              'type': 'items',
+              'data': {'col': 'second'},
+            },
+            {
+              'client_id': 3,
+              'tx_id': 2,
+              'op': 'PATCH',
+              'id': 'a',
+              'type': 'items',
+              'data': {'col': 'updated'},
+            },
+          ],
+          'has_more': false,
+        });
+      });
+
+      test('respects limits', () {
+        expect(nextBatch({'limit': 1}),
+            {'entries': hasLength(1), 'has_more': true});
+        expect(nextBatch({'max_bytes': 1}),
+            {'entries': hasLength(1), 'has_more': true});
+        expect(nextBatch({'single_transaction': true}),
+            {'entries': hasLength(2), 'has_more': true});
+      });
+
+      test('completes batches', () {
+        expect(json.decode(control('complete_crud_batch', 2) as String),
+            {'has_more': true, 'instructions': isEmpty});
+        expect(nextBatch(), containsPair('entries', hasLength(1)));
+
+        expect(
+          json.decode(control('complete_crud_batch',
+              json.encode({'last_id': 3, 'checkpoint_request_id': '4'})) as String),
+          {'has_more': false, 'instructions': isEmpty},
+        );
+        expect(nextBatch(), {'entries': isEmpty, 'has_more': false});
+        // Transaction ids restart once all entries have been uploaded.
+        expect(db.select('SELECT next_tx FROM ps_tx'), [
+          {'next_tx': 1}
+        ]);
+        expect(
+          db.select(
+              "SELECT value FROM ps_kv WHERE key = 'target_checkpoint_request_id'"),
+          [
+            {'value': 4}
+          ],
+        );
+      });
+
+      test('are not compacted while in flight', () {
+        db.execute('BEGIN');
+        db.execute("UPDATE items SET col = 'x' WHERE id = 'b'");
+        db.execute("UPDATE items SET col = 'y' WHERE id = 'b'");
+        db.execute('COMMIT');
+
+        Object? compact() {
+          db.execute('BEGIN');
+          final [result] =
+              db.select('SELECT powersync_compact_crud() AS removed');
+          db.execute('COMMIT');
+          return result['removed'];
+        }
+
+        expect(nextBatch({'limit': 4}), containsPair('entries', hasLength(4)));
+        expect(compact(), 0);
+
+        control('complete_crud_batch', 3);
+        expect(compact(), 1);
+      });
+    });
+
+    test('powersync_compact_crud', () {
+      db.executeInTx('select powersync_replace_schema(?)', [
+        json.encode({
+          'tables': [
+            {
+              'name': 'items',
+              'columns': [
+                {'name': 'a', 'type': 'text'},
+                {'name': 'b', 'type': 'text'},
+              ],
+            }
+          ]
+        })
+      ]);
+
+      db.execute('BEGIN');
+      db.execute("INSERT INTO items (id, a) VALUES ('row', '1')");
+      db.execute("UPDATE items SET a = '2' WHERE id = 'row'");
+      db.execute("UPDATE items SET b = '3' WHERE id = 'row'");
+      db.execute("INSERT INTO items (id, a) VALUES ('other', '1')");
+      db.execute("UPDATE items SET a = '4' WHERE id = 'row'");
+      db.execute('COMMIT');
+
+      // Separate transaction, must not be merged with the previous entries.
+      db.execute("UPDATE items SET a = '5' WHERE id = 'row'");
+      db.execute("DELETE FROM items WHERE id = 'row'");
+
+      db.execute('BEGIN');
+      final [result] =
+          db.select('SELECT powersync_compact_crud() AS removed');
+      db.execute('COMMIT');
+      expect(result['removed'], 2);
+
+      final entries = db
+          .select('SELECT tx_id, data FROM ps_crud ORDER BY id')
+          .map((row) => {'tx': row['tx_id'], ...json.decode(row['data'])})
+          .toList();
+      expect(entries, [
+        {
+          'tx': 1,
+          'op': 'PUT',
+          'id': 'row',
+          'type': 'items',
+          'data': {'a': '2', 'b': '3'}
+        },
+        {
+          'tx': 1,
+          'op': 'PUT',
+          'id': 'other',
+          'type': 'items',
+          'data': {'a': '1'}
+        },
+        {
+          'tx': 1,
+          'op': 'PATCH',
+          'id': 'row',
+          'type': 'items',
+          'data': {'a': '4'}
+        },
+        {
+          'tx': 2,
+          'op': 'PATCH',
+          'id': 'row',
+          'type': 'items',
+          'data': {'a': '5'}
+        },
+        {'tx': 3, 'op': 'DELETE', 'id': 'row', 'type': 'items'},
+      ]);
+    });
+
     group('insert only', () {
       test('smoke test', () {
         db
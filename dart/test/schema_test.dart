@@ -122,6 +122,306 @@ void main() {
       });
     });
 
+    group('typed columns', () {
+      Map<String, Object?> schema(
+          {required bool typed,
+          List<Map<String, Object?>> columns = const [
+            {"name": "name", "type": "TEXT"},
+            {"name": "age", "type": "INTEGER"},
+          ]}) {
+        return {
+          "tables": [
+            {
+              "name": "users",
+              "typed_columns": typed,
+              "columns": columns,
+              "indexes": [
+                {
+                  "name": "age",
+                  "columns": [
+                    {"name": "age", "type": "INTEGER", "ascending": true}
+                  ],
+                }
+              ],
+            },
+          ]
+        };
+      }
+
+      test('creates typed columns', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: true))]);
+
+        expect(
+          db.select("SELECT name, type FROM pragma_table_info('ps_data__users')"),
+          [
+            {'name': 'id', 'type': 'TEXT'},
+            {'name': 'name', 'type': 'TEXT'},
+            {'name': 'age', 'type': 'INTEGER'},
+            {'name': '_rest', 'type': 'TEXT'},
+          ],
+        );
+        expect(
+          db.select(
+              "SELECT sql FROM sqlite_schema WHERE name = 'ps_data__users__age'"),
+          [
+            {
+              'sql':
+                  'CREATE INDEX "ps_data__users__age" ON "ps_data__users"("age")'
+            }
+          ],
+        );
+
+        db.execute("INSERT INTO users (id, name, age) VALUES ('a', 'user', '42')");
+        expect(db.select('SELECT * FROM ps_data__users'), [
+          {'id': 'a', 'name': 'user', 'age': 42, '_rest': null}
+        ]);
+
+        db.execute("UPDATE users SET age = 43 WHERE id = 'a'");
+        expect(db.select('SELECT * FROM users'), [
+          {'id': 'a', 'name': 'user', 'age': 43}
+        ]);
+        expect(db.select('SELECT op, data FROM ps_crud'), hasLength(2));
+      });
+
+      test('rejects _rest column', () {
+        expect(
+          () => db.executeInTx('SELECT powersync_replace_schema(?)', [
+            json.encode(schema(typed: true, columns: [
+              {"name": "_rest", "type": "TEXT"}
+            ]))
+          ]),
+          throwsA(isA<SqliteException>()),
+        );
+      });
+
+      test('migrates between layouts', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: false))]);
+        db.execute('INSERT INTO ps_data__users (id, data) VALUES (?, ?)', [
+          'synced',
+          json.encode({'name': 'user', 'age': 42, 'unknown': 'value'})
+        ]);
+
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: true))]);
+        expect(db.select('SELECT * FROM ps_data__users'), [
+          {
+            'id': 'synced',
+            'name': 'user',
+            'age': 42,
+            '_rest': json.encode({'unknown': 'value'}),
+          }
+        ]);
+        expect(db.select('SELECT * FROM users'), [
+          {'id': 'synced', 'name': 'user', 'age': 42}
+        ]);
+
+        // Removing a typed column keeps its values in _rest.
+        db.executeInTx('SELECT powersync_replace_schema(?)', [
+          json.encode(schema(typed: true, columns: [
+            {"name": "name", "type": "TEXT"},
+          ]))
+        ]);
+        expect(db.select('SELECT * FROM ps_data__users'), [
+          {
+            'id': 'synced',
+            'name': 'user',
+            '_rest': json.encode({'unknown': 'value', 'age': 42}),
+          }
+        ]);
+
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: false))]);
+        final [row] = db.select('SELECT * FROM ps_data__users');
+        expect(row['id'], 'synced');
+        expect(json.decode(row['data'] as String),
+            {'name': 'user', 'age': 42, 'unknown': 'value'});
+        expect(db.select('SELECT * FROM users'), [
+          {'id': 'synced', 'name': 'user', 'age': 42}
+        ]);
+      });
+
+      test('moves rows from and to ps_untyped', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: true))]);
+        db.execute("INSERT INTO users (id, name, age) VALUES ('a', 'user', 1)");
+
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode({"tables": []})]);
+        final [untyped] = db.select('SELECT * FROM ps_untyped');
+        expect(json.decode(untyped['data'] as String),
+            {'name': 'user', 'age': 1});
+
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema(typed: true))]);
+        expect(db.select('SELECT * FROM ps_untyped'), isEmpty);
+        expect(db.select('SELECT * FROM ps_data__users'), [
+          {'id': 'a', 'name': 'user', 'age': 1, '_rest': null}
+        ]);
+      });
+    });
+
+    group('previous names', () {
+      Map<String, Object?> schema(String name,
+          {List<String> previousNames = const [],
+          bool localOnly = false,
+          bool uniqueName = false}) {
+        return {
+          'tables': [
+            {
+              'name': name,
+              'previous_names': previousNames,
+              'local_only': localOnly,
+              'columns': [
+                {'name': 'name', 'type': 'TEXT'},
+              ],
+              if (uniqueName)
+                'indexes': [
+                  {
+                    'name': 'name',
+                    'unique': true,
+                    'columns': [
+                      {'name': 'name', 'type': 'TEXT', 'ascending': true}
+                    ],
+                  }
+                ],
+            }
+          ]
+        };
+      }
+
+      test('renames synced tables', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('users'))]);
+        db.execute('''
+INSERT INTO ps_data__users (id, data) VALUES ('synced', '{"name":"synced"}');
+INSERT INTO ps_buckets (name) VALUES ('a');
+INSERT INTO ps_oplog (bucket, op_id, row_type, row_id, key, data, hash)
+  VALUES (1, 1, 'users', 'synced', 'users/synced/null', '{"name":"synced"}', 0);
+INSERT INTO users (id, name) VALUES ('local', 'local');
+''');
+
+        db.executeInTx('SELECT powersync_replace_schema(?)', [
+          json.encode(schema('customers', previousNames: ['users']))
+        ]);
+
+        expect(db.select('SELECT * FROM customers ORDER BY id'), [
+          {'id': 'local', 'name': 'local'},
+          {'id': 'synced', 'name': 'synced'},
+        ]);
+        expect(() => db.select('SELECT * FROM ps_data__users'),
+            throwsA(isA<SqliteException>()));
+        expect(db.select('SELECT * FROM ps_untyped'), isEmpty);
+        expect(db.select('SELECT row_type, key FROM ps_oplog'), [
+          {'row_type': 'customers', 'key': 'customers/synced/null'}
+        ]);
+        expect(db.select('SELECT row_type FROM ps_updated_rows'), [
+          {'row_type': 'customers'}
+        ]);
+
+        // Pending and new writes are uploaded with the new name.
+        db.execute("UPDATE customers SET name = 'updated' WHERE id = 'local'");
+        final types = db
+            .select('SELECT data ->> \'type\' AS type FROM ps_crud ORDER BY id')
+            .map((row) => row['type']);
+        expect(types, ['customers', 'customers']);
+
+        // Applying the schema again doesn't change anything.
+        db.executeInTx('SELECT powersync_replace_schema(?)', [
+          json.encode(schema('customers', previousNames: ['users']))
+        ]);
+        expect(db.select('SELECT * FROM customers'), hasLength(2));
+      });
+
+      test('merges rows synced under the new name', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('users'))]);
+        db.execute('''
+INSERT INTO ps_data__users (id, data) VALUES ('a', '{"name":"old"}');
+INSERT INTO ps_untyped (type, id, data) VALUES
+  ('customers', 'a', '{"name":"new"}'),
+  ('customers', 'b', '{"name":"b"}');
+INSERT INTO ps_updated_rows (row_type, row_id) VALUES
+  ('users', 'a'), ('customers', 'a');
+''');
+
+        db.executeInTx('SELECT powersync_replace_schema(?)', [
+          json.encode(schema('customers', previousNames: ['users']))
+        ]);
+
+        expect(db.select('SELECT * FROM customers ORDER BY id'), [
+          {'id': 'a', 'name': 'new'},
+          {'id': 'b', 'name': 'b'},
+        ]);
+        expect(db.select('SELECT * FROM ps_untyped'), isEmpty);
+        expect(db.select('SELECT row_type, row_id FROM ps_updated_rows'), [
+          {'row_type': 'customers', 'row_id': 'a'}
+        ]);
+      });
+
+      test('does not replace rows conflicting with unique indexes', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('users', uniqueName: true))]);
+        db.execute('''
+INSERT INTO ps_data__users (id, data) VALUES ('a', '{"name":"same"}');
+INSERT INTO ps_untyped (type, id, data) VALUES
+  ('customers', 'b', '{"name":"same"}');
+''');
+
+        expect(
+          () => db.executeInTx('SELECT powersync_replace_schema(?)', [
+            json.encode(schema('customers',
+                previousNames: ['users'], uniqueName: true))
+          ]),
+          throwsA(isA<SqliteException>()),
+        );
+        expect(db.select('SELECT * FROM users'), [
+          {'id': 'a', 'name': 'same'}
+        ]);
+      });
+
+      test('renames local-only tables', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('drafts', localOnly: true))]);
+        db.execute("INSERT INTO drafts (id, name) VALUES ('a', 'draft')");
+
+        final [diff] = db.select('SELECT powersync_schema_diff(?) AS diff', [
+          json.encode(
+              schema('notes', previousNames: ['drafts'], localOnly: true))
+        ]);
+        expect(json.decode(diff['diff'] as String)['tables'], [
+          {
+            'name': 'notes',
+            'previous_name': 'drafts',
+            'action': 'rename',
+            'rows': 1,
+            'untyped_rows': 0,
+            'deletes_rows': false,
+          }
+        ]);
+
+        db.executeInTx('SELECT powersync_replace_schema(?)', [
+          json.encode(
+              schema('notes', previousNames: ['drafts'], localOnly: true))
+        ]);
+        expect(db.select('SELECT * FROM notes'), [
+          {'id': 'a', 'name': 'draft'}
+        ]);
+        expect(db.select('SELECT * FROM ps_crud'), isEmpty);
+      });
+
+      test('does not rename between local-only and synced tables', () {
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('drafts', localOnly: true))]);
+        db.execute("INSERT INTO drafts (id, name) VALUES ('a', 'draft')");
+
+        db.executeInTx('SELECT powersync_replace_schema(?)',
+            [json.encode(schema('notes', previousNames: ['drafts']))]);
+        expect(db.select('SELECT * FROM notes'), isEmpty);
+      });
+    });
+
     group('metadata', () {
       // This is a special because we have two delete triggers when
       // include_metadata is true (one for actual `DELETE` statements and one
@@ -322,6 +622,245 @@ END''',
         test('#$i', () => testCase.testWith(db));
       }
     });
+
+    test('unique and partial indexes', () {
+      Map<String, Object?> schemaWithIndex(Map<String, Object?> index) {
+        return {
+          'tables': [
+            {
+              'name': 'users',
+              'columns': [
+                {'name': 'email', 'type': 'TEXT'},
+                {'name': 'archived', 'type': 'INTEGER'},
+              ],
+              'indexes': [index],
+            }
+          ]
+        };
+      }
+
+      final uniqueEmail = {
+        'name': 'email',
+        'unique': true,
+        'where': 'archived = 0',
+        'columns': [
+          {
+            'name': 'email',
+            'type': 'TEXT',
+            'ascending': true,
+            'collate': 'NOCASE'
+          },
+        ],
+      };
+      db.executeInTx('SELECT powersync_replace_schema(?)',
+          [json.encode(schemaWithIndex(uniqueEmail))]);
+
+      db.execute(
+          "INSERT INTO users (id, email, archived) VALUES (uuid(), 'a@example.org', 0)");
+      db.execute(
+          "INSERT INTO users (id, email, archived) VALUES (uuid(), 'A@example.org', 1)");
+      expect(
+        () => db.execute(
+            "INSERT INTO users (id, email, archived) VALUES (uuid(), 'A@EXAMPLE.org', 0)"),
+        throwsA(isSqliteException(2067, contains('UNIQUE constraint failed'))),
+      );
+
+      // Changing the definition re-creates the index
+      final [before] = db.select('PRAGMA schema_version');
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode(schemaWithIndex({
+          ...uniqueEmail,
+          'unique': false,
+          'columns': [
+            {'expression': 'lower(email)', 'ascending': true},
+          ],
+        }))
+      ]);
+      final [after] = db.select('PRAGMA schema_version');
+      expect(after['schema_version'],
+          greaterThan(before['schema_version'] as int));
+      expect(
+        db.select(
+            "SELECT sql FROM sqlite_schema WHERE name = 'ps_data__users__email'"),
+        [
+          {
+            'sql': 'CREATE INDEX "ps_data__users__email" ON "ps_data__users"'
+                "((lower(CAST(json_extract(data, '\$.email') as TEXT)))) "
+                "WHERE CAST(json_extract(data, '\$.archived') as INTEGER) = 0"
+          }
+        ],
+      );
+    });
+
+    test('powersync_schema_diff', () {
+      Map<String, Object?> users({required bool typed}) {
+        return {
+          'name': 'users',
+          'typed_columns': typed,
+          'columns': [
+            {'name': 'name', 'type': 'TEXT'},
+          ],
+          'indexes': [
+            {
+              'name': 'name',
+              'columns': [
+                {'name': 'name', 'type': 'TEXT', 'ascending': true}
+              ],
+            }
+          ],
+        };
+      }
+
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode({
+          'tables': [
+            users(typed: false),
+            {
+              'name': 'drafts',
+              'local_only': true,
+              'columns': [
+                {'name': 'content', 'type': 'TEXT'},
+              ],
+            },
+          ]
+        })
+      ]);
+      db.execute('''
+INSERT INTO ps_data__users (id, data) VALUES ('a', '{"name":"a"}'), ('b', '{"name":"b"}');
+INSERT INTO drafts (id, content) VALUES ('draft', 'local');
+INSERT INTO ps_untyped (type, id, data) VALUES ('comments', 'c', '{}');
+''');
+
+      final [before] = db.select('PRAGMA schema_version');
+      final [row] = db.select('SELECT powersync_schema_diff(?)', [
+        json.encode({
+          'tables': [
+            users(typed: true),
+            {
+              'name': 'comments',
+              'columns': [
+                {'name': 'content', 'type': 'TEXT'},
+              ],
+            },
+          ]
+        })
+      ]);
+      final [after] = db.select('PRAGMA schema_version');
+      expect(after, before);
+
+      expect(json.decode(row.columnAt(0) as String), {
+        'tables': [
+          {
+            'name': 'users',
+            'action': 'change_storage',
+            'rows': 2,
+            'untyped_rows': 0,
+            'deletes_rows': false,
+          },
+          {
+            'name': 'comments',
+            'action': 'create',
+            'rows': 0,
+            'untyped_rows': 1,
+            'deletes_rows': false,
+          },
+          {
+            'name': 'drafts',
+            'action': 'drop',
+            'rows': 1,
+            'untyped_rows': 0,
+            'deletes_rows': true,
+          },
+        ],
+        'views': [
+          {
+            'name': 'users',
+            'action': 'recreate',
+            'changed': ['view', 'insert_trigger', 'update_trigger'],
+          },
+          {'name': 'comments', 'action': 'create'},
+          {'name': 'drafts', 'action': 'drop'},
+        ],
+        'indexes': [
+          {'name': 'ps_data__users__name', 'action': 'recreate'},
+        ],
+      });
+    });
+
+    test('powersync_schema and powersync_schema_hash', () {
+      db.execute('SELECT powersync_init()');
+      expect(
+        db.select(
+            'SELECT powersync_schema() AS s, powersync_schema_hash() AS h'),
+        [
+          {'s': null, 'h': null}
+        ],
+      );
+
+      const schema = '''
+{
+  "tables": [{"name": "users", "columns": [{"type": "TEXT", "name": "name"}]}]
+}''';
+      db.executeInTx('SELECT powersync_replace_schema(?)', [schema]);
+
+      final [row] = db.select(
+          'SELECT powersync_schema() AS s, powersync_schema_hash() AS h, '
+          'powersync_schema_hash(?) AS expected',
+          [json.encode(json.decode(schema))]);
+      expect(row['s'],
+          '{"tables":[{"columns":[{"name":"name","type":"TEXT"}],"name":"users"}]}');
+      expect(row['h'], row['expected']);
+
+      // The schema survives clearing the database, since views are kept too.
+      db.executeInTx('SELECT powersync_clear(0)');
+      expect(db.select('SELECT powersync_schema_hash() AS h'), [
+        {'h': row['h']}
+      ]);
+    });
+
+    test('powersync_query_tables', () {
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode({
+          'tables': [
+            {
+              'name': 'users',
+              'view_name': 'people',
+              'columns': [
+                {'name': 'name', 'type': 'TEXT'}
+              ],
+            },
+            {
+              'name': 'drafts',
+              'local_only': true,
+              'columns': [
+                {'name': 'content', 'type': 'TEXT'}
+              ],
+            },
+          ],
+        })
+      ]);
+      db.execute('CREATE TABLE raw_items (id TEXT PRIMARY KEY, name TEXT)');
+
+      List<Object?> queryTables(String sql) {
+        final [row] = db.select('SELECT powersync_query_tables(?)', [sql]);
+        return json.decode(row.columnAt(0) as String);
+      }
+
+      expect(queryTables('SELECT id FROM people'), ['people']);
+      expect(
+        queryTables(
+            'SELECT * FROM people JOIN drafts ON drafts.id = people.id'),
+        ['drafts', 'people'],
+      );
+      expect(
+        queryTables('SELECT * FROM raw_items WHERE id IN (SELECT id FROM ps_crud)'),
+        ['raw_items'],
+      );
+      expect(
+        () => queryTables('SELECT * FROM does_not_exist'),
+        throwsA(isSqliteException(1, contains('no such table'))),
+      );
+    });
   });
 }
 
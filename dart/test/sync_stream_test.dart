@@ -658,6 +658,63 @@ void main() {
         ]));
   });
 
+  syncTest('can be inspected', (_) {
+    control(
+      'subscriptions',
+      json.encode({
+        'subscribe': {
+          'stream': {
+            'name': 'a',
+            'params': {'id': 1},
+          },
+          'ttl': 60,
+          'priority': 1,
+        }
+      }),
+    );
+    control('start', null);
+    control(
+      'line_text',
+      json.encode(
+        checkpoint(
+          lastOpId: 1,
+          buckets: [
+            bucketDescription('b', subscriptions: [
+              {'default': 0}
+            ])
+          ],
+          streams: [stream('b', true)],
+        ),
+      ),
+    );
+    control('line_text', json.encode(checkpointComplete()));
+
+    expect(db.select('SELECT * FROM powersync_streams'), [
+      {
+        'name': 'a',
+        'parameters': '{"id":1}',
+        'priority': 1,
+        'active': 0,
+        'is_default': 0,
+        'has_explicit_subscription': 1,
+        'ttl': 60,
+        'expires_at': timestamp(plusSeconds: 60),
+        'last_synced_at': timestamp(),
+      },
+      {
+        'name': 'b',
+        'parameters': null,
+        'priority': null,
+        'active': 1,
+        'is_default': 1,
+        'has_explicit_subscription': 0,
+        'ttl': null,
+        'expires_at': null,
+        'last_synced_at': timestamp(),
+      },
+    ]);
+  });
+
   syncTest('clearing database clears subscriptions', (_) {
     control(
       'subscriptions',
@@ -343,6 +343,65 @@ void _syncTests<T>({
     ]);
   });
 
+  syncTest('closes stream after keepalive timeout', (controller) {
+    final options = json.encode({
+      'keepalive_timeout_ms': 30000,
+      'reconnect_backoff': {'initial_delay_ms': 1000, 'max_delay_ms': 3000},
+    });
+
+    Map closeInstruction(List<Object?> instructions) {
+      return instructions.whereType<Map>().firstWhere(
+          (i) => i.containsKey('CloseSyncStream'))['CloseSyncStream'];
+    }
+
+    invokeControl('start', options);
+    invokeControl('connection', 'established');
+    controller.elapse(const Duration(seconds: 20));
+    expect(invokeControl('tick', null), isEmpty);
+
+    // Receiving lines resets the timeout.
+    syncLine({'token_expires_in': 3600});
+    controller.elapse(const Duration(seconds: 20));
+    expect(invokeControl('tick', null), isEmpty);
+
+    controller.elapse(const Duration(seconds: 10));
+    expect(closeInstruction(invokeControl('tick', null)), {
+      'hide_disconnect': false,
+      'reason': 'keepalive_timeout',
+      'retry_delay_ms': 1000,
+    });
+
+    // Consecutive failures increase the delay.
+    invokeControl('start', options);
+    expect(closeInstruction(invokeControl('connection', 'end')), {
+      'hide_disconnect': false,
+      'retry_delay_ms': 2000,
+    });
+    invokeControl('start', options);
+    controller.elapse(const Duration(minutes: 1));
+    expect(closeInstruction(invokeControl('tick', null)), {
+      'hide_disconnect': false,
+      'reason': 'keepalive_timeout',
+      'retry_delay_ms': 3000,
+    });
+
+    // Applying a checkpoint resets the failure counter.
+    invokeControl('start', options);
+    pushCheckpoint(buckets: []);
+    pushCheckpointComplete();
+    expect(closeInstruction(invokeControl('connection', 'end')), {
+      'hide_disconnect': false,
+      'retry_delay_ms': 1000,
+    });
+
+    // Token refreshes don't count as a failure.
+    invokeControl('start', options);
+    expect(closeInstruction(invokeControl('refreshed_token', null)), {
+      'hide_disconnect': true,
+      'retry_delay_ms': 0,
+    });
+  });
+
   test('does not publish until reaching checkpoint', () {
     invokeControl('start', null);
     pushCheckpoint(buckets: priorityBuckets);
@@ -439,6 +498,260 @@ void _syncTests<T>({
     }
   });
 
+  syncTest('can handle multiple lines at once', (_) {
+    invokeControl('start', null);
+
+    Object dataLine(String opId, String rowId) {
+      return {
+        'data': {
+          'bucket': 'a',
+          'has_more': false,
+          'after': null,
+          'next_after': null,
+          'data': [
+            {
+              'op_id': opId,
+              'op': 'PUT',
+              'object_type': 'items',
+              'object_id': rowId,
+              'checksum': 0,
+              'data': json.encode({'col': rowId}),
+            }
+          ],
+        },
+      };
+    }
+
+    final lines = [
+      checkpoint(lastOpId: 2, buckets: [bucketDescription('a', count: 2)]),
+      dataLine('1', 'row-0'),
+      dataLine('2', 'row-1'),
+      checkpointComplete(lastOpId: '2'),
+    ];
+
+    final List<Object?> instructions;
+    if (isBson) {
+      final builder = BytesBuilder();
+      for (final line in lines) {
+        builder.add(BsonCodec.serialize(line).byteList);
+      }
+      instructions = invokeControl('lines_binary', builder.takeBytes());
+    } else {
+      instructions =
+          invokeControl('lines_text', lines.map(jsonEncode).join('\n'));
+    }
+
+    expect(
+      instructions.where((i) => (i as Map).containsKey('UpdateSyncStatus')),
+      hasLength(1),
+    );
+    expect(instructions.last, containsPair('UpdateSyncStatus', anything));
+    expect(instructions, contains(containsPair('DidCompleteSync', anything)));
+    expect(fetchRows(), [
+      {'id': 'row-0', 'col': 'row-0'},
+      {'id': 'row-1', 'col': 'row-1'},
+    ]);
+  });
+
+  test('keeps handled lines when a later line in a batch fails', () {
+    invokeControl('start', null);
+
+    final instructions = invokeControl(
+      'lines_text',
+      [
+        jsonEncode(checkpoint(lastOpId: 1, buckets: [bucketDescription('a')])),
+        jsonEncode({
+          'data': {
+            'bucket': 'a',
+            'has_more': false,
+            'after': null,
+            'next_after': null,
+            'data': [
+              {
+                'op_id': '1',
+                'op': 'PUT',
+                'object_type': 'items',
+                'object_id': 'row-0',
+                'checksum': 0,
+                'data': json.encode({'col': 'row-0'}),
+              }
+            ],
+          },
+        }),
+        'not a sync line',
+      ].join('\n'),
+    );
+
+    expect(instructions, contains(containsPair('UpdateSyncStatus', anything)));
+    expect(
+      instructions,
+      contains(containsPair('DidFailLine', {
+        'line': 2,
+        'error': allOf(
+          containsPair('variant', 'SyncProtocolError'),
+          containsPair('retryable', false),
+        ),
+      })),
+    );
+    expect(instructions.last, containsPair('CloseSyncStream', anything));
+
+    final [row] = db.select('SELECT powersync_last_error() AS e');
+    expect(json.decode(row['e'] as String),
+        containsPair('variant', 'SyncProtocolError'));
+    expect(db.select('SELECT row_id FROM ps_oplog'), [
+      {'row_id': 'row-0'}
+    ]);
+  });
+
+  test('rejects incomplete binary lines in batch', () {
+    invokeControl('start', null);
+
+    final line = BsonCodec.serialize(
+            checkpoint(lastOpId: 1, buckets: [bucketDescription('a')]))
+        .byteList;
+
+    expect(
+      () => invokeControl('lines_binary', line.sublist(0, line.length - 1)),
+      throwsA(
+        isA<SqliteException>().having(
+          (e) => e.message,
+          'message',
+          contains('incomplete binary line'),
+        ),
+      ),
+    );
+  });
+
+  test('reassembles lines from stream chunks', () {
+    invokeControl('start', null);
+
+    final lines = [
+      checkpoint(lastOpId: 1, buckets: [bucketDescription('a')]),
+      {
+        'data': {
+          'bucket': 'a',
+          'has_more': false,
+          'after': null,
+          'next_after': null,
+          'data': [
+            {
+              'op_id': '1',
+              'op': 'PUT',
+              'object_type': 'items',
+              'object_id': 'row-0',
+              'checksum': 0,
+              'data': json.encode({'col': 'ä'}),
+            }
+          ],
+        },
+      },
+      checkpointComplete(),
+    ];
+
+    final builder = BytesBuilder();
+    for (final line in lines) {
+      if (isBson) {
+        builder.add(BsonCodec.serialize(line).byteList);
+      } else {
+        builder.add(utf8.encode('${jsonEncode(line)}\n'));
+      }
+    }
+    // Add an incomplete line at the end.
+    final bytes = builder.takeBytes();
+    final stream = [...bytes, ...bytes.sublist(0, 10)];
+
+    final command = isBson ? 'chunk_binary' : 'chunk_text';
+    for (var i = 0; i < stream.length; i += 7) {
+      final end = i + 7 < stream.length ? i + 7 : stream.length;
+      invokeControl(command, Uint8List.fromList(stream.sublist(i, end)));
+    }
+
+    expect(fetchRows(), [
+      {'id': 'row-0', 'col': 'ä'}
+    ]);
+
+    expect(
+      () => invokeControl('connection', 'end'),
+      throwsA(
+        isA<SqliteException>().having(
+          (e) => e.message,
+          'message',
+          contains('stream ended with an incomplete line'),
+        ),
+      ),
+    );
+  });
+
+  test('can emit status deltas', () {
+    List<Map> deltas(List<Object?> instructions) {
+      expect(
+          instructions
+              .whereType<Map>()
+              .where((i) => i.containsKey('UpdateSyncStatus')),
+          isEmpty);
+      return instructions
+          .whereType<Map>()
+          .where((i) => i.containsKey('UpdateSyncStatusDelta'))
+          .map((i) => i['UpdateSyncStatusDelta'] as Map)
+          .toList();
+    }
+
+    final options = json.encode({'status_deltas': true});
+    expect(deltas(invokeControl('start', options)), [
+      {
+        'sequence': 0,
+        'snapshot': true,
+        'changes': {
+          'connected': false,
+          'connecting': true,
+          'downloading': null,
+          'priority_status': [],
+          'streams': [],
+        },
+      }
+    ]);
+
+    expect(deltas(pushCheckpoint(buckets: [bucketDescription('a')])), [
+      {
+        'sequence': 1,
+        'snapshot': false,
+        'changes': {
+          'connected': true,
+          'connecting': false,
+          'downloading': {
+            'buckets': {
+              'prio_3': {
+                'priority': 3,
+                'at_last': 0,
+                'since_last': 0,
+                'target_count': 1,
+              },
+            },
+          },
+        },
+      }
+    ]);
+
+    expect(deltas(pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'})), [
+      {
+        'sequence': 2,
+        'snapshot': false,
+        'changes': {
+          'downloading': {
+            'buckets': {
+              'prio_3': {'since_last': 1},
+            },
+          },
+        },
+      }
+    ]);
+
+    final completed = deltas(pushCheckpointComplete());
+    expect(completed, hasLength(1));
+    expect(completed[0], containsPair('sequence', 3));
+    expect(completed[0]['changes'], containsPair('downloading', null));
+  });
+
   syncTest('remembers sync state', (controller) {
     invokeControl('start', null);
 
@@ -1052,8 +1365,214 @@ void _syncTests<T>({
         },
       });
     });
+
+    test('can apply data from embedded documents', () {
+      invokeControl('start', null);
+      pushCheckpoint(buckets: [bucketDescription('a', count: 1)]);
+
+      syncLine({
+        'data': {
+          'bucket': 'a',
+          'has_more': false,
+          'after': null,
+          'next_after': null,
+          'data': [
+            {
+              'op_id': '1',
+              'op': 'PUT',
+              'object_type': 'items',
+              'object_id': 'row-0',
+              'checksum': 0,
+              'data': {'col': 'hi'},
+            }
+          ],
+        },
+      });
+      pushCheckpointComplete();
+
+      expect(db.select('SELECT data FROM ps_oplog'), [
+        {'data': '{"col":"hi"}'}
+      ]);
+      expect(db.select('SELECT id, col FROM items'), [
+        {'id': 'row-0', 'col': 'hi'}
+      ]);
+    });
   }
 
+  test('integrity check and repair', () {
+    Map<String, Object?> integrityCheck() {
+      final [row] = db.select('SELECT powersync_integrity_check()');
+      return json.decode(row.columnAt(0) as String);
+    }
+
+    invokeControl('start', null);
+    pushCheckpoint(buckets: [bucketDescription('a', count: 2, checksum: 3)]);
+    pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'}, checksum: 1);
+    pushSyncData('a', '2', 'row-1', 'PUT', {'col': 'b'}, checksum: 2);
+    pushCheckpointComplete(lastOpId: '2');
+    expect(integrityCheck(), containsPair('ok', true));
+
+    // Simulate inconsistencies in the local database.
+    db.execute('''
+INSERT INTO ps_data__items (id, data) VALUES ('dangling', '{}');
+DELETE FROM ps_data__items WHERE id = 'row-0';
+UPDATE ps_buckets SET op_checksum = 10;
+INSERT INTO ps_updated_rows (row_type, row_id) VALUES ('unknown', 'x');
+''');
+    // Local writes are not reported.
+    db.execute("INSERT INTO items (id, col) VALUES ('local', 'data')");
+
+    expect(integrityCheck(), {
+      'ok': false,
+      'dangling_rows': [
+        {'type': 'items', 'id': 'dangling'}
+      ],
+      'unapplied_rows': [
+        {'type': 'items', 'id': 'row-0'}
+      ],
+      'checksum_mismatches': [
+        {
+          'bucket': 'a',
+          'add_checksum': 0,
+          'op_checksum': 10,
+          'oplog_checksum': 3,
+        }
+      ],
+      'unknown_updated_rows': [
+        {'type': 'unknown', 'count': 1}
+      ],
+    });
+
+    db.execute('BEGIN');
+    final [repair] = db.select('SELECT powersync_repair()');
+    db.execute('COMMIT');
+    expect(json.decode(repair.columnAt(0) as String), {
+      'removed_rows': 1,
+      'restored_rows': 1,
+      'conflicting_rows': isEmpty,
+      'repaired_buckets': 1,
+    });
+
+    expect(integrityCheck(), containsPair('dangling_rows', isEmpty));
+    expect(integrityCheck(), containsPair('unapplied_rows', isEmpty));
+    expect(integrityCheck(), containsPair('checksum_mismatches', isEmpty));
+    expect(db.select('SELECT id, col FROM items ORDER BY id'), [
+      {'id': 'local', 'col': 'data'},
+      {'id': 'row-0', 'col': 'a'},
+      {'id': 'row-1', 'col': 'b'},
+    ]);
+  });
+
+  test('fails on synced rows violating unique indexes', () {
+    db.executeInTx('SELECT powersync_replace_schema(?)', [
+      json.encode({
+        'tables': [
+          {
+            'name': 'items',
+            'columns': [
+              {'name': 'col', 'type': 'TEXT'},
+            ],
+            'indexes': [
+              {
+                'name': 'col',
+                'unique': true,
+                'columns': [
+                  {'name': 'col', 'type': 'TEXT', 'ascending': true}
+                ],
+              }
+            ],
+          }
+        ]
+      })
+    ]);
+
+    invokeControl('start', null);
+    pushCheckpoint(buckets: [bucketDescription('a', count: 1)]);
+    pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'});
+    pushCheckpointComplete();
+
+    // Updating a row with the same id is fine.
+    pushCheckpoint(lastOpId: 2, buckets: [bucketDescription('a', count: 2)]);
+    pushSyncData('a', '2', 'row-0', 'PUT', {'col': 'b'});
+    pushCheckpointComplete(lastOpId: '2');
+    expect(db.select('SELECT id, col FROM items'), [
+      {'id': 'row-0', 'col': 'b'}
+    ]);
+
+    // Re-creating a row under a new id works regardless of the order in which
+    // rows are applied.
+    pushCheckpoint(lastOpId: 4, buckets: [bucketDescription('a', count: 4)]);
+    pushSyncData('a', '3', 'row-0', 'REMOVE', null);
+    pushSyncData('a', '4', 'new-row', 'PUT', {'col': 'b'});
+    pushCheckpointComplete(lastOpId: '4');
+    expect(db.select('SELECT id, col FROM items'), [
+      {'id': 'new-row', 'col': 'b'}
+    ]);
+
+    // A different row with the same value must not silently replace it.
+    pushCheckpoint(lastOpId: 5, buckets: [bucketDescription('a', count: 5)]);
+    pushSyncData('a', '5', 'row-1', 'PUT', {'col': 'b'});
+    expect(
+      () => pushCheckpointComplete(lastOpId: '5'),
+      throwsA(isA<SqliteException>().having((e) => e.message, 'message',
+          contains('Could not apply synced row items/row-1'))),
+    );
+    expect(db.select('SELECT id, col FROM items'), [
+      {'id': 'new-row', 'col': 'b'}
+    ]);
+  });
+
+  test('repair reports rows violating unique indexes', () {
+    db.executeInTx('SELECT powersync_replace_schema(?)', [
+      json.encode({
+        'tables': [
+          {
+            'name': 'items',
+            'columns': [
+              {'name': 'col', 'type': 'TEXT'},
+            ],
+            'indexes': [
+              {
+                'name': 'col',
+                'unique': true,
+                'columns': [
+                  {'name': 'col', 'type': 'TEXT', 'ascending': true}
+                ],
+              }
+            ],
+          }
+        ]
+      })
+    ]);
+
+    invokeControl('start', null);
+    pushCheckpoint(buckets: [bucketDescription('a', count: 2)]);
+    pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'});
+    pushSyncData('a', '2', 'row-1', 'PUT', {'col': 'b'});
+    pushCheckpointComplete(lastOpId: '2');
+
+    // Lose both rows, and have a local write take the value of row-1.
+    db.execute("DELETE FROM ps_data__items WHERE id IN ('row-0', 'row-1')");
+    db.execute("INSERT INTO items (id, col) VALUES ('local', 'b')");
+
+    db.execute('BEGIN');
+    final [repair] = db.select('SELECT powersync_repair()');
+    db.execute('COMMIT');
+    expect(json.decode(repair.columnAt(0) as String), {
+      'removed_rows': 0,
+      'restored_rows': 1,
+      'conflicting_rows': [
+        {'type': 'items', 'id': 'row-1'}
+      ],
+      'repaired_buckets': 0,
+    });
+
+    expect(db.select('SELECT id, col FROM items ORDER BY id'), [
+      {'id': 'local', 'col': 'b'},
+      {'id': 'row-0', 'col': 'a'},
+    ]);
+  });
+
   group('progress', () {
     Map<String, BucketProgress>? progress = null;
     var lastOpId = 0;
@@ -1310,48 +1829,130 @@ void _syncTests<T>({
       ]);
     });
 
-    test('write checkpoint with synced data', () {
-      // local write while offline
+    test('completing the last crud batch applies pending checkpoint', () {
       db.execute("insert into items (id, col) values ('local', 'data');");
       invokeControl('start', null);
 
-      // Complete upload process
-      db.execute('DELETE FROM ps_crud');
-      probeTargetCheckpointRequestId(1);
-      expect(invokeControl('completed_upload', null), isEmpty);
-      expect(lastRequestedCheckpointRequestId(), 1);
-
-      // Sync afterwards containing data and write checkpoint.
       pushCheckpoint(buckets: priorityBuckets, writeCheckpoint: '1');
       pushSyncData('prio1', '1', 'row-0', 'PUT', {'col': 'hi'});
       pushCheckpointComplete();
+      expect(fetchRows(), [
+        {'id': 'local', 'col': 'data'}
+      ]);
+
+      final [entry] = db.select('SELECT id FROM ps_crud');
+      final result = json.decode(invokeControlScalar(
+          'complete_crud_batch',
+          json.encode({
+            'last_id': entry['id'],
+            'checkpoint_request_id': 1,
+          })) as String);
+      expect(result['has_more'], isFalse);
+      expect(
+        result['instructions'],
+        contains({
+          'DidCompleteSync': {'applied_checkpoint_request_id': '1'}
+        }),
+      );
       expect(fetchRows(), [
         {'id': 'row-0', 'col': 'hi'}
       ]);
     });
 
-    test('write checkpoint after synced data', () {
-      // local write while offline
+    test('row-level gating applies rows without local changes', () {
       db.execute("insert into items (id, col) values ('local', 'data');");
-      invokeControl('start', null);
+      db.execute("insert into items (id, col) values ('row-1', 'local');");
+      invokeControl('start', json.encode({'row_level_gating': true}));
 
-      // Upload changes, assume that triggered a checkpoint.
-      db.execute('DELETE FROM ps_crud');
-      pushCheckpoint(buckets: priorityBuckets);
+      // Rows affected by downloaded data (e.g. removed buckets) are tracked in
+      // ps_updated_rows too, that must not prevent applying them.
+      db.execute(
+          "INSERT INTO ps_updated_rows (row_type, row_id) VALUES ('items', 'row-0')");
+
+      pushCheckpoint(
+          buckets: priorityBuckets, lastOpId: 2, writeCheckpoint: '1');
       pushSyncData('prio1', '1', 'row-0', 'PUT', {'col': 'hi'});
-      expect(pushCheckpointComplete(), [
+      pushSyncData('prio1', '2', 'row-1', 'PUT', {'col': 'remote'});
+      expect(pushCheckpointComplete(lastOpId: '2'), [
         containsPair('LogLine', {
           'severity': 'INFO',
-          'line': contains('Will retry at completed upload')
+          'line': contains('Applied rows without local changes')
         })
       ]);
 
-      // Now the upload is complete and requests a write checkpoint
-      probeTargetCheckpointRequestId(1);
-      expect(invokeControl('completed_upload', null), isEmpty);
+      // row-0 has no local changes and should be applied, the others keep
+      // their local state.
+      expect(db.select('SELECT * FROM items ORDER BY id'), [
+        {'id': 'local', 'col': 'data'},
+        {'id': 'row-0', 'col': 'hi'},
+        {'id': 'row-1', 'col': 'local'},
+      ]);
 
-      // Which triggers a new iteration
-      pushCheckpoint(buckets: priorityBuckets, writeCheckpoint: '1');
+      // After uploading local changes, rows keep their local state until the
+      // write checkpoint has been applied.
+      db.execute('DELETE FROM ps_crud');
+      pushCheckpoint(
+          buckets: priorityBuckets, lastOpId: 3, writeCheckpoint: '1');
+      pushSyncData('prio1', '3', 'row-1', 'PUT', {'col': 'newer'});
+      pushCheckpointComplete(lastOpId: '3');
+      expect(db.select('SELECT * FROM items ORDER BY id'), [
+        {'id': 'local', 'col': 'data'},
+        {'id': 'row-0', 'col': 'hi'},
+        {'id': 'row-1', 'col': 'local'},
+      ]);
+
+      // Completing the upload applies the rest of the checkpoint.
+      probeTargetCheckpointRequestId(1);
+      invokeControl('completed_upload', null);
+      expect(db.select('SELECT * FROM items ORDER BY id'), [
+        {'id': 'row-0', 'col': 'hi'},
+        {'id': 'row-1', 'col': 'newer'},
+      ]);
+      expect(db.select('SELECT * FROM ps_updated_rows'), isEmpty);
+    });
+
+    test('write checkpoint with synced data', () {
+      // local write while offline
+      db.execute("insert into items (id, col) values ('local', 'data');");
+      invokeControl('start', null);
+
+      // Complete upload process
+      db.execute('DELETE FROM ps_crud');
+      probeTargetCheckpointRequestId(1);
+      expect(invokeControl('completed_upload', null), isEmpty);
+      expect(lastRequestedCheckpointRequestId(), 1);
+
+      // Sync afterwards containing data and write checkpoint.
+      pushCheckpoint(buckets: priorityBuckets, writeCheckpoint: '1');
+      pushSyncData('prio1', '1', 'row-0', 'PUT', {'col': 'hi'});
+      pushCheckpointComplete();
+      expect(fetchRows(), [
+        {'id': 'row-0', 'col': 'hi'}
+      ]);
+    });
+
+    test('write checkpoint after synced data', () {
+      // local write while offline
+      db.execute("insert into items (id, col) values ('local', 'data');");
+      invokeControl('start', null);
+
+      // Upload changes, assume that triggered a checkpoint.
+      db.execute('DELETE FROM ps_crud');
+      pushCheckpoint(buckets: priorityBuckets);
+      pushSyncData('prio1', '1', 'row-0', 'PUT', {'col': 'hi'});
+      expect(pushCheckpointComplete(), [
+        containsPair('LogLine', {
+          'severity': 'INFO',
+          'line': contains('Will retry at completed upload')
+        })
+      ]);
+
+      // Now the upload is complete and requests a write checkpoint
+      probeTargetCheckpointRequestId(1);
+      expect(invokeControl('completed_upload', null), isEmpty);
+
+      // Which triggers a new iteration
+      pushCheckpoint(buckets: priorityBuckets, writeCheckpoint: '1');
       expect(
           pushCheckpointComplete(),
           contains(containsPair('LogLine', {
@@ -1456,6 +2057,22 @@ void _syncTests<T>({
                   "Checksums didn't match, failed for: a (expected 0x000004d2, got 0x000010e1 = 0x000010e1 (op) + 0x00000000 (add))")
             }
           },
+          {
+            'DidFailChecksumValidation': {
+              'priority': null,
+              'buckets': [
+                {
+                  'bucket': 'a',
+                  'expected_checksum': 1234,
+                  'actual_op_checksum': 4321,
+                  'actual_add_checksum': 0,
+                  'expected_count': 1,
+                  'actual_count': 1,
+                }
+              ],
+              'quarantined': false,
+            }
+          },
           {
             'CloseSyncStream': {'hide_disconnect': false}
           },
@@ -1466,6 +2083,118 @@ void _syncTests<T>({
       expect(db.select('SELECT * FROM ps_buckets'), isEmpty);
     });
 
+    syncTest('quarantines buckets with checksum mismatch', (_) {
+      final options = json.encode({'checksum_failure_policy': 'quarantine'});
+      invokeControl('start', options);
+
+      pushCheckpoint(buckets: [bucketDescription('a', checksum: 1)]);
+      pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'}, checksum: 1);
+      pushCheckpointComplete();
+      expect(fetchRows(), [
+        {'id': 'row-0', 'col': 'a'}
+      ]);
+
+      pushCheckpoint(
+          lastOpId: 2, buckets: [bucketDescription('a', checksum: 3, count: 2)]);
+      pushSyncData('a', '2', 'row-1', 'PUT', {'col': 'b'}, checksum: 1);
+      expect(
+        pushCheckpointComplete(lastOpId: '2'),
+        containsAll([
+          {
+            'DidFailChecksumValidation': {
+              'priority': null,
+              'buckets': [
+                {
+                  'bucket': 'a',
+                  'expected_checksum': 3,
+                  'actual_op_checksum': 2,
+                  'actual_add_checksum': 0,
+                  'expected_count': 2,
+                  'actual_count': 2,
+                }
+              ],
+              'quarantined': true,
+            }
+          },
+          {
+            'CloseSyncStream': {'hide_disconnect': false}
+          },
+        ]),
+      );
+
+      // Applied rows are kept while the bucket is downloaded again.
+      expect(fetchRows(), [
+        {'id': 'row-0', 'col': 'a'}
+      ]);
+      expect(db.select('SELECT name, pending_delete FROM ps_buckets'), [
+        {'name': r'$quarantine/a', 'pending_delete': 1}
+      ]);
+
+      // The quarantined bucket should not be requested.
+      invokeControl('stop', null);
+      final instructions = invokeControl('start', options);
+      final establish = instructions.whereType<Map>().firstWhere(
+          (i) => i.containsKey('EstablishSyncStream'))['EstablishSyncStream'];
+      expect(establish['request']['buckets'], isEmpty);
+
+      pushCheckpoint(
+          lastOpId: 2, buckets: [bucketDescription('a', checksum: 3, count: 2)]);
+      pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'a'}, checksum: 1);
+      pushSyncData('a', '2', 'row-1', 'PUT', {'col': 'b'}, checksum: 2);
+      pushCheckpointComplete(lastOpId: '2');
+
+      expect(fetchRows(), [
+        {'id': 'row-0', 'col': 'a'},
+        {'id': 'row-1', 'col': 'b'},
+      ]);
+      expect(db.select('SELECT name FROM ps_buckets'), [
+        {'name': 'a'}
+      ]);
+    });
+
+    syncTest('does not apply unvalidated rows of quarantined buckets', (_) {
+      final options = json.encode({'checksum_failure_policy': 'quarantine'});
+      invokeControl('start', options);
+
+      pushCheckpoint(lastOpId: 2, buckets: [bucketDescription('a', checksum: 1)]);
+      pushSyncData('a', '2', 'row-0', 'PUT', {'col': 'a'}, checksum: 1);
+      pushCheckpointComplete(lastOpId: '2');
+
+      pushCheckpoint(
+          lastOpId: 3, buckets: [bucketDescription('a', checksum: 3, count: 2)]);
+      pushSyncData('a', '3', 'row-1', 'PUT', {'col': 'unvalidated'},
+          checksum: 1);
+      pushCheckpointComplete(lastOpId: '3');
+
+      // Operations from the failed download are dropped, the bucket is reset
+      // to the last validated checkpoint.
+      expect(db.select('SELECT op_id FROM ps_oplog'), [
+        {'op_id': 2}
+      ]);
+      expect(
+          db.select(
+              'SELECT last_applied_op, last_op, op_checksum FROM ps_buckets'),
+          [
+            {'last_applied_op': 2, 'last_op': 2, 'op_checksum': 1}
+          ]);
+
+      // Applying another bucket touching the same row must not pick up the
+      // unvalidated operation, even though it has a higher op id.
+      invokeControl('stop', null);
+      invokeControl('start', options);
+      pushCheckpoint(lastOpId: 3, buckets: [
+        bucketDescription('a', checksum: 3, count: 2),
+        bucketDescription('b', checksum: 1, priority: 1),
+      ]);
+      pushSyncData('b', '1', 'row-1', 'PUT', {'col': 'b'}, checksum: 1);
+      pushCheckpointComplete(priority: 1, lastOpId: '3');
+
+      expect(db.select('SELECT * FROM items ORDER BY id'), [
+        {'id': 'row-0', 'col': 'a'},
+        {'id': 'row-1', 'col': 'b'},
+      ]);
+    });
+
     group('recoverable',
         skip: testingWithSanitizers != null
             ? 'Unsupported in memory VFS'
@@ -1936,6 +2665,364 @@ SELECT
       ]);
     });
 
+    test('nested and metadata sources', () {
+      db.execute('''
+CREATE TABLE users (
+  id TEXT NOT NULL,
+  city TEXT,
+  first_tag TEXT,
+  role TEXT,
+  kind TEXT,
+  data TEXT,
+  priority INTEGER,
+  op_id INTEGER
+)''');
+      invokeControl(
+        'start',
+        json.encode({
+          'schema': {
+            'tables': [],
+            'raw_tables': [
+              {
+                'name': 'users',
+                'put': {
+                  'sql':
+                      'INSERT OR REPLACE INTO users VALUES (?, ?, ?, ?, ?, ?, ?, ?);',
+                  'params': [
+                    'Id',
+                    {'JsonPath': r'$.address.city'},
+                    {'JsonPath': r'$.tags[0]'},
+                    {
+                      'ColumnWithDefault': {'name': 'role', 'default': 'member'}
+                    },
+                    {'Constant': 'user'},
+                    'Data',
+                    'BucketPriority',
+                    'OpId',
+                  ],
+                },
+                'delete': {
+                  'sql': 'DELETE FROM users WHERE id = ?',
+                  'params': ['Id'],
+                },
+              }
+            ]
+          }
+        }),
+      );
+
+      pushCheckpoint(buckets: [bucketDescription('a', priority: 2)]);
+      pushSyncData(
+        'a',
+        '1',
+        'user1',
+        'PUT',
+        {
+          'address': json.encode({'city': 'Berlin'}),
+          'tags': ['a', 'b'],
+        },
+        objectType: 'users',
+      );
+      pushCheckpointComplete();
+
+      final [row] = db.select('SELECT * FROM users;');
+      expect(row, {
+        'id': 'user1',
+        'city': 'Berlin',
+        'first_tag': 'a',
+        'role': 'member',
+        'kind': 'user',
+        'data': isA<String>(),
+        'priority': 2,
+        'op_id': 1,
+      });
+      expect(json.decode(row['data']), containsPair('tags', ['a', 'b']));
+    });
+
+    test('inferred schema with column sources', () {
+      db.execute(
+          'CREATE TABLE local_users (id TEXT NOT NULL PRIMARY KEY, name TEXT, city TEXT) STRICT;');
+
+      invokeControl(
+          'start',
+          json.encode({
+            'schema': {
+              'raw_tables': [
+                {
+                  'name': 'users',
+                  'table_name': 'local_users',
+                  'column_sources': {
+                    'city': {'JsonPath': r'$.address.city'},
+                  },
+                }
+              ],
+              'tables': [],
+            }
+          }));
+
+      pushCheckpoint(buckets: [bucketDescription('a')]);
+      pushSyncData(
+        'a',
+        '1',
+        'my_user',
+        'PUT',
+        {
+          'name': 'First user',
+          'address': {'city': 'Berlin'},
+        },
+        objectType: 'users',
+      );
+      pushCheckpointComplete();
+
+      expect(db.select('SELECT * FROM local_users;'), [
+        {'id': 'my_user', 'name': 'First user', 'city': 'Berlin'}
+      ]);
+    });
+
+    test('inferred schema adding missing columns', () {
+      db.execute(
+          'CREATE TABLE local_users (id TEXT NOT NULL PRIMARY KEY, name TEXT) STRICT;');
+      final table = {
+        'name': 'users',
+        'table_name': 'local_users',
+        'add_missing_columns': true,
+      };
+      invokeControl(
+          'start',
+          json.encode({
+            'schema': {
+              'raw_tables': [table],
+              'tables': [],
+            }
+          }));
+
+      pushCheckpoint(buckets: [bucketDescription('a')]);
+      pushSyncData(
+        'a',
+        '1',
+        'my_user',
+        'PUT',
+        {'name': 'First user', 'age': 42, 'nickname': null},
+        objectType: 'users',
+      );
+      pushCheckpointComplete();
+
+      expect(db.select('SELECT * FROM local_users;'), [
+        {'id': 'my_user', 'name': 'First user', 'age': 42}
+      ]);
+      expect(
+        db.select(
+            "SELECT type FROM pragma_table_info('local_users') WHERE name = 'age'"),
+        [
+          {'type': 'INTEGER'}
+        ],
+      );
+    });
+
+    test('adds missing columns when replacing schema', () {
+      db.execute(
+          'CREATE TABLE local_users (id TEXT NOT NULL PRIMARY KEY, name TEXT) STRICT;');
+      final table = {
+        'name': 'users',
+        'table_name': 'local_users',
+      };
+      invokeControl(
+          'start',
+          json.encode({
+            'schema': {
+              'raw_tables': [table],
+              'tables': [],
+            }
+          }));
+
+      pushCheckpoint(buckets: [bucketDescription('a')]);
+      pushSyncData(
+        'a',
+        '1',
+        'my_user',
+        'PUT',
+        {'name': 'First user', 'email': 'user@example.org'},
+        objectType: 'users',
+      );
+      pushCheckpointComplete();
+      expect(db.select('SELECT * FROM local_users;'), [
+        {'id': 'my_user', 'name': 'First user'}
+      ]);
+
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode({
+          'raw_tables': [
+            {...table, 'add_missing_columns': true}
+          ],
+          'tables': [],
+        })
+      ]);
+      expect(
+        db.select("SELECT name, type FROM pragma_table_info('local_users')"),
+        [
+          {'name': 'id', 'type': 'TEXT'},
+          {'name': 'name', 'type': 'TEXT'},
+          {'name': 'email', 'type': 'TEXT'},
+        ],
+      );
+    });
+
+    test('replacing schema ignores missing raw tables', () {
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode({
+          'raw_tables': [
+            {
+              'name': 'users',
+              'table_name': 'local_users',
+              'add_missing_columns': true,
+            }
+          ],
+          'tables': [],
+        })
+      ]);
+      expect(db.select("SELECT * FROM sqlite_master WHERE name = 'local_users'"),
+          isEmpty);
+    });
+
+    test('composite local key', () {
+      db.execute('''
+CREATE TABLE legacy (
+  org_id TEXT NOT NULL,
+  external_ref TEXT NOT NULL,
+  name TEXT,
+  PRIMARY KEY (org_id, external_ref)
+) STRICT;''');
+      final table = {
+        'name': 'legacy',
+        'table_name': 'legacy',
+        'local_key': ['org_id', 'external_ref'],
+      };
+      db.execute('''
+SELECT
+  powersync_create_raw_table_crud_trigger(?1, 'legacy_insert', 'INSERT'),
+  powersync_create_raw_table_crud_trigger(?1, 'legacy_update', 'UPDATE'),
+  powersync_create_raw_table_crud_trigger(?1, 'legacy_delete', 'DELETE')
+''', [json.encode(table)]);
+
+      invokeControl(
+          'start',
+          json.encode({
+            'schema': {
+              'raw_tables': [table],
+              'tables': [],
+            }
+          }));
+
+      pushCheckpoint(buckets: [bucketDescription('a', count: 2)]);
+      pushSyncData(
+        'a',
+        '1',
+        json.encode(['org1', 'ref1']),
+        'PUT',
+        {'name': 'First'},
+        objectType: 'legacy',
+      );
+      pushSyncData(
+        'a',
+        '2',
+        json.encode(['org1', 'ref2']),
+        'PUT',
+        {'name': 'Second'},
+        objectType: 'legacy',
+      );
+      pushCheckpointComplete();
+
+      expect(db.select('SELECT * FROM legacy ORDER BY external_ref'), [
+        {'org_id': 'org1', 'external_ref': 'ref1', 'name': 'First'},
+        {'org_id': 'org1', 'external_ref': 'ref2', 'name': 'Second'},
+      ]);
+
+      pushCheckpoint(lastOpId: 3, buckets: [bucketDescription('a', count: 3)]);
+      pushSyncData(
+        'a',
+        '3',
+        json.encode(['org1', 'ref2']),
+        'REMOVE',
+        null,
+        objectType: 'legacy',
+      );
+      pushCheckpointComplete(lastOpId: '3');
+      expect(db.select('SELECT external_ref FROM legacy'), [
+        {'external_ref': 'ref1'}
+      ]);
+
+      db.execute(
+          "UPDATE legacy SET name = 'Updated' WHERE external_ref = 'ref1'");
+      expect(db.select('SELECT data FROM ps_crud'), [
+        {
+          'data': json.encode({
+            'op': 'PATCH',
+            'id': json.encode(['org1', 'ref1']),
+            'type': 'legacy',
+            'data': {'name': 'Updated'},
+          })
+        }
+      ]);
+      expect(() => db.execute("UPDATE legacy SET org_id = 'org2'"),
+          throwsA(isA<SqliteException>()));
+    });
+
+    test('typed columns', () {
+      db.executeInTx('SELECT powersync_replace_schema(?)', [
+        json.encode({
+          'tables': [
+            {
+              'name': 'users',
+              'typed_columns': true,
+              'columns': [
+                {'name': 'name', 'type': 'TEXT'},
+                {'name': 'age', 'type': 'INTEGER'},
+              ],
+            }
+          ],
+        })
+      ]);
+
+      invokeControl('start', null);
+      pushCheckpoint(buckets: [bucketDescription('a', count: 2)]);
+      pushSyncData(
+        'a',
+        '1',
+        'u1',
+        'PUT',
+        {'name': 'First', 'age': 30, 'extra': true},
+        objectType: 'users',
+      );
+      pushSyncData(
+        'a',
+        '2',
+        'u2',
+        'PUT',
+        {'name': 'Second'},
+        objectType: 'users',
+      );
+      pushCheckpointComplete();
+
+      expect(db.select('SELECT * FROM ps_data__users ORDER BY id'), [
+        {'id': 'u1', 'name': 'First', 'age': 30, '_rest': '{"extra":true}'},
+        {'id': 'u2', 'name': 'Second', 'age': null, '_rest': null},
+      ]);
+      expect(db.select('SELECT * FROM users ORDER BY id'), [
+        {'id': 'u1', 'name': 'First', 'age': 30},
+        {'id': 'u2', 'name': 'Second', 'age': null},
+      ]);
+
+      final [check] = db.select('SELECT powersync_integrity_check()');
+      expect(json.decode(check.columnAt(0) as String), containsPair('ok', true));
+
+      pushCheckpoint(lastOpId: 3, buckets: [bucketDescription('a', count: 3)]);
+      pushSyncData('a', '3', 'u2', 'REMOVE', null, objectType: 'users');
+      pushCheckpointComplete(lastOpId: '3');
+      expect(db.select('SELECT id FROM users'), [
+        {'id': 'u1'}
+      ]);
+    });
+
     test('crud vtab', () {
       // This is mostly a test for the triggers, validating the suggestions we
       // give on https://docs.powersync.com/usage/use-case-examples/raw-tables#capture-local-writes-with-triggers
@@ -2172,6 +3259,149 @@ CREATE TRIGGER users_ref_delete
     expectDownloadSize(isBson ? 376 : 378);
   });
 
+  syncTest('records sync history', (controller) {
+    invokeControl('start', null);
+    pushCheckpoint(buckets: [bucketDescription('a')]);
+    pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'hi'});
+    pushCheckpointComplete();
+    controller.elapse(const Duration(seconds: 10));
+    invokeControl('connection', 'end');
+
+    // Errors are recorded even though the transaction is rolled back.
+    invokeControl('start', null);
+    expect(() => syncLine({'checkpoint_diff': {}}),
+        throwsA(isA<SqliteException>()));
+    invokeControl('start', null);
+    invokeControl('stop', null);
+
+    expect(db.select('SELECT * FROM powersync_sync_history()'), [
+      {
+        'id': 3,
+        'started_at': timestamp(plusSeconds: 10),
+        'ended_at': timestamp(plusSeconds: 10),
+        'close_reason': 'stopped',
+        'error': null,
+        'downloaded_bytes': 0,
+        'downloaded_operations': 0,
+        'completed_checkpoints': 0,
+      },
+      {
+        'id': 2,
+        'started_at': timestamp(plusSeconds: 10),
+        'ended_at': timestamp(plusSeconds: 10),
+        'close_reason': 'error',
+        'error': contains('Sync protocol error'),
+        'downloaded_bytes': 0,
+        'downloaded_operations': 0,
+        'completed_checkpoints': 0,
+      },
+      {
+        'id': 1,
+        'started_at': timestamp(),
+        'ended_at': timestamp(plusSeconds: 10),
+        'close_reason': 'stream_ended',
+        'error': null,
+        'downloaded_bytes': isPositive,
+        'downloaded_operations': 1,
+        'completed_checkpoints': 1,
+      },
+    ]);
+  });
+
+  syncTest('limits sync history', (_) {
+    for (var i = 0; i < 60; i++) {
+      invokeControl('start', null);
+    }
+    invokeControl('stop', null);
+
+    final rows = db.select(
+        'SELECT min(id) AS first, max(id) AS last, count(*) AS count FROM ps_sync_history');
+    expect(rows, [
+      {'first': 11, 'last': 60, 'count': 50}
+    ]);
+  });
+
+  syncTest('clearing removes sync history', (_) {
+    invokeControl('start', null);
+    invokeControl('stop', null);
+    expect(db.select('SELECT * FROM ps_sync_history'), hasLength(1));
+
+    db.executeInTx('SELECT powersync_clear(0)');
+    expect(db.select('SELECT * FROM ps_sync_history'), isEmpty);
+  });
+
+  syncTest('can inspect buckets', (_) {
+    invokeControl('start', null);
+    pushCheckpoint(
+        buckets: [bucketDescription('a', priority: 1, checksum: 1)]);
+    pushSyncData('a', '1', 'row-0', 'PUT', {'col': 'hi'}, checksum: 1);
+
+    expect(db.select('SELECT * FROM powersync_buckets'), [
+      {
+        'name': 'a',
+        // Only known once a checkpoint including the bucket has been applied.
+        'priority': null,
+        'last_op': 1,
+        'last_applied_op': 0,
+        'count_at_last': 0,
+        'count_since_last': 1,
+        'downloaded_size': isPositive,
+        'add_checksum': 0,
+        'op_checksum': 1,
+        'pending_delete': 0,
+      }
+    ]);
+
+    pushCheckpointComplete();
+    expect(
+      db.select('SELECT * FROM powersync_buckets'),
+      [
+        allOf(
+          containsPair('priority', 1),
+          containsPair('last_applied_op', 1),
+          containsPair('count_at_last', 1),
+          containsPair('count_since_last', 0),
+        )
+      ],
+    );
+
+    expect(
+      () => db.execute("INSERT INTO powersync_buckets (name) VALUES ('b')"),
+      throwsA(isA<SqliteException>()),
+    );
+  });
+
+  syncTest('describes last error', (_) {
+    Object? lastError() {
+      final [row] = db.select('SELECT powersync_last_error() AS e');
+      final error = row['e'] as String?;
+      return error == null ? null : json.decode(error);
+    }
+
+    expect(lastError(), isNull);
+    invokeControl('start', null);
+
+    // A document with an element of an unknown type (0x20).
+    expect(
+      () => invokeControl('line_binary',
+          Uint8List.fromList([8, 0, 0, 0, 0x20, 0x61, 0, 0])),
+      throwsA(isA<SqliteException>()),
+    );
+    expect(lastError(), {
+      'variant': 'SyncProtocolError',
+      'message': startsWith('Sync protocol error: invalid binary line'),
+      'code': 4, // SQLITE_ABORT
+      'retryable': false,
+      'sql': null,
+      'sqlite_message': null,
+      'bson_offset': 4,
+      'context': isEmpty,
+    });
+
+    invokeControl('stop', null);
+    expect(lastError(), isNull);
+  });
+
   group('diagnostics', () {
     test('infers schema', () {
       invokeControl('start', json.encode({'diagnostics': {}}));
@@ -57,4 +57,173 @@ void main() {
 
     expect(collectUpdates(), isEmpty);
   });
+
+  group('row tracking', () {
+    setUp(() {
+      db
+        ..executeInTx('select powersync_replace_schema(?)', [
+          json.encode({
+            'tables': [
+              {
+                'name': 'users',
+                'columns': [
+                  {'name': 'name', 'type': 'TEXT'}
+                ],
+              }
+            ]
+          })
+        ])
+        ..select("SELECT powersync_update_hooks('track_rows', 100)");
+    });
+
+    Map<String, Object?> collectRows() {
+      final [row] = db.select("SELECT powersync_update_hooks('get_rows')");
+      return json.decode(row.values[0] as String);
+    }
+
+    void control(String op, Object? payload) {
+      db.executeInTx('SELECT powersync_control(?, ?)', [op, payload]);
+    }
+
+    test('reports local writes', () {
+      db.execute("INSERT INTO users (id, name) VALUES ('a', 'name')");
+      db.execute("UPDATE users SET name = 'changed' WHERE id = 'a'");
+      db.execute("DELETE FROM users WHERE id = 'a'");
+      db.execute('INSERT INTO foo (bar) VALUES (1)');
+
+      final changes = collectRows();
+      expect(changes['tables'], contains('ps_data__users'));
+      expect(changes['truncated'], isFalse);
+      expect(changes['rows'], [
+        {
+          'table': 'ps_data__users',
+          'rowid': 1,
+          'id': 'a',
+          'op': 'insert',
+          'source': 'local'
+        },
+        {
+          'table': 'ps_data__users',
+          'rowid': 1,
+          'id': 'a',
+          'op': 'update',
+          'source': 'local'
+        },
+        {
+          'table': 'ps_data__users',
+          'rowid': 1,
+          'id': 'a',
+          'op': 'delete',
+          'source': 'local'
+        },
+        {'table': 'foo', 'rowid': 1, 'op': 'insert', 'source': 'local'},
+      ]);
+
+      expect(collectRows()['rows'], isEmpty);
+    });
+
+    test('reports remote writes', () {
+      control('start', null);
+      control('seed_checkpoint_request_id', 1);
+      control(
+          'line_text',
+          json.encode(checkpoint(lastOpId: 1, buckets: [
+            bucketDescription('a', count: 1),
+          ])));
+      control(
+          'line_text',
+          json.encode({
+            'data': {
+              'bucket': 'a',
+              'data': [
+                {
+                  'op_id': '1',
+                  'op': 'PUT',
+                  'object_type': 'users',
+                  'object_id': 'u',
+                  'checksum': 0,
+                  'data': json.encode({'name': 'remote'}),
+                }
+              ],
+            },
+          }));
+      control('line_text', json.encode(checkpointComplete()));
+
+      expect(collectRows()['rows'], [
+        {
+          'table': 'ps_data__users',
+          'rowid': 1,
+          'id': 'u',
+          'op': 'insert',
+          'source': 'remote'
+        },
+      ]);
+    });
+
+    test('ignores ids of dropped tables', () {
+      db.execute(
+          "INSERT INTO ps_data__users (id, data) VALUES ('a', '{}'), ('b', '{}')");
+      db.executeInTx('select powersync_replace_schema(?)', [
+        json.encode({'tables': []})
+      ]);
+
+      final rows = collectRows()['rows'] as List;
+      expect(
+        rows.where((row) => row['table'] == 'ps_data__users'),
+        [
+          {'table': 'ps_data__users', 'rowid': 1, 'op': 'insert', 'source': 'local'},
+          {'table': 'ps_data__users', 'rowid': 2, 'op': 'insert', 'source': 'local'},
+        ],
+      );
+    });
+
+    test('reports ids of local-only tables', () {
+      db.executeInTx('select powersync_replace_schema(?)', [
+        json.encode({
+          'tables': [
+            {
+              'name': 'drafts',
+              'local_only': true,
+              'columns': [
+                {'name': 'name', 'type': 'TEXT'}
+              ],
+            }
+          ]
+        })
+      ]);
+      collectRows();
+
+      db.execute("INSERT INTO drafts (id, name) VALUES ('a', 'name')");
+      db.execute("UPDATE drafts SET name = 'changed' WHERE id = 'a'");
+      db.execute("DELETE FROM drafts WHERE id = 'a'");
+      // Re-uses the rowid of the deleted row.
+      db.execute("INSERT INTO drafts (id, name) VALUES ('b', 'name')");
+
+      final rows = collectRows()['rows'] as List;
+      expect(rows.map((row) => (row['rowid'], row['id'], row['op'])), [
+        (1, 'a', 'insert'),
+        (1, 'a', 'update'),
+        (1, 'a', 'delete'),
+        (1, 'b', 'insert'),
+      ]);
+    });
+
+    test('does not report rollbacks', () {
+      db.execute('BEGIN');
+      db.execute("INSERT INTO users (id, name) VALUES ('a', 'name')");
+      db.execute('ROLLBACK');
+
+      expect(collectRows()['rows'], isEmpty);
+    });
+
+    test('truncates rows', () {
+      db.select("SELECT powersync_update_hooks('track_rows', 2)");
+      db.execute('INSERT INTO foo (bar) VALUES (1), (2), (3)');
+
+      final changes = collectRows();
+      expect(changes['rows'], hasLength(2));
+      expect(changes['truncated'], isTrue);
+      expect(changes['tables'], contains('foo'));
+    });
+  });
 }
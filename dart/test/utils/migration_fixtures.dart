@@ -1,5 +1,5 @@
 /// The current database version
-const databaseVersion = 14;
+const databaseVersion = 17;
 
 /// This is the base database state that we expect at various schema versions.
 /// Generated by loading the specific library version, and exporting the schema.
@@ -544,9 +544,31 @@ Map<int, String> _expectedState() {
   state[14] =
       '''${state[13]!.trim().replaceFirst('  target_op INTEGER NOT NULL DEFAULT 0,\n', '')}
 ;INSERT INTO ps_migration(id, down_migrations) VALUES(14, '[{"sql":"ALTER TABLE ps_buckets RENAME TO ps_buckets_14"},{"sql":"DROP INDEX ps_buckets_name"},{"sql":"CREATE TABLE ps_buckets(\\n  id INTEGER PRIMARY KEY,\\n  name TEXT NOT NULL,\\n  last_applied_op INTEGER NOT NULL DEFAULT 0,\\n  last_op INTEGER NOT NULL DEFAULT 0,\\n  target_op INTEGER NOT NULL DEFAULT 0,\\n  add_checksum INTEGER NOT NULL DEFAULT 0,\\n  op_checksum INTEGER NOT NULL DEFAULT 0,\\n  pending_delete INTEGER NOT NULL DEFAULT 0\\n) STRICT"},{"sql":"CREATE UNIQUE INDEX ps_buckets_name ON ps_buckets (name)"},{"sql":"ALTER TABLE ps_buckets ADD COLUMN count_at_last INTEGER NOT NULL DEFAULT 0"},{"sql":"ALTER TABLE ps_buckets ADD COLUMN count_since_last INTEGER NOT NULL DEFAULT 0"},{"sql":"ALTER TABLE ps_buckets ADD COLUMN downloaded_size INTEGER NOT NULL DEFAULT 0"},{"sql":"INSERT INTO ps_buckets(\\n  id,\\n  name,\\n  last_applied_op,\\n  last_op,\\n  add_checksum,\\n  op_checksum,\\n  pending_delete,\\n  count_at_last,\\n  count_since_last,\\n  downloaded_size\\n)\\nSELECT\\n  id,\\n  name,\\n  last_applied_op,\\n  last_op,\\n  add_checksum,\\n  op_checksum,\\n  pending_delete,\\n  count_at_last,\\n  count_since_last,\\n  downloaded_size\\nFROM ps_buckets_14"},{"sql":"DROP TABLE ps_buckets_14"},{"sql":"INSERT INTO ps_buckets(name, pending_delete, last_op, last_applied_op, target_op)\\nSELECT ''\$local'', 1, seen, applied, target\\n  FROM (\\n    SELECT\\n      IFNULL((SELECT CAST(value AS INTEGER) FROM ps_kv WHERE key = ''last_seen_checkpoint_request_id''), 0) AS seen,\\n      IFNULL((SELECT CAST(value AS INTEGER) FROM ps_kv WHERE key = ''last_applied_checkpoint_request_id''), 0) AS applied,\\n      (SELECT CAST(value AS INTEGER) FROM ps_kv WHERE key = ''target_checkpoint_request_id'') AS target\\n  )\\n WHERE EXISTS (\\n    SELECT 1 FROM ps_kv WHERE key = ''target_checkpoint_request_id''\\n )\\nON CONFLICT(name) DO UPDATE SET\\n  pending_delete = excluded.pending_delete,\\n  last_op = excluded.last_op,\\n  last_applied_op = excluded.last_applied_op,\\n  target_op = excluded.target_op"},{"sql":"DELETE FROM ps_migration WHERE id >= 14"}]')''';
+  state[15] = '''${state[14]!.trim().replaceFirst(';CREATE TABLE ps_sync_state (', _syncHistoryTable)}
+;INSERT INTO ps_migration(id, down_migrations) VALUES(15, '[{"sql":"DROP TABLE ps_sync_history"},{"sql":"DELETE FROM ps_migration WHERE id >= 15"}]')
+''';
+  state[16] = '''${state[15]!.trim().replaceFirst(', downloaded_size INTEGER NOT NULL DEFAULT 0) STRICT', ', downloaded_size INTEGER NOT NULL DEFAULT 0, priority INTEGER) STRICT')}
+;INSERT INTO ps_migration(id, down_migrations) VALUES(16, '[{"sql":"ALTER TABLE ps_buckets DROP COLUMN priority"},{"sql":"DELETE FROM ps_migration WHERE id >= 16"}]')
+''';
+  state[17] = '''${state[16]!.trim().replaceFirst('  row_id TEXT,\n  PRIMARY KEY(row_type, row_id)) STRICT, WITHOUT ROWID', '  row_id TEXT, local_write INTEGER NOT NULL DEFAULT 0,\n  PRIMARY KEY(row_type, row_id)) STRICT, WITHOUT ROWID')}
+;INSERT INTO ps_migration(id, down_migrations) VALUES(17, '[{"sql":"ALTER TABLE ps_updated_rows DROP COLUMN local_write"},{"sql":"DELETE FROM ps_migration WHERE id >= 17"}]')
+''';
   return state;
 }
 
+const _syncHistoryTable = '''
+;CREATE TABLE ps_sync_history (
+  id INTEGER NOT NULL PRIMARY KEY,
+  started_at INTEGER NOT NULL,
+  ended_at INTEGER,
+  close_reason TEXT,
+  error TEXT,
+  downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+  downloaded_operations INTEGER NOT NULL DEFAULT 0,
+  completed_checkpoints INTEGER NOT NULL DEFAULT 0
+) STRICT
+;CREATE TABLE ps_sync_state (''';
+
 final finalState = expectedState[databaseVersion]!;
 
 /// data to test "up" migrations
@@ -695,6 +717,23 @@ Map<int, String> _data1() {
 ;INSERT INTO ps_updated_rows(row_type, row_id) VALUES
   ('lists', 'l2')
 ''';
+  data[15] = data[14]!;
+  data[16] = r'''
+;INSERT INTO ps_buckets(id, name, last_applied_op, last_op, add_checksum, op_checksum, pending_delete, count_at_last, count_since_last, downloaded_size, priority) VALUES
+  (1, 'b1', 0, 0, 0, 120, 0, 0, 0, 0, null),
+  (2, 'b2', 0, 0, 1005, 3, 0, 0, 0, 0, null)
+;INSERT INTO ps_oplog(bucket, op_id, row_type, row_id, key, data, hash) VALUES
+  (1, 1, 'todos', 't1', '', '{}', 100),
+  (1, 2, 'todos', 't2', '', '{}', 20),
+  (2, 3, 'lists', 'l1', '', '{}', 3)
+;INSERT INTO ps_updated_rows(row_type, row_id) VALUES
+  ('lists', 'l2')
+''';
+  data[17] = data[16]!.replaceFirst(
+      ''';INSERT INTO ps_updated_rows(row_type, row_id) VALUES
+  ('lists', 'l2')''',
+      ''';INSERT INTO ps_updated_rows(row_type, row_id, local_write) VALUES
+  ('lists', 'l2', 0)''');
   return data;
 }
 
@@ -744,6 +783,9 @@ final dataDown1 = <int, String>{
   11: data1[10]!,
   12: data1[12]!,
   13: data1[13]!,
+  14: data1[14]!,
+  15: data1[15]!,
+  16: data1[16]!,
 };
 
 final finalData1 = data1[databaseVersion]!;